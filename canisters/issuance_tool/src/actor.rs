@@ -1,5 +1,11 @@
 use std::{cell::RefCell};
+use crate::ledger_canister::*;
+use crate::locks::{IssuanceGuard, LockKey};
+use crate::http::handle_http_request;
 use crate::management_canister::*;
+use crate::payment::{convert_payment, refund_payment};
+use crate::stable::{IcMemory, RecordLog};
+use crate::token_args::*;
 use crate::types::*;
 use candid::encode_args;
 use ic_cdk::api::time;
@@ -7,6 +13,7 @@ use ic_cdk::export::candid::Principal;
 use ic_cdk::{api, storage};
 
 use candid::candid_method;
+use sha2::{Digest, Sha256};
 use ic_cdk_macros::*;
 use std::string::String;
 use crate::tool::*;
@@ -14,6 +21,15 @@ use crate::tool::*;
 thread_local! {
     static ISSUANCE_TOOL: RefCell<IssuanceTool> = RefCell::new(IssuanceTool::new());
 }
+
+// append to the audit log, for the async calls not holding the tool
+fn record_event(caller: Principal, event: ToolEvent) {
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        tool.record_event(caller, event, time())
+    });
+}
+
 #[query(name = "owner")]
 #[candid_method(query, rename = "owner")]
 fn owner() -> Principal {
@@ -23,16 +39,185 @@ fn owner() -> Principal {
     })
 }
 
-#[update(name = "setOwner")]
-#[candid_method(update, rename = "setOwner")]
-fn set_owner(owner: Principal) -> ActorResult<bool> {
+// the owner is set by the init args, or is the principal installing the tool
+#[init]
+#[candid_method(init)]
+fn init(owner: Option<Principal>) {
+    let owner = owner.unwrap_or_else(api::caller);
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        tool.attach_store(RecordLog::init(Box::new(IcMemory)));
+        if let Err(e) = tool.set_owner(&owner, owner) {
+            ic_cdk::trap(&format!("Failed to set the owner: {}", e));
+        }
+        tool.record_event(api::caller(), ToolEvent::OwnerSet { owner }, time());
+        // certify the empty registry, so absence proofs verify before the first issuance
+        api::set_certified_data(&tool.certified_tokens_root());
+    });
+}
+
+// propose a new owner, the ownership changes when the new owner accepts it
+#[update(name = "proposeOwner")]
+#[candid_method(update, rename = "proposeOwner")]
+fn propose_owner(new_owner: Principal) -> ActorResult<OwnershipTransfer> {
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        let caller = api::caller();
+        let transfer = tool.propose_owner(&caller, new_owner, time())?;
+        tool.record_event(
+            caller,
+            ToolEvent::OwnershipProposed {
+                new_owner,
+                expires_at: transfer.expires_at,
+            },
+            time(),
+        );
+        Ok(transfer)
+    })
+}
+
+#[update(name = "acceptOwnership")]
+#[candid_method(update, rename = "acceptOwnership")]
+fn accept_ownership() -> ActorResult<bool> {
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        let caller = api::caller();
+        let previous_owner = tool.owner();
+        tool.accept_ownership(&caller, time())?;
+        tool.record_event(
+            caller,
+            ToolEvent::OwnershipAccepted {
+                previous_owner,
+                owner: caller,
+            },
+            time(),
+        );
+        Ok(true)
+    })
+}
+
+#[update(name = "cancelOwnershipTransfer")]
+#[candid_method(update, rename = "cancelOwnershipTransfer")]
+fn cancel_ownership_transfer() -> ActorResult<bool> {
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        let caller = api::caller();
+        tool.cancel_ownership_transfer(&caller)?;
+        tool.record_event(caller, ToolEvent::OwnershipTransferCancelled, time());
+        Ok(true)
+    })
+}
+
+#[query(name = "pendingOwner")]
+#[candid_method(query, rename = "pendingOwner")]
+fn pending_owner() -> Option<OwnershipTransfer> {
+    ISSUANCE_TOOL.with(|tool| {
+        let tool = tool.borrow();
+        tool.get_pending_owner(time())
+    })
+}
+
+#[update(name = "grantRole")]
+#[candid_method(update, rename = "grantRole")]
+fn grant_role(role: Role, principal: Principal) -> ActorResult<bool> {
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        let caller = api::caller();
+        tool.grant_role(&caller, role, principal)?;
+        tool.record_event(caller, ToolEvent::RoleGranted { role, principal }, time());
+        Ok(true)
+    })
+}
+
+#[update(name = "revokeRole")]
+#[candid_method(update, rename = "revokeRole")]
+fn revoke_role(role: Role, principal: Principal) -> ActorResult<bool> {
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        let caller = api::caller();
+        tool.revoke_role(&caller, role, principal)?;
+        tool.record_event(caller, ToolEvent::RoleRevoked { role, principal }, time());
+        Ok(true)
+    })
+}
+
+// holders of the role, or of every role if none
+#[query(name = "roleHolders")]
+#[candid_method(query, rename = "roleHolders")]
+fn role_holders(role: Option<Role>) -> Vec<RoleHolders> {
+    ISSUANCE_TOOL.with(|tool| {
+        let tool = tool.borrow();
+        tool.get_role_holders(role)
+    })
+}
+
+// enable the M-of-N approval of the privileged operations, once enabled it is changed by a proposal
+#[update(name = "setMultisig")]
+#[candid_method(update, rename = "setMultisig")]
+fn set_multisig(config: Option<MultisigConfig>) -> ActorResult<bool> {
     ISSUANCE_TOOL.with(|tool| {
         let mut tool = tool.borrow_mut();
-        tool.set_owner(&api::caller(), owner)?;
+        let caller = api::caller();
+        tool.set_multisig(&caller, config.clone())?;
+        tool.record_event(caller, ToolEvent::MultisigSet { config }, time());
         Ok(true)
     })
 }
 
+#[query(name = "multisig")]
+#[candid_method(query, rename = "multisig")]
+fn multisig() -> Option<MultisigConfig> {
+    ISSUANCE_TOOL.with(|tool| {
+        let tool = tool.borrow();
+        tool.get_multisig()
+    })
+}
+
+#[update(name = "propose")]
+#[candid_method(update, rename = "propose")]
+fn propose(action: ProposalAction) -> ActorResult<Proposal> {
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        to_actor_result(tool.propose(&api::caller(), action, time()))
+    })
+}
+
+#[update(name = "approveProposal")]
+#[candid_method(update, rename = "approveProposal")]
+fn approve_proposal(proposal_id: u64) -> ActorResult<Proposal> {
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        to_actor_result(tool.approve_proposal(&api::caller(), proposal_id, time()))
+    })
+}
+
+#[update(name = "cancelProposal")]
+#[candid_method(update, rename = "cancelProposal")]
+fn cancel_proposal(proposal_id: u64) -> ActorResult<bool> {
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        to_actor_result(tool.cancel_proposal(&api::caller(), proposal_id, time()).map(|_| true))
+    })
+}
+
+#[query(name = "proposalOf")]
+#[candid_method(query, rename = "proposalOf")]
+fn proposal_of(proposal_id: u64) -> ActorResult<Proposal> {
+    ISSUANCE_TOOL.with(|tool| {
+        let tool = tool.borrow();
+        to_actor_result(tool.get_proposal(proposal_id, time()))
+    })
+}
+
+#[query(name = "openProposals")]
+#[candid_method(query, rename = "openProposals")]
+fn open_proposals() -> Vec<Proposal> {
+    ISSUANCE_TOOL.with(|tool| {
+        let tool = tool.borrow();
+        tool.get_open_proposals(time())
+    })
+}
+
 #[update(name = "setCyclesPerToken")]
 #[candid_method(update, rename = "setCyclesPerToken")]
 fn set_cycles_per_token(cycles: u64) -> ActorResult<bool> {
@@ -40,6 +225,7 @@ fn set_cycles_per_token(cycles: u64) -> ActorResult<bool> {
         let mut tool = tool.borrow_mut();
         let caller = api::caller();
         tool.set_cycles_per_token(&caller, cycles)?;
+        tool.record_event(caller, ToolEvent::CyclesPerTokenSet { cycles_per_token: cycles }, time());
         Ok(true)
     })
 }
@@ -49,13 +235,91 @@ fn set_cycles_per_token(cycles: u64) -> ActorResult<bool> {
 fn upload_token_wasm(args: StoreWASMArgs) -> ActorResult<bool> {
     ISSUANCE_TOOL.with(|tool| {
         let mut tool = tool.borrow_mut();
-        let wasm_bytes = args.wasm_module;
         let caller = api::caller();
-        tool.set_token_wasm(&caller, wasm_bytes)?;
+        let info = tool.set_token_wasm(&caller, args, time())?;
+        tool.record_event(caller, wasm_uploaded_event(info), time());
         Ok(true)
     })
 }
 
+// chunked upload for token wasm larger than the ingress message limit:
+// beginTokenWasmUpload -> appendTokenWasmChunk (repeat) -> commitTokenWasmUpload
+// only the caller who began an upload can append to, commit or abort it
+#[update(name = "beginTokenWasmUpload")]
+#[candid_method(update, rename = "beginTokenWasmUpload")]
+fn begin_token_wasm_upload(args: BeginWasmUploadArgs) -> ActorResult<u64> {
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        let caller = api::caller();
+        to_actor_result(tool.begin_wasm_upload(&caller, args, time()))
+    })
+}
+
+#[update(name = "appendTokenWasmChunk")]
+#[candid_method(update, rename = "appendTokenWasmChunk")]
+fn append_token_wasm_chunk(upload_id: u64, chunk: Vec<u8>) -> ActorResult<u64> {
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        let caller = api::caller();
+        to_actor_result(tool.append_wasm_chunk(&caller, upload_id, chunk))
+    })
+}
+
+#[update(name = "commitTokenWasmUpload")]
+#[candid_method(update, rename = "commitTokenWasmUpload")]
+fn commit_token_wasm_upload(upload_id: u64, expected_hash: Vec<u8>) -> ActorResult<TokenWasmInfo> {
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        let caller = api::caller();
+        let info = tool.commit_wasm_upload(&caller, upload_id, expected_hash, time())?;
+        tool.record_event(caller, wasm_uploaded_event(info.clone()), time());
+        Ok(info)
+    })
+}
+
+#[update(name = "abortTokenWasmUpload")]
+#[candid_method(update, rename = "abortTokenWasmUpload")]
+fn abort_token_wasm_upload(upload_id: u64) -> ActorResult<bool> {
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        let caller = api::caller();
+        tool.abort_wasm_upload(&caller, upload_id)?;
+        Ok(true)
+    })
+}
+
+// enable only when the management canister accepts gzip compressed wasm in install_code
+#[update(name = "setInstallCompressedWasm")]
+#[candid_method(update, rename = "setInstallCompressedWasm")]
+fn set_install_compressed_wasm(enabled: bool) -> ActorResult<bool> {
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        let caller = api::caller();
+        tool.set_install_compressed_wasm(&caller, enabled)?;
+        tool.record_event(caller, setting_event("installCompressedWasm", enabled), time());
+        Ok(true)
+    })
+}
+
+#[query(name = "tokenWasmUploads")]
+#[candid_method(query, rename = "tokenWasmUploads")]
+fn token_wasm_uploads() -> Vec<WasmUploadInfo> {
+    ISSUANCE_TOOL.with(|tool| {
+        let tool = tool.borrow();
+        tool.get_wasm_uploads()
+    })
+}
+
+// list versions of the token wasm registry, optionally of a single template
+#[query(name = "tokenWasms")]
+#[candid_method(query, rename = "tokenWasms")]
+fn token_wasms(template: Option<String>) -> Vec<TokenWasmInfo> {
+    ISSUANCE_TOOL.with(|tool| {
+        let tool = tool.borrow();
+        tool.get_token_wasm_versions(template)
+    })
+}
+
 #[query(name = "tokenOf")]
 #[candid_method(query, rename = "tokenOf")]
 fn token_of(token_id: Principal) -> ActorResult<TokenInfo> {
@@ -74,6 +338,158 @@ fn tokens(start: usize, size: usize) -> ActorResult<Vec<TokenInfo>> {
     })
 }
 
+// tokenOf with the certificate and witness of the token, for clients to verify the token info
+#[query(name = "tokenOfCertified")]
+#[candid_method(query, rename = "tokenOfCertified")]
+fn token_of_certified(token_id: Principal) -> CertifiedToken {
+    ISSUANCE_TOOL.with(|tool| {
+        let tool = tool.borrow();
+        let mut certified = tool.get_certified_token(&token_id);
+        certified.certificate = api::data_certificate().map(serde_bytes::ByteBuf::from);
+        certified
+    })
+}
+
+#[query(name = "tokensCertified")]
+#[candid_method(query, rename = "tokensCertified")]
+fn tokens_certified(start: usize, size: usize) -> ActorResult<CertifiedTokens> {
+    ISSUANCE_TOOL.with(|tool| {
+        let tool = tool.borrow();
+        let mut certified = tool.get_certified_tokens(start, size)?;
+        certified.certificate = api::data_certificate().map(serde_bytes::ByteBuf::from);
+        Ok(certified)
+    })
+}
+
+#[query(name = "tokensOfIssuer")]
+#[candid_method(query, rename = "tokensOfIssuer")]
+fn tokens_of_issuer(issuer: Principal, start: usize, size: usize) -> ActorResult<Vec<TokenInfo>> {
+    ISSUANCE_TOOL.with(|tool| {
+        let tool = tool.borrow();
+        to_actor_result(tool.get_tokens_of_issuer(&issuer, start, size))
+    })
+}
+
+#[query(name = "searchTokens")]
+#[candid_method(query, rename = "searchTokens")]
+fn search_tokens(query: String, start: usize, size: usize) -> ActorResult<Vec<TokenInfo>> {
+    ISSUANCE_TOOL.with(|tool| {
+        let tool = tool.borrow();
+        to_actor_result(tool.search_tokens(&query, start, size))
+    })
+}
+
+#[query(name = "listTokens")]
+#[candid_method(query, rename = "listTokens")]
+fn list_tokens(args: ListTokensArgs) -> ActorResult<TokenPage> {
+    ISSUANCE_TOOL.with(|tool| {
+        let tool = tool.borrow();
+        to_actor_result(tool.list_tokens(args))
+    })
+}
+
+#[update(name = "setUniqueSymbols")]
+#[candid_method(update, rename = "setUniqueSymbols")]
+fn set_unique_symbols(enabled: bool) -> ActorResult<bool> {
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        let caller = api::caller();
+        tool.set_unique_symbols(&caller, enabled)?;
+        tool.record_event(caller, setting_event("uniqueSymbols", enabled), time());
+        Ok(true)
+    })
+}
+
+#[update(name = "blockSymbols")]
+#[candid_method(update, rename = "blockSymbols")]
+fn block_symbols(symbols: Vec<String>) -> ActorResult<bool> {
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        let caller = api::caller();
+        tool.block_symbols(&caller, symbols.clone())?;
+        tool.record_event(caller, ToolEvent::SymbolsBlocked { symbols }, time());
+        Ok(true)
+    })
+}
+
+#[update(name = "unblockSymbols")]
+#[candid_method(update, rename = "unblockSymbols")]
+fn unblock_symbols(symbols: Vec<String>) -> ActorResult<bool> {
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        let caller = api::caller();
+        tool.unblock_symbols(&caller, symbols.clone())?;
+        tool.record_event(caller, ToolEvent::SymbolsUnblocked { symbols }, time());
+        Ok(true)
+    })
+}
+
+#[query(name = "blockedSymbols")]
+#[candid_method(query, rename = "blockedSymbols")]
+fn blocked_symbols() -> Vec<String> {
+    ISSUANCE_TOOL.with(|tool| {
+        let tool = tool.borrow();
+        tool.get_blocked_symbols()
+    })
+}
+
+#[update(name = "setSymbolReservationTtl")]
+#[candid_method(update, rename = "setSymbolReservationTtl")]
+fn set_symbol_reservation_ttl(ttl: u64) -> ActorResult<bool> {
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        let caller = api::caller();
+        tool.set_symbol_reservation_ttl(&caller, ttl)?;
+        tool.record_event(caller, setting_event("symbolReservationTtl", ttl), time());
+        Ok(true)
+    })
+}
+
+#[update(name = "reserveSymbol")]
+#[candid_method(update, rename = "reserveSymbol")]
+fn reserve_symbol(symbol: String) -> ActorResult<SymbolReservation> {
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        to_actor_result(tool.reserve_symbol(&api::caller(), &symbol, time()))
+    })
+}
+
+#[update(name = "releaseSymbol")]
+#[candid_method(update, rename = "releaseSymbol")]
+fn release_symbol(symbol: String) -> ActorResult<bool> {
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        to_actor_result(tool.release_symbol(&api::caller(), &symbol).map(|_| true))
+    })
+}
+
+#[query(name = "symbolReservationOf")]
+#[candid_method(query, rename = "symbolReservationOf")]
+fn symbol_reservation_of(symbol: String) -> Option<SymbolReservation> {
+    ISSUANCE_TOOL.with(|tool| {
+        let tool = tool.borrow();
+        tool.get_symbol_reservation(&symbol, time())
+    })
+}
+
+#[query(name = "tokenLogo")]
+#[candid_method(query, rename = "tokenLogo")]
+fn token_logo(token_id: Principal) -> Option<TokenLogo> {
+    ISSUANCE_TOOL.with(|tool| {
+        let tool = tool.borrow();
+        tool.get_token_logo(&token_id)
+    })
+}
+
+#[query(name = "http_request")]
+#[candid_method(query, rename = "http_request")]
+fn http_request(req: HttpRequest) -> HttpResponse {
+    ISSUANCE_TOOL.with(|tool| {
+        let tool = tool.borrow();
+        handle_http_request(&tool, req, api::canister_balance())
+    })
+}
+
 #[update(name = "issueToken")]
 #[candid_method(update, rename = "issueToken")]
 async fn issue_token(args: IssueTokenArgs) -> ActorResult<IssueResult> {
@@ -81,88 +497,615 @@ async fn issue_token(args: IssueTokenArgs) -> ActorResult<IssueResult> {
     let tool_id = api::id();
     ISSUANCE_TOOL.with(|tool| {
         let tool = tool.borrow();
-        tool.only_owner(&caller)
+        tool.only_issuer(&caller)?;
+        validate_issue_args(&args)?;
+        tool.check_symbol(&caller, &args.symbol, None, time())?;
+        // fail early if the template has no token wasm
+        tool.get_token_wasm(&args.template()).map(|_| ())
     })?;
 
     api::print(format!("issue token caller is {}", caller.to_text()));
 
-    // get token wasm
-    let token_wasm = ISSUANCE_TOOL.with(|tool| {
+    let keys = vec![LockKey::Caller(caller), LockKey::Canister(args.canister_id)];
+    let _guard = IssuanceGuard::new(&ISSUANCE_TOOL, keys, time())?;
+    let status = match get_canister_status(&args.canister_id).await {
+        Ok(status) => status,
+        Err(e) => return Err(ToolError::Unknown { detail: e }.into()),
+    };
+    // check if the caller is the controller of the token
+    if !status.settings.controllers.contains(&caller) {
+        return Err(ToolError::CallerIsNotControllerOfToken.into());
+    }
+    if status.module_hash.is_some() {
+        return Err(ToolError::CanisterAlreadyInstalled.into());
+    }
+    // the token keeps its controllers, except the issuance tool
+    let mut controllers = status.settings.controllers;
+    controllers.retain(|c| c != &tool_id);
+
+    let canister_id = args.canister_id;
+    let issuance = ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        tool.begin_issuance(&caller, Some(canister_id), args.into_create_args(), controllers, None, time())
+    })?;
+    record_event(caller, issuance_started_event(&issuance));
+    let canister_id = run_issuance(caller, issuance.issuance_id).await?;
+    Ok(IssueResult { canister_id })
+}
+
+#[update(name = "createAndIssueToken")]
+#[candid_method(update, rename = "createAndIssueToken")]
+async fn create_and_issue_token(args: CreateTokenArgs) -> ActorResult<IssueResult> {
+    let caller = api::caller();
+    let (is_owner, cycles_per_token) = ISSUANCE_TOOL.with(|tool| {
         let tool = tool.borrow();
-        tool.get_token_wasm()
+        tool.only_issuer(&caller)?;
+        validate_create_args(&args)?;
+        tool.check_symbol(&caller, &args.symbol, None, time())?;
+        Ok::<_, ToolError>((tool.owner() == caller, tool.cycles_per_token()))
     })?;
-    let install_args = encode_args((
-        args.sub_account,
-        args.logo,
-        args.name.to_string(),
-        args.symbol.to_string(),
-        args.decimals.clone(),
-        args.total_supply.clone(),
-        args.fee.clone(),
-        Some(caller.clone()),
-    ))
-        .expect("Failed to encode arguments.");
 
-    match get_canister_status(&args.canister_id).await {
-        Ok(status) => {
-            // check if the caller is the controller of the token
-            if !status.settings.controllers.contains(&caller) {
-                return Err(ToolError::CallerIsNotControllerOfToken.into());
+    let _guard = IssuanceGuard::new(&ISSUANCE_TOOL, vec![LockKey::Caller(caller)], time())?;
+    // public issuers pay for the token canister with the cycles attached to the call
+    if !is_owner {
+        let available = api::call::msg_cycles_available();
+        if available < cycles_per_token {
+            return Err(ToolError::InsufficientCycles {
+                required: cycles_per_token,
+                available,
             }
-            match status.module_hash {
-                Some(_) => {
-                    return Err(ToolError::CanisterAlreadyInstalled.into());
-                }
-                None => match install_canister(&args.canister_id, token_wasm, install_args).await {
-                    Ok(_) => {
-                        let token_info = TokenInfo {
-                            issuer: caller.clone(),
-                            token_id: args.canister_id.clone(),
-                            name: args.name.to_string(),
-                            symbol: args.symbol.to_string(),
-                            decimals: args.decimals,
-                            total_supply: args.total_supply,
-                            fee: args.fee.clone(),
-                            timestamp: time(),
-                        };
-
-                        // add token info to IssuanceTool
-                        ISSUANCE_TOOL.with(|tool| {
-                            let mut tool = tool.borrow_mut();
-                            tool.add_token(&caller, token_info)
-                        })?;
-
-                        // remove issuance tool id from token's controllers
-                        let mut settings: CanisterSettings = status.settings.into();
-                        let mut current_controllers = settings.controllers.unwrap().clone();
-                        current_controllers.retain(|c| c != &tool_id);
-                        settings.controllers = Some(current_controllers);
-
-                        let update_settings_args = UpdateSettingsArgs {
-                            canister_id: args.canister_id.clone(),
-                            settings,
-                        };
-
-                        match update_settings_call(update_settings_args).await {
-                            Ok(_) => {}
-                            Err(e) =>
-                                return Err(ToolError::Unknown { detail: e }.into())
-                        };
-
-                        Ok(IssueResult {
-                            canister_id: args.canister_id.clone(),
-                        })
-                    }
-                    Err(e) => {
-                        Err(ToolError::InstallTokenCodeFailed { reason: e.to_string() }.into())
-                    }
+                .into());
+        }
+        api::call::msg_cycles_accept(cycles_per_token);
+    }
+
+    api::print(format!("create and issue token caller is {}", caller.to_text()));
+
+    let canister_id = create_and_install_token(caller, caller, args, None).await?;
+    Ok(IssueResult { canister_id })
+}
+
+// upgrade an issued token to the current token wasm, the tool must be a controller of the token again,
+// the upgrade is recorded once the token is handed back, if that fails the call is retried without reinstalling
+#[update(name = "upgradeToken")]
+#[candid_method(update, rename = "upgradeToken")]
+async fn upgrade_token(token_id: Principal) -> ActorResult<TokenUpgrade> {
+    let caller = api::caller();
+    let tool_id = api::id();
+    // upgrade to the latest wasm of the template the token was issued with
+    let (recorded_module_hash, token_wasm) = ISSUANCE_TOOL.with(|tool| {
+        let tool = tool.borrow();
+        let token = tool.get_token_by_id(&token_id)?;
+        Ok::<_, ToolError>((token.module_hash, tool.get_install_module(&token.template)?))
+    })?;
+    // the module hash reported by canister_status is the sha256 of the installed module
+    let new_module_hash = Sha256::digest(&token_wasm).to_vec();
+
+    let keys = vec![LockKey::Caller(caller), LockKey::Canister(token_id)];
+    let _guard = IssuanceGuard::new(&ISSUANCE_TOOL, keys, time())?;
+    let status = match get_canister_status(&token_id).await {
+        Ok(status) => status,
+        Err(e) => return Err(ToolError::Unknown { detail: e }.into()),
+    };
+    let install = ISSUANCE_TOOL.with(|tool| {
+        let tool = tool.borrow();
+        tool.only_token_upgrader(&caller, &token_id, &status.settings.controllers)?;
+        tool.check_token_upgrade(&token_id, status.module_hash.as_ref(), &new_module_hash)
+    })?;
+
+    api::print(format!("upgrade token {} caller is {}", token_id.to_text(), caller.to_text()));
+
+    let old_module_hash = if install {
+        let upgrade_args = encode_args(()).expect("Failed to encode arguments.");
+        if let Err(e) = install_canister(&token_id, token_wasm, upgrade_args, InstallMode::Upgrade).await {
+            return Err(ToolError::UpgradeTokenCodeFailed { reason: e }.into());
+        }
+        status.module_hash.clone()
+    } else {
+        // installed by an earlier call that failed to hand the token back
+        recorded_module_hash
+    };
+
+    // remove issuance tool id from token's controllers
+    let mut settings: CanisterSettings = status.settings.into();
+    let mut current_controllers = settings.controllers.unwrap();
+    current_controllers.retain(|c| c != &tool_id);
+    settings.controllers = Some(current_controllers);
+
+    let update_settings_args = UpdateSettingsArgs {
+        canister_id: token_id,
+        settings,
+    };
+    if let Err(e) = update_settings_call(update_settings_args).await {
+        return Err(ToolError::HandOverTokenFailed { reason: e }.into());
+    }
+
+    let upgrade = TokenUpgrade {
+        operator: caller,
+        old_module_hash,
+        new_module_hash,
+        timestamp: time(),
+    };
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        tool.add_token_upgrade(&token_id, upgrade.clone())?;
+        // the module hash is part of the certified token info
+        api::set_certified_data(&tool.certified_tokens_root());
+        tool.record_event(
+            caller,
+            ToolEvent::TokenUpgraded {
+                token_id,
+                old_module_hash: upgrade.old_module_hash.clone(),
+                new_module_hash: upgrade.new_module_hash.clone(),
+            },
+            upgrade.timestamp,
+        );
+        Ok::<_, ToolError>(())
+    })?;
+
+    Ok(upgrade)
+}
+
+#[query(name = "tokenUpgradesOf")]
+#[candid_method(query, rename = "tokenUpgradesOf")]
+fn token_upgrades_of(token_id: Principal) -> ActorResult<Vec<TokenUpgrade>> {
+    ISSUANCE_TOOL.with(|tool| {
+        let tool = tool.borrow();
+        to_actor_result(tool.get_token_upgrades(&token_id))
+    })
+}
+
+#[update(name = "setPublicIssuance")]
+#[candid_method(update, rename = "setPublicIssuance")]
+fn set_public_issuance(enabled: bool) -> ActorResult<bool> {
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        let caller = api::caller();
+        tool.set_public_issuance(&caller, enabled)?;
+        tool.record_event(caller, setting_event("publicIssuance", enabled), time());
+        Ok(true)
+    })
+}
+
+#[update(name = "setIssuerQuota")]
+#[candid_method(update, rename = "setIssuerQuota")]
+fn set_issuer_quota(quota: u64) -> ActorResult<bool> {
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        let caller = api::caller();
+        tool.set_issuer_quota(&caller, quota)?;
+        tool.record_event(caller, setting_event("issuerQuota", quota), time());
+        Ok(true)
+    })
+}
+
+// place an issuance order, the token will be issued once the ICP payment of the order is notified by the ledger
+#[update(name = "placeOrder")]
+#[candid_method(update, rename = "placeOrder")]
+fn place_order(args: CreateTokenArgs) -> ActorResult<IssuanceOrder> {
+    let caller = api::caller();
+    let tool_id = api::id();
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        let order = tool.place_order(&caller, &tool_id, args, time())?;
+        tool.record_event(caller, ToolEvent::OrderPlaced { order_id: order.order_id }, time());
+        Ok(order)
+    })
+}
+
+#[query(name = "orderOf")]
+#[candid_method(query, rename = "orderOf")]
+fn order_of(order_id: u64) -> ActorResult<IssuanceOrder> {
+    ISSUANCE_TOOL.with(|tool| {
+        let tool = tool.borrow();
+        to_actor_result(tool.get_order(order_id))
+    })
+}
+
+#[update(name = "setE8sPerToken")]
+#[candid_method(update, rename = "setE8sPerToken")]
+fn set_e8s_per_token(e8s: u64) -> ActorResult<bool> {
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        let caller = api::caller();
+        tool.set_e8s_per_token(&caller, e8s)?;
+        tool.record_event(caller, ToolEvent::E8sPerTokenSet { e8s_per_token: e8s }, time());
+        Ok(true)
+    })
+}
+
+// the ledger and cycles minting canister can be replaced with mock canisters when testing locally
+#[update(name = "setPaymentCanisters")]
+#[candid_method(update, rename = "setPaymentCanisters")]
+fn set_payment_canisters(ledger: Principal, cmc: Principal) -> ActorResult<bool> {
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        let caller = api::caller();
+        tool.set_payment_canisters(&caller, ledger, cmc)?;
+        tool.record_event(caller, payment_canisters_event(&ledger, &cmc), time());
+        Ok(true)
+    })
+}
+
+// called by the ledger when ICP is sent to the tool with notify
+#[update(name = "transaction_notification")]
+#[candid_method(update, rename = "transaction_notification")]
+async fn transaction_notification(notification: TransactionNotification) -> ActorResult<IssueResult> {
+    let caller = api::caller();
+    let tool_id = api::id();
+    let order = ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        let order = tool.accept_payment(&caller, &tool_id, &notification)?;
+        tool.record_event(
+            caller,
+            ToolEvent::PaymentReceived {
+                order_id: order.order_id,
+                issuer: order.issuer,
+                block_height: notification.block_height,
+            },
+            time(),
+        );
+        Ok::<_, ToolError>(order)
+    })?;
+
+    api::print(format!(
+        "payment of order {} received at block {}",
+        order.order_id, notification.block_height
+    ));
+
+    let _guard = IssuanceGuard::new(&ISSUANCE_TOOL, vec![LockKey::Order(order.order_id)], time())?;
+    let canister_id = process_order(caller, order.order_id).await?;
+    Ok(IssueResult { canister_id })
+}
+
+// resume a paid order from its last completed step, converting its payment and issuing its token
+#[update(name = "retryOrder")]
+#[candid_method(update, rename = "retryOrder")]
+async fn retry_order(order_id: u64) -> ActorResult<IssueResult> {
+    let caller = api::caller();
+    let order = ISSUANCE_TOOL.with(|tool| tool.borrow().only_order_operator(&caller, order_id))?;
+    if !matches!(
+        order.status,
+        OrderStatus::Paid { .. } | OrderStatus::TopUpSent { .. } | OrderStatus::Converted
+    ) {
+        return Err(ToolError::OrderNotRetryable.into());
+    }
+    let keys = vec![LockKey::Caller(caller), LockKey::Order(order_id)];
+    let _guard = IssuanceGuard::new(&ISSUANCE_TOOL, keys, time())?;
+    let canister_id = process_order(caller, order_id).await?;
+    Ok(IssueResult { canister_id })
+}
+
+// send the payment of an order not converted yet back to the payer, returns the block height of the refund
+#[update(name = "refundOrder")]
+#[candid_method(update, rename = "refundOrder")]
+async fn refund_order(order_id: u64) -> ActorResult<u64> {
+    let caller = api::caller();
+    let (order, ledger) = ISSUANCE_TOOL.with(|tool| {
+        let tool = tool.borrow();
+        Ok::<_, ToolError>((tool.only_order_operator(&caller, order_id)?, tool.ledger_canister_id))
+    })?;
+    let keys = vec![LockKey::Caller(caller), LockKey::Order(order_id)];
+    let _guard = IssuanceGuard::new(&ISSUANCE_TOOL, keys, time())?;
+    let block_height = refund_payment(&ISSUANCE_TOOL, &LedgerCanister(ledger), order_id).await?;
+    if let Some(payment) = order.payment {
+        record_event(
+            caller,
+            ToolEvent::OrderRefunded {
+                order_id,
+                payer: payment.payer,
+                block_height,
+            },
+        );
+    }
+    Ok(block_height)
+}
+
+// convert the payment of the order into cycles of the tool, then create the token with them,
+// the error is saved to the order so it can be resumed by retryOrder
+async fn process_order(caller: Principal, order_id: u64) -> CommonResult<Principal> {
+    let result = convert_and_issue(caller, order_id).await;
+    if let Err(e) = &result {
+        ISSUANCE_TOOL.with(|tool| {
+            let mut tool = tool.borrow_mut();
+            tool.record_event(
+                caller,
+                ToolEvent::OrderFailed {
+                    order_id,
+                    reason: e.to_string(),
                 },
+                time(),
+            );
+            tool.set_order_error(order_id, e)
+        })?;
+    }
+    result
+}
+
+async fn convert_and_issue(caller: Principal, order_id: u64) -> CommonResult<Principal> {
+    let tool_id = api::id();
+    let (order, ledger, cmc) = ISSUANCE_TOOL.with(|tool| {
+        let tool = tool.borrow();
+        let order = tool.get_order(order_id)?;
+        // check before the payment is spent, it can still be refunded
+        if let OrderStatus::Paid { .. } = order.status {
+            tool.check_order_issuance(&order, time())?;
+        }
+        Ok::<_, ToolError>((order, tool.ledger_canister_id, tool.cmc_canister_id))
+    })?;
+    convert_payment(&ISSUANCE_TOOL, &LedgerCanister(ledger), order_id, cmc, tool_id).await?;
+    match ISSUANCE_TOOL.with(|tool| tool.borrow().get_order_issuance(order_id)) {
+        Some(issuance_id) => run_issuance(caller, issuance_id).await,
+        None => create_and_install_token(caller, order.issuer, order.args, Some(order_id)).await,
+    }
+}
+
+// create a canister funded with cycles_per_token, install the token wasm and hand it over to the issuer
+async fn create_and_install_token(
+    caller: Principal,
+    issuer: Principal,
+    args: CreateTokenArgs,
+    order_id: Option<u64>,
+) -> CommonResult<Principal> {
+    // the token is handed over to the issuer once installed
+    let issuance = ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        tool.get_token_wasm(&args.template())?;
+        tool.begin_issuance(&issuer, None, args, vec![issuer], order_id, time())
+    })?;
+    record_event(caller, issuance_started_event(&issuance));
+    run_issuance(caller, issuance.issuance_id).await
+}
+
+fn issuance_started_event(issuance: &Issuance) -> ToolEvent {
+    ToolEvent::IssuanceStarted {
+        issuance_id: issuance.issuance_id,
+        issuer: issuance.issuer,
+        canister_id: issuance.canister_id,
+    }
+}
+
+// run the issuance from its current step until it completes or a step fails,
+// the error is saved to the issuance so it can be resumed by retryIssuance
+async fn run_issuance(caller: Principal, issuance_id: u64) -> CommonResult<Principal> {
+    let _guard = IssuanceGuard::new(&ISSUANCE_TOOL, vec![LockKey::Issuance(issuance_id)], time())?;
+    loop {
+        let issuance = ISSUANCE_TOOL.with(|tool| tool.borrow().get_issuance(issuance_id))?;
+        let result = match issuance.step {
+            IssuanceStep::Pending => install_issuance(&issuance).await,
+            IssuanceStep::Installed => ISSUANCE_TOOL.with(|tool| {
+                let mut tool = tool.borrow_mut();
+                tool.register_issuance(issuance_id, time())?;
+                api::set_certified_data(&tool.certified_tokens_root());
+                if let Some(token_id) = issuance.canister_id {
+                    tool.record_event(caller, ToolEvent::TokenIssued { issuance_id, token_id }, time());
+                }
+                Ok(())
+            }),
+            IssuanceStep::Registered => hand_over_issuance(&issuance).await.map(|_| {
+                if let Some(token_id) = issuance.canister_id {
+                    record_event(
+                        caller,
+                        ToolEvent::ControllersHandedOver {
+                            issuance_id,
+                            token_id,
+                            controllers: issuance.controllers.clone(),
+                        },
+                    );
+                }
+            }),
+            IssuanceStep::Completed => {
+                return issuance.canister_id.ok_or(ToolError::IssuanceNotFound);
             }
+        };
+        if let Err(e) = result {
+            ISSUANCE_TOOL.with(|tool| {
+                let mut tool = tool.borrow_mut();
+                tool.record_event(
+                    caller,
+                    ToolEvent::IssuanceFailed {
+                        issuance_id,
+                        error: e.to_string(),
+                    },
+                    time(),
+                );
+                tool.set_issuance_error(issuance_id, &e, time())
+            })?;
+            return Err(e);
         }
-        Err(e) => {
-            return Err(ToolError::Unknown { detail: e }.into());
+    }
+}
+
+// create the token canister if needed, and install the token wasm
+async fn install_issuance(issuance: &Issuance) -> CommonResult<()> {
+    let token_wasm = ISSUANCE_TOOL.with(|tool| tool.borrow().get_install_module(&issuance.args.template()))?;
+    let canister_id = match issuance.canister_id {
+        Some(canister_id) => canister_id,
+        None => {
+            let canister_id = create_token_canister().await?;
+            ISSUANCE_TOOL.with(|tool| {
+                let mut tool = tool.borrow_mut();
+                tool.set_issuance_canister(issuance.issuance_id, canister_id, time())
+            })?;
+            canister_id
+        }
+    };
+
+    // a previous attempt may have installed the token before failing
+    let status = match get_canister_status(&canister_id).await {
+        Ok(status) => status,
+        Err(e) => return Err(ToolError::Unknown { detail: e }),
+    };
+    // the module hash reported by canister_status is the sha256 of the installed module
+    let module_hash = Sha256::digest(&token_wasm).to_vec();
+    match status.module_hash {
+        Some(hash) if hash == module_hash => {}
+        Some(_) => return Err(ToolError::CanisterAlreadyInstalled),
+        None => {
+            let args = issuance.args.clone().into_issue_args(canister_id);
+            let install_args = encode_token_init_args(&args, &issuance.issuer);
+            if let Err(e) = install_canister(&canister_id, token_wasm, install_args, InstallMode::Install).await {
+                return Err(ToolError::InstallTokenCodeFailed { reason: e });
+            }
         }
     }
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        tool.set_issuance_installed(issuance.issuance_id, module_hash, time())
+    })
+}
+
+// create a token canister funded from the tool's own balance, the tool is the only controller
+async fn create_token_canister() -> CommonResult<Principal> {
+    let cycles_per_token = ISSUANCE_TOOL.with(|tool| tool.borrow().cycles_per_token());
+    let available = api::canister_balance();
+    if available < cycles_per_token {
+        return Err(ToolError::InsufficientCycles {
+            required: cycles_per_token,
+            available,
+        });
+    }
+    let create_args = CreateCanisterArgs {
+        cycles: cycles_per_token,
+        settings: CanisterSettings {
+            controller: None,
+            controllers: Some(vec![api::id()]),
+            compute_allocation: None,
+            memory_allocation: None,
+            freezing_threshold: None,
+        },
+    };
+    match create_canister_call(create_args).await {
+        Ok(result) => Ok(result.canister_id),
+        Err(e) => Err(ToolError::CreateCanisterFailed { reason: e }),
+    }
+}
+
+// hand the controllership of the token over to the final controllers, and apply the token settings
+async fn hand_over_issuance(issuance: &Issuance) -> CommonResult<()> {
+    let canister_id = issuance.canister_id.ok_or(ToolError::IssuanceNotFound)?;
+    let settings = issuance.args.settings.clone();
+    let update_settings_args = UpdateSettingsArgs {
+        canister_id,
+        settings: CanisterSettings {
+            controller: None,
+            controllers: Some(issuance.controllers.clone()),
+            compute_allocation: settings.as_ref().and_then(|s| s.compute_allocation.clone()),
+            memory_allocation: settings.as_ref().and_then(|s| s.memory_allocation.clone()),
+            freezing_threshold: settings.and_then(|s| s.freezing_threshold),
+        },
+    };
+    if let Err(e) = update_settings_call(update_settings_args).await {
+        return Err(ToolError::Unknown { detail: e });
+    }
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        tool.set_issuance_step(issuance.issuance_id, IssuanceStep::Completed, time())
+    })
+}
+
+#[update(name = "setBlackholeCanister")]
+#[candid_method(update, rename = "setBlackholeCanister")]
+fn set_blackhole_canister(canister_id: Option<Principal>) -> ActorResult<bool> {
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        let caller = api::caller();
+        tool.set_blackhole_canister(&caller, canister_id)?;
+        tool.record_event(caller, blackhole_canister_event(&canister_id), time());
+        Ok(true)
+    })
+}
+
+// re-read the status of the token and compare it to the controllers and module hash recorded by the tool,
+// an update call since the status is read from the blackhole of the token (or the management canister while
+// the tool controls it), a token handed over to other controllers is not verifiable
+#[update(name = "verifyToken")]
+#[candid_method(update, rename = "verifyToken")]
+async fn verify_token(token_id: Principal) -> ActorResult<TokenVerification> {
+    let status_canister = ISSUANCE_TOOL.with(|tool| {
+        let tool = tool.borrow();
+        let token = tool.get_token_by_id(&token_id)?;
+        tool.get_status_canister(&token, &api::id())
+    })?;
+    let status = match get_token_canister_status(&status_canister, &token_id).await {
+        Ok(status) => status,
+        Err(e) => return Err(ToolError::Unknown { detail: e }.into()),
+    };
+    ISSUANCE_TOOL.with(|tool| {
+        let tool = tool.borrow();
+        to_actor_result(tool.verify_token(&token_id, status))
+    })
+}
+
+// resume a failed or interrupted issuance from its last completed step
+#[update(name = "retryIssuance")]
+#[candid_method(update, rename = "retryIssuance")]
+async fn retry_issuance(issuance_id: u64) -> ActorResult<IssueResult> {
+    let caller = api::caller();
+    let issuance = ISSUANCE_TOOL.with(|tool| {
+        let tool = tool.borrow();
+        tool.only_issuance_operator(&caller, issuance_id)
+    })?;
+    let mut keys = vec![LockKey::Caller(caller)];
+    keys.extend(issuance.canister_id.map(LockKey::Canister));
+    let _guard = IssuanceGuard::new(&ISSUANCE_TOOL, keys, time())?;
+    let canister_id = run_issuance(caller, issuance_id).await?;
+    Ok(IssueResult { canister_id })
+}
+
+#[query(name = "issuanceOf")]
+#[candid_method(query, rename = "issuanceOf")]
+fn issuance_of(issuance_id: u64) -> ActorResult<Issuance> {
+    ISSUANCE_TOOL.with(|tool| {
+        let tool = tool.borrow();
+        to_actor_result(tool.get_issuance(issuance_id))
+    })
+}
+
+// issuances not completed yet, owner only
+#[query(name = "stuckIssuances")]
+#[candid_method(query, rename = "stuckIssuances")]
+fn stuck_issuances() -> ActorResult<Vec<Issuance>> {
+    ISSUANCE_TOOL.with(|tool| {
+        let tool = tool.borrow();
+        to_actor_result(tool.get_stuck_issuances(&api::caller()))
+    })
+}
+
+// encode the init args of the DFT token canister
+fn encode_token_init_args(args: &IssueTokenArgs, issuer: &Principal) -> Vec<u8> {
+    encode_args((
+        args.sub_account,
+        args.logo.clone(),
+        args.name.to_string(),
+        args.symbol.to_string(),
+        args.decimals,
+        args.total_supply,
+        args.fee.clone(),
+        Some(*issuer),
+    ))
+        .expect("Failed to encode arguments.")
+}
+
+// the audit log from the event id start, optionally filtered by event type and principal
+#[query(name = "getEvents")]
+#[candid_method(query, rename = "getEvents")]
+fn get_events(start: u64, size: u64, filter: Option<EventFilter>) -> EventPage {
+    ISSUANCE_TOOL.with(|tool| {
+        let tool = tool.borrow();
+        tool.get_events(start, size, filter)
+    })
+}
+
+// rewrite the record log with the live state, dropping the superseded records, returns the size of the log
+#[update(name = "compactStore")]
+#[candid_method(update, rename = "compactStore")]
+fn compact_store() -> ActorResult<u64> {
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        let size = tool.compact_store(&api::caller())?;
+        Ok(size)
+    })
 }
 
 // fn get tool status
@@ -178,41 +1121,56 @@ fn get_status() -> ActorResult<ToolStatus> {
     })
 }
 
+// the state is written to the record log in stable memory as it changes,
+// only the settings are saved on upgrade
 #[pre_upgrade]
 fn pre_upgrade() {
-    // to payload
-    let payload = ISSUANCE_TOOL.with(|tool| {
-        let tool = tool.borrow();
-        tool.to_payload()
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        tool.save_settings();
     });
-
-    match storage::stable_save((payload, )) {
-        Ok(_) => (),
-        Err(candid_err) => {
-            ic_cdk::trap(&format!(
-                "An error occurred when saving to stable memory (pre_upgrade): {:?}",
-                candid_err
-            ));
-        }
-    };
 }
 
 #[post_upgrade]
 fn post_upgrade() {
-    match storage::stable_restore::<(ToolPayload, )>() {
-        Ok((payload, )) => {
-            ISSUANCE_TOOL.with(|tool| {
-                let mut tool = tool.borrow_mut();
-                tool.load_from_payload(payload)
-            });
+    ISSUANCE_TOOL.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        if RecordLog::is_initialized(&IcMemory) {
+            let result = RecordLog::open(Box::new(IcMemory)).and_then(|store| tool.load_from_store(store));
+            if let Err(e) = result {
+                ic_cdk::trap(&format!(
+                    "An error occurred when loading from stable memory (post_upgrade): {}",
+                    e
+                ));
+            }
+        } else {
+            // the ToolPayload saved by the versions before the record log, the first release or a later one, migrated once
+            let result = match storage::stable_restore::<(LegacyToolPayload, )>() {
+                Ok((payload, )) => tool
+                    .load_from_legacy_payload(payload, &api::caller(), time())
+                    .map_err(|e| e.to_string()),
+                Err(_) => storage::stable_restore::<(ToolPayload, )>()
+                    .map(|(payload, )| tool.load_from_payload(payload)),
+            };
+            match result {
+                Ok(()) => {
+                    tool.attach_store(RecordLog::init(Box::new(IcMemory)));
+                    tool.save_all();
+                }
+                Err(candid_err) => {
+                    ic_cdk::trap(&format!(
+                        "An error occurred when loading from stable memory (post_upgrade): {:?}",
+                        candid_err
+                    ));
+                }
+            }
         }
-        Err(candid_err) => {
-            ic_cdk::trap(&format!(
-                "An error occurred when saving to stable memory (post_upgrade): {:?}",
-                candid_err
-            ));
+        // the tool was upgraded from the first release before its owner was set, setOwner is gone since
+        if let Err(e) = tool.set_owner_if_unset(&api::caller(), time()) {
+            ic_cdk::trap(&format!("Failed to set the owner: {}", e));
         }
-    }
+        api::set_certified_data(&tool.certified_tokens_root());
+    });
 }
 candid::export_service!();
 
@@ -0,0 +1,182 @@
+use candid::Nat;
+use ic_certified_map::{fork, labeled, labeled_hash, AsHashTree, Hash, HashTree, RbTree};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::tool::TokenInfo;
+
+// label of the token registry in the certified data
+pub const TOKENS_LABEL: &[u8] = b"tokens";
+
+// token id bytes -> representation-independent hash of the TokenInfo
+pub type CertifiedTokenTree = RbTree<Vec<u8>, Vec<u8>>;
+
+// a value hashed the way the IC hashes request ids, independent of how the token info is encoded
+enum HashValue<'a> {
+    Nat(Nat),
+    Text(&'a str),
+    Blob(&'a [u8]),
+    Array(Vec<HashValue<'a>>),
+    Map(Vec<(&'static str, HashValue<'a>)>),
+}
+
+// the certified leaf of a token, the TokenInfo as a map of its candid field names, see actor.did:
+// nat fields and booleans (0 or 1) hash the sha256 of their leb128 encoding, text the sha256 of the utf-8 bytes,
+// principals and blobs the sha256 of the bytes, vectors the sha256 of the concatenated hashes of the elements,
+// and records the sha256 of the sorted concatenations of sha256(field name) and the field hash,
+// null options are left out
+pub fn token_hash(token: &TokenInfo) -> Vec<u8> {
+    let mut fields = vec![
+        ("issuer", HashValue::Blob(token.issuer.as_slice())),
+        ("tokenId", HashValue::Blob(token.token_id.as_slice())),
+        ("name", HashValue::Text(&token.name)),
+        ("symbol", HashValue::Text(&token.symbol)),
+        ("decimals", HashValue::Nat(Nat::from(token.decimals))),
+        ("totalSupply", HashValue::Nat(Nat::from(token.total_supply))),
+        (
+            "fee",
+            HashValue::Map(vec![
+                ("minimum", HashValue::Nat(token.fee.minimum.clone())),
+                ("rate", HashValue::Nat(token.fee.rate.clone())),
+            ]),
+        ),
+        ("template", HashValue::Text(&token.template)),
+        (
+            "controllers",
+            HashValue::Array(token.controllers.iter().map(|c| HashValue::Blob(c.as_slice())).collect()),
+        ),
+        ("immutable", HashValue::Nat(Nat::from(token.immutable as u8))),
+        ("timestamp", HashValue::Nat(Nat::from(token.timestamp))),
+    ];
+    if let Some(blackhole) = &token.blackhole_canister_id {
+        fields.push(("blackholeCanisterId", HashValue::Blob(blackhole.as_slice())));
+    }
+    if let Some(module_hash) = &token.module_hash {
+        fields.push(("moduleHash", HashValue::Blob(module_hash)));
+    }
+    hash_value(&HashValue::Map(fields)).to_vec()
+}
+
+fn hash_value(value: &HashValue) -> Hash {
+    let mut hasher = Sha256::new();
+    match value {
+        HashValue::Nat(n) => {
+            let mut bytes = vec![];
+            n.encode(&mut bytes).expect("Failed to encode nat.");
+            hasher.update(&bytes);
+        }
+        HashValue::Text(text) => hasher.update(text.as_bytes()),
+        HashValue::Blob(bytes) => hasher.update(bytes),
+        HashValue::Array(values) => {
+            for value in values {
+                hasher.update(hash_value(value));
+            }
+        }
+        HashValue::Map(fields) => {
+            let mut pairs: Vec<Vec<u8>> = fields
+                .iter()
+                .map(|(name, value)| {
+                    let mut pair = Sha256::digest(name.as_bytes()).to_vec();
+                    pair.extend_from_slice(&hash_value(value));
+                    pair
+                })
+                .collect();
+            pairs.sort();
+            for pair in pairs {
+                hasher.update(&pair);
+            }
+        }
+    }
+    hasher.finalize().into()
+}
+
+// the root hash to set as certified data
+pub fn certified_root(tree: &CertifiedTokenTree) -> Hash {
+    labeled_hash(TOKENS_LABEL, &tree.root_hash())
+}
+
+// witness of the keys, proving the presence or absence of each key, serialized as self-describing cbor,
+// no keys reveal nothing but the root hash
+pub fn tokens_witness(tree: &CertifiedTokenTree, keys: &[Vec<u8>]) -> Vec<u8> {
+    let witness = keys
+        .iter()
+        .map(|key| tree.witness(key))
+        .reduce(merge_witness)
+        .unwrap_or_else(|| HashTree::Pruned(tree.root_hash()));
+    let witness = labeled(TOKENS_LABEL, witness);
+    let mut serializer = serde_cbor::ser::Serializer::new(vec![]);
+    serializer.self_describe().expect("Failed to serialize witness.");
+    witness
+        .serialize(&mut serializer)
+        .expect("Failed to serialize witness.");
+    serializer.into_inner()
+}
+
+// merge two witnesses of the same tree, keeping the revealed parts of both
+fn merge_witness<'a>(a: HashTree<'a>, b: HashTree<'a>) -> HashTree<'a> {
+    match (a, b) {
+        (HashTree::Pruned(_), b) => b,
+        (a, HashTree::Pruned(_)) => a,
+        (HashTree::Fork(a), HashTree::Fork(b)) => {
+            let (al, ar) = *a;
+            let (bl, br) = *b;
+            fork(merge_witness(al, bl), merge_witness(ar, br))
+        }
+        (HashTree::Labeled(label, a), HashTree::Labeled(_, b)) => labeled(label, merge_witness(*a, *b)),
+        (a, _) => a,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_value() {
+        // the request id example of the IC interface specification
+        let canister_id = [0, 0, 0, 0, 0, 0, 0x04, 0xd2];
+        let value = HashValue::Map(vec![
+            ("request_type", HashValue::Text("call")),
+            ("sender", HashValue::Blob(&[0x04])),
+            ("ingress_expiry", HashValue::Nat(Nat::from(1_685_570_400_000_000_000u64))),
+            ("canister_id", HashValue::Blob(&canister_id)),
+            ("method_name", HashValue::Text("hello")),
+            ("arg", HashValue::Blob(b"DIDL\x00\xfd*")),
+        ]);
+        assert_eq!(
+            hash_value(&value),
+            [
+                0x1d, 0x10, 0x91, 0x36, 0x4d, 0x6b, 0xb8, 0xa6,
+                0xc1, 0x6b, 0x20, 0x3e, 0xe7, 0x54, 0x67, 0xd5,
+                0x9e, 0xad, 0x46, 0x8f, 0x52, 0x3e, 0xb0, 0x58,
+                0x88, 0x0a, 0xe8, 0xec, 0x80, 0xe2, 0xb1, 0x01,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_witness() {
+        let mut tree = CertifiedTokenTree::new();
+        for i in 0..10u8 {
+            tree.insert(vec![i], vec![i; 32]);
+        }
+        let root = tree.root_hash();
+        let merged = merge_witness(tree.witness(&[2]), tree.witness(&[7]));
+        assert_eq!(merged.reconstruct(), root);
+        let merged = merge_witness(merged, tree.witness(&[42]));
+        assert_eq!(merged.reconstruct(), root);
+
+        assert!(!tokens_witness(&tree, &[vec![1], vec![3]]).is_empty());
+        // an empty page reveals nothing but the root hash
+        use serde_cbor::Value;
+        let witness: Value = serde_cbor::from_slice(&tokens_witness(&tree, &[])).unwrap();
+        assert_eq!(
+            witness,
+            Value::Array(vec![
+                Value::Integer(2),
+                Value::Bytes(TOKENS_LABEL.to_vec()),
+                Value::Array(vec![Value::Integer(4), Value::Bytes(root.to_vec())]),
+            ])
+        );
+    }
+}
@@ -0,0 +1,403 @@
+use crate::tool::{IssuanceTool, TokenInfo, ToolStatus};
+use crate::types::*;
+use candid::Principal;
+use serde_bytes::ByteBuf;
+
+// default page size of the token lists, the tool caps it at 200
+const DEFAULT_PAGE_SIZE: usize = 50;
+
+// serve the registry over the http gateway:
+//  /                   html page listing the tokens
+//  /tokens             json list of tokens, ?start=&size=
+//  /tokens/{id}        json of a token
+//  /issuers/{id}       json list of the tokens of an issuer, ?start=&size=
+//  /status             json status of the tool
+//  /logo/{id}          logo image of a token
+// the responses are not certified, so they are only served on the raw domain of the canister,
+// the gateway of the certified domain would reject them, its requests are redirected to the raw domain
+// cycles is the current balance of the canister, reported by /status
+pub fn handle_http_request(tool: &IssuanceTool, req: HttpRequest, cycles: u64) -> HttpResponse {
+    if req.method != "GET" && req.method != "HEAD" {
+        return HttpResponse::new(405, "text/plain", b"Method not allowed".to_vec());
+    }
+    let mut res = match raw_location(&req) {
+        Some(location) => {
+            let mut res = HttpResponse::new(308, "text/plain", vec![]);
+            res.headers.push(("Location".to_string(), location));
+            res
+        }
+        None => route_request(tool, &req.url, cycles),
+    };
+    // HEAD gets the headers of GET without the body
+    if req.method == "HEAD" {
+        res.body = ByteBuf::new();
+    }
+    res
+}
+
+// the same url on the raw domain, for a request through the certified domain, the request is not redirected
+// if it was sent to the raw domain or without a host
+fn raw_location(req: &HttpRequest) -> Option<String> {
+    let host = req
+        .headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("host"))
+        .map(|(_, value)| value.to_ascii_lowercase())?;
+    if host.contains(".raw.") {
+        return None;
+    }
+    let (canister, domain) = host.split_once('.')?;
+    let scheme = if domain.starts_with("localhost") { "http" } else { "https" };
+    Some(format!("{}://{}.raw.{}{}", scheme, canister, domain, req.url))
+}
+
+fn route_request(tool: &IssuanceTool, url: &str, cycles: u64) -> HttpResponse {
+    let mut parts = url.splitn(2, '?');
+    let path = parts.next().unwrap_or_default();
+    let query = parts.next().unwrap_or_default();
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    match segments.as_slice() {
+        [] | ["index.html"] => index_response(tool),
+        ["tokens"] => {
+            let (start, size) = page_params(query);
+            let tokens = tool.get_tokens(start, size).unwrap_or_default();
+            let total = tool.token_ids.len();
+            json_response(200, token_list_json(tool, &tokens, total))
+        }
+        ["tokens", token_id] => match parse_principal(token_id) {
+            Some(token_id) => match tool.get_token_by_id(&token_id) {
+                Ok(token) => json_response(200, token_json(tool, &token)),
+                Err(e) => error_response(404, &e.to_string()),
+            },
+            None => error_response(400, "Invalid token id"),
+        },
+        ["issuers", issuer] => match parse_principal(issuer) {
+            Some(issuer) => {
+                let (start, size) = page_params(query);
+                let tokens = tool
+                    .get_tokens_of_issuer(&issuer, start, size)
+                    .unwrap_or_default();
+                let total = tool.issuer_tokens.get(&issuer).map_or(0, |ids| ids.len());
+                json_response(200, token_list_json(tool, &tokens, total))
+            }
+            None => error_response(400, "Invalid issuer"),
+        },
+        ["status"] => {
+            let mut status = tool.get_status();
+            status.cycles = cycles;
+            json_response(200, status_json(&status))
+        }
+        ["logo", token_id] => logo_response(tool, token_id),
+        _ => error_response(404, "Not found"),
+    }
+}
+
+fn logo_response(tool: &IssuanceTool, token_id: &str) -> HttpResponse {
+    let logo = parse_principal(token_id).and_then(|token_id| tool.get_token_logo(&token_id));
+    match logo {
+        Some(logo) => {
+            let mut res = HttpResponse::new(200, &logo.content_type, logo.data.into_vec());
+            // logos can't be changed after issuance
+            res.headers
+                .push(("Cache-Control".to_string(), "public, max-age=604800".to_string()));
+            // the logo is issuer content, never let the browser run it in the origin of the registry
+            for (name, value) in [
+                ("Content-Security-Policy", "default-src 'none'; sandbox"),
+                ("X-Content-Type-Options", "nosniff"),
+                ("Content-Disposition", "attachment"),
+            ] {
+                res.headers.push((name.to_string(), value.to_string()));
+            }
+            res
+        }
+        None => HttpResponse::not_found(),
+    }
+}
+
+fn index_response(tool: &IssuanceTool) -> HttpResponse {
+    let mut rows = String::new();
+    for token in tool.get_tokens(0, 200).unwrap_or_default() {
+        rows.push_str(&format!(
+            "<tr><td><a href=\"/tokens/{id}\">{id}</a></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            html_escape(&token.name),
+            html_escape(&token.symbol),
+            token.decimals,
+            token.total_supply,
+            token.issuer,
+            id = token.token_id,
+        ));
+    }
+    let html = format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Token Registry</title></head><body>\
+         <h1>Token Registry</h1><p>{} tokens issued</p>\
+         <table><tr><th>Token</th><th>Name</th><th>Symbol</th><th>Decimals</th><th>Total supply</th><th>Issuer</th></tr>{}</table>\
+         </body></html>",
+        tool.token_ids.len(),
+        rows
+    );
+    HttpResponse::new(200, "text/html; charset=utf-8", html.into_bytes())
+}
+
+fn json_response(status_code: u16, json: String) -> HttpResponse {
+    let mut res = HttpResponse::new(status_code, "application/json", json.into_bytes());
+    res.headers
+        .push(("Access-Control-Allow-Origin".to_string(), "*".to_string()));
+    res
+}
+
+fn error_response(status_code: u16, message: &str) -> HttpResponse {
+    json_response(status_code, format!("{{\"error\":{}}}", json_string(message)))
+}
+
+fn parse_principal(text: &str) -> Option<Principal> {
+    Principal::from_text(text).ok()
+}
+
+// parse start and size from the query string, invalid values fall back to the defaults
+fn page_params(query: &str) -> (usize, usize) {
+    let mut start = 0;
+    let mut size = DEFAULT_PAGE_SIZE;
+    for pair in query.split('&') {
+        let mut kv = pair.splitn(2, '=');
+        let key = kv.next().unwrap_or_default();
+        let value = kv.next().and_then(|v| v.parse::<usize>().ok());
+        match (key, value) {
+            ("start", Some(v)) => start = v,
+            ("size", Some(v)) => size = v,
+            _ => {}
+        }
+    }
+    (start, size)
+}
+
+// u128 and Nat values are written as strings, they don't fit in a javascript number
+fn token_json(tool: &IssuanceTool, token: &TokenInfo) -> String {
+    let logo = if tool.logos.contains_key(&token.token_id) {
+        json_string(&format!("/logo/{}", token.token_id))
+    } else {
+        "null".to_string()
+    };
+    format!(
+        "{{\"tokenId\":{},\"issuer\":{},\"name\":{},\"symbol\":{},\"decimals\":{},\"totalSupply\":{},\
+         \"fee\":{{\"minimum\":{},\"rate\":{}}},\"template\":{},\"immutable\":{},\"timestamp\":{},\"logo\":{}}}",
+        json_string(&token.token_id.to_text()),
+        json_string(&token.issuer.to_text()),
+        json_string(&token.name),
+        json_string(&token.symbol),
+        token.decimals,
+        json_string(&token.total_supply.to_string()),
+        json_string(&token.fee.minimum.0.to_string()),
+        json_string(&token.fee.rate.0.to_string()),
+        json_string(&token.template),
+        token.immutable,
+        token.timestamp,
+        logo
+    )
+}
+
+fn token_list_json(tool: &IssuanceTool, tokens: &[TokenInfo], total: usize) -> String {
+    let tokens: Vec<String> = tokens.iter().map(|t| token_json(tool, t)).collect();
+    format!("{{\"total\":{},\"tokens\":[{}]}}", total, tokens.join(","))
+}
+
+fn status_json(status: &ToolStatus) -> String {
+    format!(
+        "{{\"owner\":{},\"cycles\":{},\"cyclesPerToken\":{},\"e8sPerToken\":{},\"issuedTokenCount\":{},\
+         \"tokenWasmCount\":{},\"publicIssuance\":{},\"issuerQuota\":{}}}",
+        json_string(&status.owner.to_text()),
+        status.cycles,
+        status.cycles_per_token,
+        status.e8s_per_token,
+        status.issued_token_count,
+        status.token_wasm_count,
+        status.public_issuance,
+        status.issuer_quota
+    )
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Nat;
+
+    fn get(url: &str) -> HttpRequest {
+        HttpRequest {
+            method: "GET".to_string(),
+            url: url.to_string(),
+            headers: vec![],
+            body: ByteBuf::new(),
+        }
+    }
+
+    fn body(res: HttpResponse) -> String {
+        String::from_utf8(res.body.into_vec()).unwrap()
+    }
+
+    fn test_tool() -> (IssuanceTool, Principal, Principal) {
+        let mut tool = IssuanceTool::new();
+        let owner =
+            Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe")
+                .unwrap();
+        let token_id = Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap();
+        let result = tool.set_owner(&owner, owner);
+        assert!(result.is_ok());
+        let result = tool.add_token(
+            &owner,
+            TokenInfo {
+                issuer: owner,
+                token_id,
+                name: "<b>\"test\"</b>".to_string(),
+                symbol: "TST".to_string(),
+                decimals: 18,
+                total_supply: 100,
+                fee: Fee {
+                    minimum: Nat::from(1),
+                    rate: Nat::from(10000),
+                },
+                template: DEFAULT_TOKEN_TEMPLATE.to_string(),
+                controllers: vec![],
+                immutable: false,
+                blackhole_canister_id: None,
+                module_hash: None,
+                timestamp: 0,
+            },
+        );
+        assert!(result.is_ok());
+        (tool, owner, token_id)
+    }
+
+    #[test]
+    fn test_logo_request() {
+        let (mut tool, _, token_id) = test_tool();
+
+        // no logo yet
+        let res = handle_http_request(&tool, get(&format!("/logo/{}", token_id)), 0);
+        assert_eq!(res.status_code, 404);
+
+        let logo = b"GIF89a0000".to_vec();
+        let result = tool.set_token_logo(&token_id, logo.clone());
+        assert!(result.is_ok());
+        let res = handle_http_request(&tool, get(&format!("/logo/{}?v=1", token_id)), 0);
+        assert_eq!(res.status_code, 200);
+        assert!(res
+            .headers
+            .contains(&("Content-Type".to_string(), "image/gif".to_string())));
+        for header in [
+            ("Content-Security-Policy", "default-src 'none'; sandbox"),
+            ("X-Content-Type-Options", "nosniff"),
+            ("Content-Disposition", "attachment"),
+        ] {
+            assert!(res
+                .headers
+                .contains(&(header.0.to_string(), header.1.to_string())));
+        }
+        assert_eq!(res.body.into_vec(), logo);
+
+        assert_eq!(handle_http_request(&tool, get("/logo/not-a-principal"), 0).status_code, 404);
+        assert_eq!(handle_http_request(&tool, get("/unknown"), 0).status_code, 404);
+        let mut req = get("/");
+        req.method = "POST".to_string();
+        assert_eq!(handle_http_request(&tool, req, 0).status_code, 405);
+    }
+
+    #[test]
+    fn test_json_api() {
+        let (tool, owner, token_id) = test_tool();
+        let token_json = format!(
+            "{{\"tokenId\":\"{}\",\"issuer\":\"{}\",\"name\":\"<b>\\\"test\\\"</b>\",\"symbol\":\"TST\",\
+             \"decimals\":18,\"totalSupply\":\"100\",\"fee\":{{\"minimum\":\"1\",\"rate\":\"10000\"}},\
+             \"template\":\"dft_basic\",\"immutable\":false,\"timestamp\":0,\"logo\":null}}",
+            token_id, owner
+        );
+
+        let res = handle_http_request(&tool, get("/tokens"), 0);
+        assert_eq!(res.status_code, 200);
+        assert!(res
+            .headers
+            .contains(&("Content-Type".to_string(), "application/json".to_string())));
+        assert_eq!(body(res), format!("{{\"total\":1,\"tokens\":[{}]}}", token_json));
+        let res = handle_http_request(&tool, get("/tokens?start=1&size=10"), 0);
+        assert_eq!(body(res), "{\"total\":1,\"tokens\":[]}");
+
+        let res = handle_http_request(&tool, get(&format!("/tokens/{}", token_id)), 0);
+        assert_eq!(body(res), token_json);
+        let res = handle_http_request(&tool, get("/tokens/ryjl3-tyaaa-aaaaa-aaaba-cai"), 0);
+        assert_eq!(res.status_code, 404);
+        let res = handle_http_request(&tool, get("/tokens/bad"), 0);
+        assert_eq!(res.status_code, 400);
+
+        let res = handle_http_request(&tool, get(&format!("/issuers/{}/", owner)), 0);
+        assert_eq!(body(res), format!("{{\"total\":1,\"tokens\":[{}]}}", token_json));
+        let res = handle_http_request(&tool, get("/issuers/ryjl3-tyaaa-aaaaa-aaaba-cai"), 0);
+        assert_eq!(body(res), "{\"total\":0,\"tokens\":[]}");
+
+        let res = handle_http_request(&tool, get("/status"), 42);
+        let status = body(res);
+        assert!(status.contains("\"cycles\":42"));
+        assert!(status.contains("\"issuedTokenCount\":1"));
+
+        // the html page escapes token metadata
+        let res = handle_http_request(&tool, get("/"), 0);
+        let html = body(res);
+        assert!(html.contains("&lt;b&gt;&quot;test&quot;&lt;/b&gt;"));
+        assert!(!html.contains("<b>"));
+    }
+
+    #[test]
+    fn test_raw_domain_and_head() {
+        let (tool, _, token_id) = test_tool();
+        let with_host = |method: &str, host: &str| HttpRequest {
+            method: method.to_string(),
+            url: format!("/tokens/{}?x=1", token_id),
+            headers: vec![("Host".to_string(), host.to_string())],
+            body: ByteBuf::new(),
+        };
+
+        // requests through the certified domain are redirected to the raw domain
+        let res = handle_http_request(&tool, with_host("GET", "rkp4c-7iaaa-aaaaa-aaaca-cai.ic0.app"), 0);
+        assert_eq!(res.status_code, 308);
+        let location = format!("https://rkp4c-7iaaa-aaaaa-aaaca-cai.raw.ic0.app/tokens/{}?x=1", token_id);
+        assert!(res.headers.contains(&("Location".to_string(), location)));
+        let res = handle_http_request(&tool, with_host("GET", "rkp4c-7iaaa-aaaaa-aaaca-cai.localhost:8000"), 0);
+        let location = format!("http://rkp4c-7iaaa-aaaaa-aaaca-cai.raw.localhost:8000/tokens/{}?x=1", token_id);
+        assert!(res.headers.contains(&("Location".to_string(), location)));
+
+        // served on the raw domain
+        let res = handle_http_request(&tool, with_host("GET", "rkp4c-7iaaa-aaaaa-aaaca-cai.raw.ic0.app"), 0);
+        assert_eq!(res.status_code, 200);
+        assert!(!res.body.is_empty());
+
+        // HEAD has the headers of GET without the body
+        let get = handle_http_request(&tool, with_host("GET", "rkp4c-7iaaa-aaaaa-aaaca-cai.raw.ic0.app"), 0);
+        let head = handle_http_request(&tool, with_host("HEAD", "rkp4c-7iaaa-aaaaa-aaaca-cai.raw.ic0.app"), 0);
+        assert_eq!(head.status_code, 200);
+        assert_eq!(head.headers, get.headers);
+        assert!(head.body.is_empty());
+    }
+}
@@ -0,0 +1,55 @@
+use crate::types::*;
+use ic_cdk::api;
+use ic_cdk::export::candid::Principal;
+use std::future::Future;
+use std::pin::Pin;
+
+pub async fn send_dfx(ledger: &Principal, args: SendArgs) -> Result<BlockHeight, String> {
+    let (block_height, ): (BlockHeight, ) = match api::call::call(*ledger, "send_dfx", (args, )).await
+    {
+        Ok(x) => x,
+        Err((code, msg)) => {
+            return Err(format!(
+                "An error happened during the call: {}: {}",
+                code as u8, msg
+            ));
+        }
+    };
+    Ok(block_height)
+}
+
+pub async fn notify_dfx(ledger: &Principal, args: NotifyCanisterArgs) -> Result<CyclesResponse, String> {
+    let (response, ): (CyclesResponse, ) = match api::call::call(*ledger, "notify_dfx", (args, )).await {
+        Ok(x) => x,
+        Err((code, msg)) => {
+            return Err(format!(
+                "An error happened during the call: {}: {}",
+                code as u8, msg
+            ));
+        }
+    };
+    Ok(response)
+}
+
+// a call in flight to the ledger
+pub type LedgerCall<'a, T> = Pin<Box<dyn Future<Output = Result<T, String>> + 'a>>;
+
+// the calls of the payment flow to the ledger, and through it to the cycles minting canister,
+// so the flow can be tested without them
+pub trait Ledger {
+    fn send_dfx(&self, args: SendArgs) -> LedgerCall<'_, BlockHeight>;
+    fn notify_dfx(&self, args: NotifyCanisterArgs) -> LedgerCall<'_, CyclesResponse>;
+}
+
+// the ledger canister with the id
+pub struct LedgerCanister(pub Principal);
+
+impl Ledger for LedgerCanister {
+    fn send_dfx(&self, args: SendArgs) -> LedgerCall<'_, BlockHeight> {
+        Box::pin(send_dfx(&self.0, args))
+    }
+
+    fn notify_dfx(&self, args: NotifyCanisterArgs) -> LedgerCall<'_, CyclesResponse> {
+        Box::pin(notify_dfx(&self.0, args))
+    }
+}
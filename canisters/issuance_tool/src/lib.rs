@@ -1,4 +1,12 @@
+mod certification;
+mod ledger_canister;
+mod locks;
 mod management_canister;
+mod payment;
+mod http;
+mod stable;
 pub mod actor;
 mod types;
+mod token_args;
+mod token_wasm;
 mod tool;
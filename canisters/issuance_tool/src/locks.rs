@@ -0,0 +1,111 @@
+use crate::payment::ToolState;
+use crate::types::*;
+use candid::Principal;
+use std::collections::HashMap;
+
+// a lock is considered abandoned after 10 minutes, the message holding it may have trapped after an await
+pub const LOCK_TIMEOUT: u64 = 10 * 60 * 1_000_000_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LockKey {
+    Caller(Principal),
+    Canister(Principal),
+    Issuance(u64),
+    Order(u64),
+}
+
+// locks held by the calls in flight, so calls on the same canister (or by the same caller) can't interleave
+#[derive(Default)]
+pub struct InFlightLocks {
+    // key -> time the lock was acquired
+    held: HashMap<LockKey, u64>,
+}
+
+impl InFlightLocks {
+    pub fn new() -> Self {
+        InFlightLocks::default()
+    }
+
+    // acquire all the keys or none of them
+    pub fn acquire(&mut self, keys: &[LockKey], now: u64) -> CommonResult<()> {
+        if keys.iter().any(|key| self.is_locked(key, now)) {
+            return Err(ToolError::IssuanceInProgress);
+        }
+        for key in keys {
+            self.held.insert(*key, now);
+        }
+        Ok(())
+    }
+
+    pub fn release(&mut self, keys: &[LockKey]) {
+        for key in keys {
+            self.held.remove(key);
+        }
+    }
+
+    pub fn is_locked(&self, key: &LockKey, now: u64) -> bool {
+        self.held
+            .get(key)
+            .map_or(false, |acquired_at| now < acquired_at.saturating_add(LOCK_TIMEOUT))
+    }
+}
+
+// holds in-flight locks while a call awaits, the locks are released when the guard is dropped,
+// so every exit path of the call releases them
+pub struct IssuanceGuard {
+    tool: &'static ToolState,
+    keys: Vec<LockKey>,
+}
+
+impl IssuanceGuard {
+    pub fn new(tool: &'static ToolState, keys: Vec<LockKey>, now: u64) -> CommonResult<Self> {
+        tool.with(|tool| {
+            let mut tool = tool.borrow_mut();
+            tool.locks.acquire(&keys, now)
+        })?;
+        Ok(IssuanceGuard { tool, keys })
+    }
+}
+
+impl Drop for IssuanceGuard {
+    fn drop(&mut self) {
+        self.tool.with(|tool| {
+            let mut tool = tool.borrow_mut();
+            tool.locks.release(&self.keys)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_flight_locks() {
+        let caller1 = Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe").unwrap();
+        let caller2 = Principal::from_text("czjfo-ddpvm-6sibl-6zbox-ee5zq-bx3hc-e336t-s6pka-dupmy-wcxqi-fae").unwrap();
+        let canister = Principal::from_text("g7cye-cyaaa-aaaak-aaa5a-cai").unwrap();
+        let mut locks = InFlightLocks::new();
+
+        // two callers issuing to the same canister, the second call fails while the first one awaits
+        let first = [LockKey::Caller(caller1), LockKey::Canister(canister)];
+        let second = [LockKey::Caller(caller2), LockKey::Canister(canister)];
+        assert!(locks.acquire(&first, 0).is_ok());
+        assert_eq!(locks.acquire(&second, 1).unwrap_err(), ToolError::IssuanceInProgress);
+        // nothing of the failed call is held
+        assert!(!locks.is_locked(&LockKey::Caller(caller2), 1));
+        // the same caller can't start another issuance
+        assert_eq!(
+            locks.acquire(&[LockKey::Caller(caller1)], 1).unwrap_err(),
+            ToolError::IssuanceInProgress
+        );
+        assert!(locks.acquire(&[LockKey::Issuance(1)], 1).is_ok());
+
+        // released when the first call returns
+        locks.release(&first);
+        assert!(locks.acquire(&second, 2).is_ok());
+
+        // abandoned locks expire
+        assert!(locks.acquire(&second, 2 + LOCK_TIMEOUT).is_ok());
+    }
+}
@@ -3,34 +3,34 @@ use ic_cdk::api;
 use ic_cdk::export::candid::{CandidType, Principal};
 use serde::Deserialize;
 
-// pub async fn create_canister_call(args: CreateCanisterArgs) -> Result<CreateResult, String> {
-//     #[derive(CandidType)]
-//     struct In {
-//         settings: Option<CanisterSettings>,
-//     }
-//     let in_arg = In {
-//         settings: Some(args.settings),
-//     };
+pub async fn create_canister_call(args: CreateCanisterArgs) -> Result<CreateResult, String> {
+    #[derive(CandidType)]
+    struct In {
+        settings: Option<CanisterSettings>,
+    }
+    let in_arg = In {
+        settings: Some(args.settings),
+    };
 
-//     let (create_result,): (CreateResult,) = match api::call::call_with_payment(
-//         Principal::management_canister(),
-//         "create_canister",
-//         (in_arg,),
-//         args.cycles,
-//     )
-//     .await
-//     {
-//         Ok(x) => x,
-//         Err((code, msg)) => {
-//             return Err(format!(
-//                 "An error happened during the call: {}: {}",
-//                 code as u8, msg
-//             ))
-//         }
-//     };
+    let (create_result, ): (CreateResult, ) = match api::call::call_with_payment(
+        Principal::management_canister(),
+        "create_canister",
+        (in_arg, ),
+        args.cycles,
+    )
+        .await
+    {
+        Ok(x) => x,
+        Err((code, msg)) => {
+            return Err(format!(
+                "An error happened during the call: {}: {}",
+                code as u8, msg
+            ));
+        }
+    };
 
-//     Ok(create_result)
-// }
+    Ok(create_result)
+}
 
 pub async fn get_canister_status(
     canister_id: &Principal,
@@ -39,7 +39,32 @@ pub async fn get_canister_status(
         Principal::management_canister(),
         "canister_status",
         (StatusRequest {
-            canister_id: canister_id.clone(),
+            canister_id: *canister_id,
+        }, ),
+    )
+        .await
+    {
+        Ok(x) => x,
+        Err((code, msg)) => {
+            return Err(format!(
+                "An error happened during the call: {}: {}",
+                code as u8, msg
+            ));
+        }
+    };
+    Ok(status)
+}
+
+// read the status of a token from the management canister, or from a blackhole canister controlling it
+pub async fn get_token_canister_status(
+    status_canister: &Principal,
+    canister_id: &Principal,
+) -> Result<TokenCanisterStatus, String> {
+    let (status, ): (TokenCanisterStatus, ) = match api::call::call(
+        *status_canister,
+        "canister_status",
+        (StatusRequest {
+            canister_id: *canister_id,
         }, ),
     )
         .await
@@ -55,22 +80,23 @@ pub async fn get_canister_status(
     Ok(status)
 }
 
+#[derive(CandidType, Deserialize)]
+pub enum InstallMode {
+    #[serde(rename = "install")]
+    Install,
+    #[serde(rename = "reinstall")]
+    Reinstall,
+    #[serde(rename = "upgrade")]
+    Upgrade,
+}
+
 pub async fn install_canister(
     canister_id: &Principal,
     wasm_module: Vec<u8>,
     args: Vec<u8>,
+    mode: InstallMode,
 ) -> Result<(), String> {
     // Install Wasm
-    #[derive(CandidType, Deserialize)]
-    enum InstallMode {
-        #[serde(rename = "install")]
-        Install,
-        #[serde(rename = "reinstall")]
-        Reinstall,
-        #[serde(rename = "upgrade")]
-        Upgrade,
-    }
-
     #[derive(CandidType, Deserialize)]
     struct CanisterInstall {
         mode: InstallMode,
@@ -82,13 +108,13 @@ pub async fn install_canister(
     }
 
     let install_config = CanisterInstall {
-        mode: InstallMode::Install,
-        canister_id: canister_id.clone(),
-        wasm_module: wasm_module.clone(),
+        mode,
+        canister_id: *canister_id,
+        wasm_module,
         arg: args,
     };
 
-    match api::call::call(
+    match api::call::call::<_, ()>(
         Principal::management_canister(),
         "install_code",
         (install_config, ),
@@ -109,7 +135,7 @@ pub async fn install_canister(
 pub async fn update_settings_call(
     args: UpdateSettingsArgs
 ) -> Result<(), String> {
-    match api::call::call(Principal::management_canister(), "update_settings", (args, )).await {
+    match api::call::call::<_, ()>(Principal::management_canister(), "update_settings", (args, )).await {
         Ok(x) => x,
         Err((code, msg)) => {
             return Err(format!(
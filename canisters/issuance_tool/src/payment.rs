@@ -0,0 +1,512 @@
+use std::cell::RefCell;
+use std::thread::LocalKey;
+
+use candid::Principal;
+
+use crate::ledger_canister::Ledger;
+use crate::tool::IssuanceTool;
+use crate::types::*;
+
+pub type ToolState = LocalKey<RefCell<IssuanceTool>>;
+
+// convert the ICP held for the paid order into cycles of the tool, the order status is saved after each
+// call, so a failed call is retried from where it stopped, the error is saved to the order
+pub async fn convert_payment(
+    tool: &'static ToolState,
+    ledger: &impl Ledger,
+    order_id: u64,
+    cmc: Principal,
+    tool_id: Principal,
+) -> CommonResult<()> {
+    let result = convert_payment_steps(tool, ledger, order_id, cmc, tool_id).await;
+    if let Err(e) = &result {
+        tool.with(|tool| tool.borrow_mut().set_order_error(order_id, e))?;
+    }
+    result
+}
+
+async fn convert_payment_steps(
+    tool: &'static ToolState,
+    ledger: &impl Ledger,
+    order_id: u64,
+    cmc: Principal,
+    tool_id: Principal,
+) -> CommonResult<()> {
+    // the cycles minting canister tops up the canister encoded in the sub-account
+    let tool_subaccount = Subaccount::from(&tool_id);
+    loop {
+        let order = tool.with(|tool| tool.borrow().get_order(order_id))?;
+        let payment = order.payment.clone().ok_or(ToolError::OrderNotRetryable)?;
+        match order.status {
+            OrderStatus::Paid { .. } => {
+                // the amount paid over the price goes back to the payer first, only the price is converted
+                let excess = payment.amount.e8s.saturating_sub(order.price.e8s);
+                if excess > TRANSACTION_FEE.e8s && payment.excess_refund.is_none() {
+                    let send_args = SendArgs {
+                        memo: Memo(order_id),
+                        amount: ICPTs::from_e8s(excess - TRANSACTION_FEE.e8s),
+                        fee: TRANSACTION_FEE,
+                        from_subaccount: payment.subaccount,
+                        to: AccountIdentifier::new(&payment.payer, payment.payer_subaccount).to_hex(),
+                        created_at_time: None,
+                    };
+                    let block_height = ledger
+                        .send_dfx(send_args)
+                        .await
+                        .map_err(|e| ToolError::RefundFailed { reason: e })?;
+                    tool.with(|tool| tool.borrow_mut().set_order_excess_refund(order_id, block_height))?;
+                    continue;
+                }
+                let amount = order.held_amount();
+                if amount <= TRANSACTION_FEE {
+                    return Err(ToolError::InsufficientPayment {
+                        required: order.price.e8s,
+                        received: payment.amount.e8s,
+                    });
+                }
+                let send_args = SendArgs {
+                    memo: MEMO_TOP_UP_CANISTER,
+                    amount: ICPTs::from_e8s(amount.e8s - TRANSACTION_FEE.e8s),
+                    fee: TRANSACTION_FEE,
+                    from_subaccount: payment.subaccount,
+                    to: AccountIdentifier::new(&cmc, Some(tool_subaccount)).to_hex(),
+                    created_at_time: None,
+                };
+                let block_height = ledger
+                    .send_dfx(send_args)
+                    .await
+                    .map_err(|e| ToolError::TopUpCyclesFailed { reason: e })?;
+                tool.with(|tool| {
+                    let mut tool = tool.borrow_mut();
+                    tool.set_order_status(order_id, OrderStatus::TopUpSent { block_height, cmc })
+                })?;
+            }
+            OrderStatus::TopUpSent { block_height, cmc } => {
+                let notify_args = NotifyCanisterArgs {
+                    block_height,
+                    max_fee: TRANSACTION_FEE,
+                    from_subaccount: payment.subaccount,
+                    to_canister: cmc,
+                    to_subaccount: Some(tool_subaccount),
+                };
+                let response = ledger
+                    .notify_dfx(notify_args)
+                    .await
+                    .map_err(|e| ToolError::TopUpCyclesFailed { reason: e })?;
+                if let CyclesResponse::Refunded(reason, _) = response {
+                    // the top up is back in the order's account, less the fees of the top up and the refund
+                    let amount = ICPTs::from_e8s(order.held_amount().e8s.saturating_sub(2 * TRANSACTION_FEE.e8s));
+                    tool.with(|tool| {
+                        let mut tool = tool.borrow_mut();
+                        tool.set_order_status(order_id, OrderStatus::Refundable { amount })
+                    })?;
+                    return Err(ToolError::TopUpCyclesFailed {
+                        reason: format!("refunded by the cycles minting canister: {}", reason),
+                    });
+                }
+                tool.with(|tool| tool.borrow_mut().set_order_status(order_id, OrderStatus::Converted))?;
+            }
+            OrderStatus::Converted => return Ok(()),
+            _ => return Err(ToolError::OrderNotRetryable),
+        }
+    }
+}
+
+// send the payment held for the order back to the payer, less the transaction fee, returns the block height
+pub async fn refund_payment(tool: &'static ToolState, ledger: &impl Ledger, order_id: u64) -> CommonResult<u64> {
+    let order = tool.with(|tool| tool.borrow().get_order(order_id))?;
+    let amount = match order.status {
+        OrderStatus::Paid { .. } => order.held_amount(),
+        OrderStatus::Refundable { amount } => amount,
+        _ => return Err(ToolError::OrderNotRefundable),
+    };
+    let payment = match order.payment {
+        Some(payment) if amount > TRANSACTION_FEE => payment,
+        _ => return Err(ToolError::OrderNotRefundable),
+    };
+    let send_args = SendArgs {
+        memo: Memo(order_id),
+        amount: ICPTs::from_e8s(amount.e8s - TRANSACTION_FEE.e8s),
+        fee: TRANSACTION_FEE,
+        from_subaccount: payment.subaccount,
+        to: AccountIdentifier::new(&payment.payer, payment.payer_subaccount).to_hex(),
+        created_at_time: None,
+    };
+    let block_height = ledger
+        .send_dfx(send_args)
+        .await
+        .map_err(|e| ToolError::RefundFailed { reason: e })?;
+    tool.with(|tool| {
+        let mut tool = tool.borrow_mut();
+        tool.set_order_status(order_id, OrderStatus::Refunded { block_height })
+    })?;
+    Ok(block_height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger_canister::LedgerCall;
+    use crate::locks::{IssuanceGuard, LockKey};
+    use crate::types::{Fee, TransactionNotification};
+    use candid::Nat;
+    use std::cell::Cell;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+    thread_local! {
+        static TOOL: RefCell<IssuanceTool> = RefCell::new(IssuanceTool::new());
+    }
+
+    // ledger answering from a script of results, recording the calls
+    #[derive(Default)]
+    struct MockLedger {
+        send_results: RefCell<Vec<Result<BlockHeight, String>>>,
+        notify_results: RefCell<Vec<Result<CyclesResponse, String>>>,
+        sent: RefCell<Vec<SendArgs>>,
+        notified: RefCell<Vec<NotifyCanisterArgs>>,
+        // the sends wait for the reply while set
+        suspended: Cell<bool>,
+    }
+
+    // pending while the flag is set, like a call awaiting the reply of another canister
+    struct Suspend<'a>(&'a Cell<bool>);
+
+    impl Future for Suspend<'_> {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<()> {
+            if self.0.get() {
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        }
+    }
+
+    impl Ledger for MockLedger {
+        fn send_dfx(&self, args: SendArgs) -> LedgerCall<'_, BlockHeight> {
+            self.sent.borrow_mut().push(args);
+            Box::pin(async move {
+                Suspend(&self.suspended).await;
+                self.send_results.borrow_mut().remove(0)
+            })
+        }
+
+        fn notify_dfx(&self, args: NotifyCanisterArgs) -> LedgerCall<'_, CyclesResponse> {
+            self.notified.borrow_mut().push(args);
+            Box::pin(async move { self.notify_results.borrow_mut().remove(0) })
+        }
+    }
+
+    // a waker doing nothing, the flows are polled again explicitly
+    fn noop_waker() -> Waker {
+        fn clone(_: *const ()) -> RawWaker {
+            RawWaker::new(std::ptr::null(), &VTABLE)
+        }
+        fn noop(_: *const ()) {}
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+        unsafe { Waker::from_raw(RawWaker::new(std::ptr::null(), &VTABLE)) }
+    }
+
+    // the mock ledger never suspends, so the flow completes in a single poll
+    fn block_on<T>(future: impl Future<Output = T>) -> T {
+        let mut future = Box::pin(future);
+        match future.as_mut().poll(&mut Context::from_waker(&noop_waker())) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("the mock ledger suspended"),
+        }
+    }
+
+    // a call holding the locks of the keys across its await, as the actor's update calls do
+    async fn locked<T>(keys: Vec<LockKey>, now: u64, call: impl Future<Output = CommonResult<T>>) -> CommonResult<T> {
+        let _guard = IssuanceGuard::new(&TOOL, keys, now)?;
+        call.await
+    }
+
+    fn order_status(order_id: u64) -> (OrderStatus, Option<String>) {
+        TOOL.with(|tool| {
+            let order = tool.borrow().get_order(order_id).unwrap();
+            (order.status, order.error)
+        })
+    }
+
+    // place an order and pay it, with the excess over the price
+    fn paid_order(payer: Principal, tool_id: Principal, ledger: Principal, excess: u64) -> u64 {
+        TOOL.with(|tool| {
+            let mut tool = tool.borrow_mut();
+            let owner = payer;
+            tool.set_owner(&owner, owner).unwrap();
+            tool.set_payment_canisters(&owner, ledger, ledger).unwrap();
+            let args = CreateTokenArgs {
+                sub_account: None,
+                logo: None,
+                name: "test".to_string(),
+                symbol: "TST".to_string(),
+                decimals: 18,
+                total_supply: 100,
+                fee: Fee {
+                    minimum: Nat::from(1),
+                    rate: Nat::from(10000),
+                },
+                template: None,
+                settings: None,
+            };
+            let order = tool.place_order(&owner, &tool_id, args, 0).unwrap();
+            let notification = TransactionNotification {
+                from: payer,
+                from_subaccount: None,
+                to: tool_id,
+                to_subaccount: Some(order.subaccount),
+                block_height: 10,
+                amount: ICPTs::from_e8s(order.price.e8s + excess),
+                memo: Memo(0),
+            };
+            tool.accept_payment(&ledger, &tool_id, &notification).unwrap();
+            order.order_id
+        })
+    }
+
+    #[test]
+    fn test_convert_payment() {
+        let payer =
+            Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe")
+                .unwrap();
+        let tool_id = Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap();
+        let ledger_id = Principal::from_text("ryjl3-tyaaa-aaaaa-aaaba-cai").unwrap();
+        let cmc = Principal::from_text("rkp4c-7iaaa-aaaaa-aaaca-cai").unwrap();
+        let order_id = paid_order(payer, tool_id, ledger_id, 0);
+        let price = TOOL.with(|tool| tool.borrow().get_order(order_id).unwrap().price);
+
+        // the send fails, the payment stays in the order's account
+        let ledger = MockLedger::default();
+        ledger.send_results.borrow_mut().push(Err("rejected".to_string()));
+        let result = block_on(convert_payment(&TOOL, &ledger, order_id, cmc, tool_id));
+        assert_eq!(
+            result.unwrap_err(),
+            ToolError::TopUpCyclesFailed {
+                reason: "rejected".to_string(),
+            }
+        );
+        let (status, error) = order_status(order_id);
+        assert_eq!(status, OrderStatus::Paid { block_height: 10 });
+        assert!(error.is_some());
+        let sent = ledger.sent.borrow()[0].clone();
+        assert_eq!(sent.amount, ICPTs::from_e8s(price.e8s - TRANSACTION_FEE.e8s));
+        assert_eq!(sent.from_subaccount, Some(Subaccount::from(order_id)));
+        assert_eq!(sent.to, AccountIdentifier::new(&cmc, Some(Subaccount::from(&tool_id))).to_hex());
+
+        // the send succeeds, the notify fails, the retry only notifies
+        ledger.send_results.borrow_mut().push(Ok(20));
+        ledger.notify_results.borrow_mut().push(Err("rejected".to_string()));
+        let result = block_on(convert_payment(&TOOL, &ledger, order_id, cmc, tool_id));
+        assert!(result.is_err());
+        assert_eq!(order_status(order_id).0, OrderStatus::TopUpSent { block_height: 20, cmc });
+        ledger.notify_results.borrow_mut().push(Ok(CyclesResponse::ToppedUp));
+        let result = block_on(convert_payment(&TOOL, &ledger, order_id, cmc, tool_id));
+        assert!(result.is_ok());
+        assert_eq!(order_status(order_id), (OrderStatus::Converted, None));
+        assert_eq!(ledger.sent.borrow().len(), 2);
+        assert_eq!(ledger.notified.borrow().len(), 2);
+        assert_eq!(ledger.notified.borrow()[1].block_height, 20);
+        assert_eq!(ledger.notified.borrow()[1].to_canister, cmc);
+
+        // a converted payment can't be refunded, nor converted again
+        let result = block_on(refund_payment(&TOOL, &ledger, order_id));
+        assert_eq!(result.unwrap_err(), ToolError::OrderNotRefundable);
+        let result = block_on(convert_payment(&TOOL, &ledger, order_id, cmc, tool_id));
+        assert!(result.is_ok());
+        assert_eq!(ledger.sent.borrow().len(), 2);
+    }
+
+    #[test]
+    fn test_refund_payment() {
+        let payer =
+            Principal::from_text("czjfo-ddpvm-6sibl-6zbox-ee5zq-bx3hc-e336t-s6pka-dupmy-wcxqi-fae")
+                .unwrap();
+        let tool_id = Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap();
+        let ledger_id = Principal::from_text("ryjl3-tyaaa-aaaaa-aaaba-cai").unwrap();
+        let cmc = Principal::from_text("rkp4c-7iaaa-aaaaa-aaaca-cai").unwrap();
+        let order_id = paid_order(payer, tool_id, ledger_id, 0);
+        let price = TOOL.with(|tool| tool.borrow().get_order(order_id).unwrap().price);
+
+        // a failed refund can be retried
+        let ledger = MockLedger::default();
+        ledger.send_results.borrow_mut().push(Err("rejected".to_string()));
+        let result = block_on(refund_payment(&TOOL, &ledger, order_id));
+        assert_eq!(
+            result.unwrap_err(),
+            ToolError::RefundFailed {
+                reason: "rejected".to_string(),
+            }
+        );
+        assert_eq!(order_status(order_id).0, OrderStatus::Paid { block_height: 10 });
+        ledger.send_results.borrow_mut().push(Ok(30));
+        let result = block_on(refund_payment(&TOOL, &ledger, order_id));
+        assert_eq!(result.unwrap(), 30);
+        assert_eq!(order_status(order_id).0, OrderStatus::Refunded { block_height: 30 });
+        let refund = ledger.sent.borrow()[1].clone();
+        assert_eq!(refund.to, AccountIdentifier::new(&payer, None).to_hex());
+        assert_eq!(refund.from_subaccount, Some(Subaccount::from(order_id)));
+        assert_eq!(refund.amount, ICPTs::from_e8s(price.e8s - TRANSACTION_FEE.e8s));
+
+        // a refunded order can't be refunded again or converted
+        let result = block_on(refund_payment(&TOOL, &ledger, order_id));
+        assert_eq!(result.unwrap_err(), ToolError::OrderNotRefundable);
+        let result = block_on(convert_payment(&TOOL, &ledger, order_id, cmc, tool_id));
+        assert_eq!(result.unwrap_err(), ToolError::OrderNotRetryable);
+        assert_eq!(ledger.sent.borrow().len(), 2);
+    }
+
+    #[test]
+    fn test_convert_overpayment() {
+        let payer =
+            Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe")
+                .unwrap();
+        let tool_id = Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap();
+        let ledger_id = Principal::from_text("ryjl3-tyaaa-aaaaa-aaaba-cai").unwrap();
+        let cmc = Principal::from_text("rkp4c-7iaaa-aaaaa-aaaca-cai").unwrap();
+        let excess = 5 * TRANSACTION_FEE.e8s;
+        let order_id = paid_order(payer, tool_id, ledger_id, excess);
+        let price = TOOL.with(|tool| tool.borrow().get_order(order_id).unwrap().price);
+
+        // the excess is refunded first, a failed refund is retried before anything is converted
+        let ledger = MockLedger::default();
+        ledger.send_results.borrow_mut().push(Err("rejected".to_string()));
+        let result = block_on(convert_payment(&TOOL, &ledger, order_id, cmc, tool_id));
+        assert_eq!(
+            result.unwrap_err(),
+            ToolError::RefundFailed {
+                reason: "rejected".to_string(),
+            }
+        );
+        ledger.send_results.borrow_mut().push(Ok(15));
+        ledger.send_results.borrow_mut().push(Err("rejected".to_string()));
+        let result = block_on(convert_payment(&TOOL, &ledger, order_id, cmc, tool_id));
+        assert!(result.is_err());
+        let order = TOOL.with(|tool| tool.borrow().get_order(order_id).unwrap());
+        assert_eq!(order.held_amount(), price);
+        assert_eq!(order.payment.unwrap().excess_refund, Some(15));
+        let refund = ledger.sent.borrow()[1].clone();
+        assert_eq!(refund.to, AccountIdentifier::new(&payer, None).to_hex());
+        assert_eq!(refund.from_subaccount, Some(Subaccount::from(order_id)));
+        assert_eq!(refund.amount, ICPTs::from_e8s(excess - TRANSACTION_FEE.e8s));
+
+        // only the price is converted, the excess is not refunded twice
+        ledger.send_results.borrow_mut().push(Ok(20));
+        ledger.notify_results.borrow_mut().push(Ok(CyclesResponse::ToppedUp));
+        let result = block_on(convert_payment(&TOOL, &ledger, order_id, cmc, tool_id));
+        assert!(result.is_ok());
+        assert_eq!(order_status(order_id), (OrderStatus::Converted, None));
+        let sent = ledger.sent.borrow();
+        assert_eq!(sent.len(), 4);
+        assert_eq!(sent[3].amount, ICPTs::from_e8s(price.e8s - TRANSACTION_FEE.e8s));
+        assert_eq!(sent[3].to, AccountIdentifier::new(&cmc, Some(Subaccount::from(&tool_id))).to_hex());
+    }
+
+    #[test]
+    fn test_top_up_refunded() {
+        let payer =
+            Principal::from_text("czjfo-ddpvm-6sibl-6zbox-ee5zq-bx3hc-e336t-s6pka-dupmy-wcxqi-fae")
+                .unwrap();
+        let tool_id = Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap();
+        let ledger_id = Principal::from_text("ryjl3-tyaaa-aaaaa-aaaba-cai").unwrap();
+        let cmc = Principal::from_text("rkp4c-7iaaa-aaaaa-aaaca-cai").unwrap();
+        let order_id = paid_order(payer, tool_id, ledger_id, 0);
+        let price = TOOL.with(|tool| tool.borrow().get_order(order_id).unwrap().price);
+
+        // the cycles minting canister refunds the top up, the order holds it less two fees
+        let ledger = MockLedger::default();
+        ledger.send_results.borrow_mut().push(Ok(20));
+        ledger
+            .notify_results
+            .borrow_mut()
+            .push(Ok(CyclesResponse::Refunded("no subnet".to_string(), Some(21))));
+        let result = block_on(convert_payment(&TOOL, &ledger, order_id, cmc, tool_id));
+        assert!(matches!(result.unwrap_err(), ToolError::TopUpCyclesFailed { .. }));
+        let amount = ICPTs::from_e8s(price.e8s - 2 * TRANSACTION_FEE.e8s);
+        let (status, error) = order_status(order_id);
+        assert_eq!(status, OrderStatus::Refundable { amount });
+        assert!(error.is_some());
+
+        // it can't be converted again, only refunded
+        let result = block_on(convert_payment(&TOOL, &ledger, order_id, cmc, tool_id));
+        assert_eq!(result.unwrap_err(), ToolError::OrderNotRetryable);
+        ledger.send_results.borrow_mut().push(Ok(30));
+        let result = block_on(refund_payment(&TOOL, &ledger, order_id));
+        assert_eq!(result.unwrap(), 30);
+        assert_eq!(order_status(order_id).0, OrderStatus::Refunded { block_height: 30 });
+        let refund = ledger.sent.borrow()[1].clone();
+        assert_eq!(refund.to, AccountIdentifier::new(&payer, None).to_hex());
+        assert_eq!(refund.amount, ICPTs::from_e8s(amount.e8s - TRANSACTION_FEE.e8s));
+    }
+
+    #[test]
+    fn test_interleaved_calls() {
+        let payer =
+            Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe")
+                .unwrap();
+        let other =
+            Principal::from_text("czjfo-ddpvm-6sibl-6zbox-ee5zq-bx3hc-e336t-s6pka-dupmy-wcxqi-fae")
+                .unwrap();
+        let token_id = Principal::from_text("g7cye-cyaaa-aaaak-aaa5a-cai").unwrap();
+        let tool_id = Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap();
+        let ledger_id = Principal::from_text("ryjl3-tyaaa-aaaaa-aaaba-cai").unwrap();
+        let cmc = Principal::from_text("rkp4c-7iaaa-aaaaa-aaaca-cai").unwrap();
+        let order_id = paid_order(payer, tool_id, ledger_id, 0);
+        let waker = noop_waker();
+        let mut context = Context::from_waker(&waker);
+
+        // issueToken suspended at its await, a second issuance of the canister by another caller is rejected
+        let status_call = Cell::new(true);
+        let issue = locked(vec![LockKey::Caller(payer), LockKey::Canister(token_id)], 0, async {
+            Suspend(&status_call).await;
+            Ok(())
+        });
+        let mut issue = Box::pin(issue);
+        assert!(issue.as_mut().poll(&mut context).is_pending());
+        let second_issue = locked(vec![LockKey::Caller(other), LockKey::Canister(token_id)], 1, async { Ok(()) });
+        assert_eq!(block_on(second_issue).unwrap_err(), ToolError::IssuanceInProgress);
+
+        // transaction_notification suspended while the ledger sends the top up,
+        // retryOrder of the same order is rejected before it reaches the ledger
+        let ledger = MockLedger::default();
+        ledger.suspended.set(true);
+        ledger.send_results.borrow_mut().push(Ok(20));
+        ledger.notify_results.borrow_mut().push(Ok(CyclesResponse::ToppedUp));
+        let notification = locked(
+            vec![LockKey::Order(order_id)],
+            1,
+            convert_payment(&TOOL, &ledger, order_id, cmc, tool_id),
+        );
+        let mut notification = Box::pin(notification);
+        assert!(notification.as_mut().poll(&mut context).is_pending());
+        let retry = locked(
+            vec![LockKey::Caller(payer), LockKey::Order(order_id)],
+            2,
+            convert_payment(&TOOL, &ledger, order_id, cmc, tool_id),
+        );
+        assert_eq!(block_on(retry).unwrap_err(), ToolError::IssuanceInProgress);
+        assert_eq!(ledger.sent.borrow().len(), 1);
+        assert_eq!(order_status(order_id).0, OrderStatus::Paid { block_height: 10 });
+
+        // the suspended calls complete and release their locks
+        ledger.suspended.set(false);
+        assert!(matches!(notification.as_mut().poll(&mut context), Poll::Ready(Ok(()))));
+        assert_eq!(order_status(order_id).0, OrderStatus::Converted);
+        status_call.set(false);
+        assert!(matches!(issue.as_mut().poll(&mut context), Poll::Ready(Ok(()))));
+        TOOL.with(|tool| {
+            let tool = tool.borrow();
+            assert!(!tool.locks.is_locked(&LockKey::Order(order_id), 3));
+            assert!(!tool.locks.is_locked(&LockKey::Canister(token_id), 3));
+        });
+        let retry = locked(
+            vec![LockKey::Caller(payer), LockKey::Order(order_id)],
+            3,
+            convert_payment(&TOOL, &ledger, order_id, cmc, tool_id),
+        );
+        assert!(block_on(retry).is_ok());
+    }
+}
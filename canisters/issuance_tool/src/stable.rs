@@ -0,0 +1,279 @@
+use ic_cdk::api::stable;
+use std::convert::TryFrom;
+
+const WASM_PAGE_SIZE: u64 = 65536;
+// "DFTL", distinct from the "DIDL" of the candid payload saved by storage::stable_save
+const MAGIC: &[u8; 4] = b"DFTL";
+const LAYOUT_VERSION: u32 = 1;
+// magic, layout version, end of the log, end of the last compaction, the rest is reserved
+const HEADER_SIZE: u64 = 64;
+
+// byte addressed memory the record log is written to
+pub trait Memory {
+    // size in bytes
+    fn size(&self) -> u64;
+    // grow by pages of 64 KiB, false if the memory can't grow
+    fn grow(&mut self, pages: u64) -> bool;
+    fn read(&self, offset: u64, buf: &mut [u8]);
+    fn write(&mut self, offset: u64, buf: &[u8]);
+}
+
+// the stable memory of the canister
+pub struct IcMemory;
+
+impl Memory for IcMemory {
+    fn size(&self) -> u64 {
+        stable::stable_size() as u64 * WASM_PAGE_SIZE
+    }
+
+    fn grow(&mut self, pages: u64) -> bool {
+        match u32::try_from(pages) {
+            Ok(pages) => stable::stable_grow(pages).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    fn read(&self, offset: u64, buf: &mut [u8]) {
+        stable::stable_read(stable_offset(offset, buf.len()), buf)
+    }
+
+    fn write(&mut self, offset: u64, buf: &[u8]) {
+        stable::stable_write(stable_offset(offset, buf.len()), buf)
+    }
+}
+
+// the offset for the 32-bit stable memory api, panics (traps) if the range does not fit in it
+fn stable_offset(offset: u64, len: usize) -> u32 {
+    let end = offset.checked_add(len as u64);
+    match (u32::try_from(offset), end) {
+        (Ok(offset), Some(end)) if end <= u32::MAX as u64 + 1 => offset,
+        _ => panic!("Stable memory range at {} of {} bytes is out of the 32-bit address space", offset, len),
+    }
+}
+
+// heap memory for tests, clones share the bytes so a log can be reopened like after an upgrade
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct VecMemory(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+#[cfg(test)]
+impl Memory for VecMemory {
+    fn size(&self) -> u64 {
+        self.0.borrow().len() as u64
+    }
+
+    fn grow(&mut self, pages: u64) -> bool {
+        let size = self.size() + pages * WASM_PAGE_SIZE;
+        self.0.borrow_mut().resize(size as usize, 0);
+        true
+    }
+
+    fn read(&self, offset: u64, buf: &mut [u8]) {
+        let offset = offset as usize;
+        buf.copy_from_slice(&self.0.borrow()[offset..offset + buf.len()]);
+    }
+
+    fn write(&mut self, offset: u64, buf: &[u8]) {
+        let offset = offset as usize;
+        self.0.borrow_mut()[offset..offset + buf.len()].copy_from_slice(buf);
+    }
+}
+
+// append-only log of length prefixed records, written as the state changes,
+// so nothing has to be serialized on upgrade but a small snapshot of the settings,
+// the owner compacts the log by rewriting the live state, so replaying it is bounded by the state, not its history
+pub struct RecordLog {
+    memory: Box<dyn Memory>,
+    end: u64,
+    compacted_end: u64,
+}
+
+impl RecordLog {
+    // check if the memory holds a record log, not a payload saved by storage::stable_save
+    pub fn is_initialized(memory: &dyn Memory) -> bool {
+        if memory.size() < HEADER_SIZE {
+            return false;
+        }
+        let mut magic = [0u8; 4];
+        memory.read(0, &mut magic);
+        &magic == MAGIC
+    }
+
+    // start an empty log, overwriting the memory
+    pub fn init(memory: Box<dyn Memory>) -> Self {
+        let mut log = RecordLog {
+            memory,
+            end: HEADER_SIZE,
+            compacted_end: HEADER_SIZE,
+        };
+        log.reserve(HEADER_SIZE);
+        log.memory.write(0, MAGIC);
+        log.memory.write(4, &LAYOUT_VERSION.to_le_bytes());
+        log.write_end();
+        log.write_compacted_end();
+        log
+    }
+
+    // open the log written before the upgrade
+    pub fn open(memory: Box<dyn Memory>) -> Result<Self, String> {
+        if !Self::is_initialized(memory.as_ref()) {
+            return Err("stable memory does not hold a record log".to_string());
+        }
+        let mut version = [0u8; 4];
+        memory.read(4, &mut version);
+        let version = u32::from_le_bytes(version);
+        if version != LAYOUT_VERSION {
+            return Err(format!("unsupported record log layout version {}", version));
+        }
+        let mut end = [0u8; 8];
+        memory.read(8, &mut end);
+        let mut compacted_end = [0u8; 8];
+        memory.read(16, &mut compacted_end);
+        Ok(RecordLog {
+            memory,
+            end: u64::from_le_bytes(end),
+            compacted_end: u64::from_le_bytes(compacted_end),
+        })
+    }
+
+    // append the record, panics (traps) if the memory can't grow
+    pub fn append(&mut self, record: &[u8]) {
+        let len = u32::try_from(record.len())
+            .unwrap_or_else(|_| panic!("Record of {} bytes is too large for the record log", record.len()));
+        let offset = self.end;
+        let end = offset
+            .checked_add(4 + len as u64)
+            .expect("Record log end overflows");
+        self.reserve(end);
+        self.memory.write(offset, &len.to_le_bytes());
+        self.memory.write(offset + 4, record);
+        self.end = end;
+        self.write_end();
+    }
+
+    // drop all the records, to rewrite the live state, the memory is reused
+    pub fn clear(&mut self) {
+        self.end = HEADER_SIZE;
+        self.write_end();
+    }
+
+    // mark the records written since clear as the compacted state
+    pub fn set_compacted(&mut self) {
+        self.compacted_end = self.end;
+        self.write_compacted_end();
+    }
+
+    // the records in the order they were appended
+    pub fn records(&self) -> RecordIter<'_> {
+        RecordIter {
+            log: self,
+            offset: HEADER_SIZE,
+        }
+    }
+
+    // bytes used by the log
+    pub fn size(&self) -> u64 {
+        self.end
+    }
+
+    // bytes used by the log after the last compaction
+    pub fn compacted_size(&self) -> u64 {
+        self.compacted_end
+    }
+
+    fn reserve(&mut self, size: u64) {
+        let available = self.memory.size();
+        if size > available {
+            let pages = (size - available + WASM_PAGE_SIZE - 1) / WASM_PAGE_SIZE;
+            if !self.memory.grow(pages) {
+                panic!("Failed to grow stable memory by {} pages", pages);
+            }
+        }
+    }
+
+    fn write_end(&mut self) {
+        self.memory.write(8, &self.end.to_le_bytes());
+    }
+
+    fn write_compacted_end(&mut self) {
+        self.memory.write(16, &self.compacted_end.to_le_bytes());
+    }
+}
+
+pub struct RecordIter<'a> {
+    log: &'a RecordLog,
+    offset: u64,
+}
+
+impl Iterator for RecordIter<'_> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        if self.offset >= self.log.end {
+            return None;
+        }
+        let mut len = [0u8; 4];
+        self.log.memory.read(self.offset, &mut len);
+        let mut record = vec![0u8; u32::from_le_bytes(len) as usize];
+        self.log.memory.read(self.offset + 4, &mut record);
+        self.offset += 4 + record.len() as u64;
+        Some(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_log() {
+        let memory = VecMemory::default();
+        assert!(!RecordLog::is_initialized(&memory));
+        // a payload saved by storage::stable_save is not a record log
+        let mut payload = memory.clone();
+        payload.grow(1);
+        payload.write(0, b"DIDL");
+        assert!(!RecordLog::is_initialized(&payload));
+        assert!(RecordLog::open(Box::new(payload.clone())).is_err());
+
+        let memory = VecMemory::default();
+        let mut log = RecordLog::init(Box::new(memory.clone()));
+        assert!(RecordLog::is_initialized(&memory));
+        assert_eq!(log.records().count(), 0);
+        log.append(b"first");
+        log.append(b"");
+        // a record larger than a page grows the memory
+        let large = vec![7u8; WASM_PAGE_SIZE as usize + 1];
+        log.append(&large);
+        assert_eq!(log.size(), HEADER_SIZE + 4 + 5 + 4 + 4 + large.len() as u64);
+        assert!(memory.size() >= log.size());
+        // offsets of the 32-bit stable memory api are checked
+        assert_eq!(stable_offset(8, 8), 8);
+        assert_eq!(stable_offset(u32::MAX as u64, 1), u32::MAX);
+        assert!(std::panic::catch_unwind(|| stable_offset(u32::MAX as u64, 2)).is_err());
+        assert!(std::panic::catch_unwind(|| stable_offset(1 << 32, 0)).is_err());
+
+        // reopen the log, as after an upgrade
+        let mut log = RecordLog::open(Box::new(memory.clone())).unwrap();
+        log.append(b"last");
+        let records: Vec<Vec<u8>> = log.records().collect();
+        assert_eq!(records, vec![b"first".to_vec(), vec![], large, b"last".to_vec()]);
+
+        // a cleared log is rewritten from the start
+        let mut log = RecordLog::open(Box::new(memory.clone())).unwrap();
+        log.clear();
+        log.append(b"live");
+        log.set_compacted();
+        let records: Vec<Vec<u8>> = log.records().collect();
+        assert_eq!(records, vec![b"live".to_vec()]);
+        let log = RecordLog::open(Box::new(memory.clone())).unwrap();
+        assert_eq!(log.records().count(), 1);
+        assert_eq!(log.compacted_size(), HEADER_SIZE + 4 + 4);
+
+        // init overwrites the log
+        let log = RecordLog::init(Box::new(memory.clone()));
+        assert_eq!(log.records().count(), 0);
+        let log = RecordLog::open(Box::new(memory)).unwrap();
+        assert_eq!(log.records().count(), 0);
+    }
+}
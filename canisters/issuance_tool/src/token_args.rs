@@ -0,0 +1,359 @@
+use crate::types::*;
+use candid::Principal;
+use std::collections::BTreeSet;
+
+pub const MAX_TOKEN_NAME_LEN: usize = 64;
+pub const MAX_TOKEN_SYMBOL_LEN: usize = 12;
+pub const MAX_TOKEN_DECIMALS: u8 = 18;
+// fee rate decimals is 8, so 10^8 means 100% of the amount
+pub const MAX_FEE_RATE: u64 = 100_000_000;
+// max size of a token logo, 256 KiB
+pub const MAX_LOGO_SIZE: usize = 256 * 1024;
+// limits of the canister settings of the management canister
+pub const MAX_CONTROLLERS: usize = 10;
+pub const MAX_COMPUTE_ALLOCATION: u64 = 100;
+pub const MAX_MEMORY_ALLOCATION: u64 = 1 << 48;
+
+// check the token metadata before creating or installing a token canister
+pub fn validate_issue_args(args: &IssueTokenArgs) -> CommonResult<()> {
+    validate_token_args(
+        &args.name,
+        &args.symbol,
+        args.decimals,
+        args.total_supply,
+        &args.fee,
+        &args.logo,
+        &args.settings,
+    )
+}
+
+pub fn validate_create_args(args: &CreateTokenArgs) -> CommonResult<()> {
+    validate_token_args(
+        &args.name,
+        &args.symbol,
+        args.decimals,
+        args.total_supply,
+        &args.fee,
+        &args.logo,
+        &args.settings,
+    )
+}
+
+fn validate_token_args(
+    name: &str,
+    symbol: &str,
+    decimals: u8,
+    total_supply: u128,
+    fee: &Fee,
+    logo: &Option<Vec<u8>>,
+    settings: &Option<TokenSettings>,
+) -> CommonResult<()> {
+    validate_name(name)?;
+    validate_symbol(symbol)?;
+    if decimals > MAX_TOKEN_DECIMALS {
+        return Err(ToolError::InvalidTokenDecimals {
+            decimals,
+            max: MAX_TOKEN_DECIMALS,
+        });
+    }
+    if total_supply == 0 {
+        return Err(ToolError::InvalidTotalSupply);
+    }
+    if fee.rate > MAX_FEE_RATE {
+        return Err(ToolError::InvalidFeeRate {
+            rate: fee.rate.0.to_string(),
+            max: MAX_FEE_RATE,
+        });
+    }
+    if let Some(logo) = logo {
+        validate_logo(logo)?;
+    }
+    if let Some(settings) = settings {
+        validate_settings(settings)?;
+    }
+    Ok(())
+}
+
+fn validate_settings(settings: &TokenSettings) -> CommonResult<()> {
+    let invalid = |reason: &str| {
+        Err(ToolError::InvalidTokenSettings {
+            reason: reason.to_string(),
+        })
+    };
+    if settings.immutable == Some(true) && settings.controllers.is_some() {
+        return invalid("controllers can't be set for an immutable token");
+    }
+    if let Some(controllers) = &settings.controllers {
+        if controllers.len() > MAX_CONTROLLERS {
+            return invalid("too many controllers");
+        }
+        if controllers.contains(&Principal::anonymous()) {
+            return invalid("anonymous principal can't be a controller");
+        }
+        let unique: BTreeSet<&Principal> = controllers.iter().collect();
+        if unique.len() != controllers.len() {
+            return invalid("duplicate controllers");
+        }
+    }
+    if matches!(&settings.compute_allocation, Some(a) if *a > MAX_COMPUTE_ALLOCATION) {
+        return invalid("compute allocation must be at most 100");
+    }
+    if matches!(&settings.memory_allocation, Some(a) if *a > MAX_MEMORY_ALLOCATION) {
+        return invalid("memory allocation must be at most 2^48");
+    }
+    if matches!(&settings.freezing_threshold, Some(t) if *t > u64::MAX) {
+        return invalid("freezing threshold must fit in 64 bits");
+    }
+    Ok(())
+}
+
+fn validate_name(name: &str) -> CommonResult<()> {
+    let invalid = |reason: &str| {
+        Err(ToolError::InvalidTokenName {
+            reason: reason.to_string(),
+        })
+    };
+    if name.trim().is_empty() {
+        return invalid("name is empty");
+    }
+    if name.trim() != name {
+        return invalid("name has leading or trailing whitespace");
+    }
+    if name.chars().count() > MAX_TOKEN_NAME_LEN {
+        return invalid("name is too long");
+    }
+    if name.chars().any(|c| c.is_control()) {
+        return invalid("name contains control characters");
+    }
+    Ok(())
+}
+
+pub fn validate_symbol(symbol: &str) -> CommonResult<()> {
+    let invalid = |reason: &str| {
+        Err(ToolError::InvalidTokenSymbol {
+            reason: reason.to_string(),
+        })
+    };
+    if symbol.is_empty() {
+        return invalid("symbol is empty");
+    }
+    if symbol.len() > MAX_TOKEN_SYMBOL_LEN {
+        return invalid("symbol is too long");
+    }
+    if !symbol.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return invalid("symbol must be ascii letters and digits");
+    }
+    Ok(())
+}
+
+pub fn validate_logo(logo: &[u8]) -> CommonResult<()> {
+    if logo.len() > MAX_LOGO_SIZE {
+        return Err(ToolError::LogoTooLarge {
+            size: logo.len() as u64,
+            limit: MAX_LOGO_SIZE as u64,
+        });
+    }
+    if logo_content_type(logo).is_none() {
+        return Err(ToolError::UnsupportedLogoType);
+    }
+    Ok(())
+}
+
+// sniff the image type of a logo from its leading bytes
+pub fn logo_content_type(logo: &[u8]) -> Option<&'static str> {
+    if logo.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some("image/png");
+    }
+    if logo.starts_with(&[0xff, 0xd8, 0xff]) {
+        return Some("image/jpeg");
+    }
+    if logo.starts_with(b"GIF87a") || logo.starts_with(b"GIF89a") {
+        return Some("image/gif");
+    }
+    if logo.len() >= 12 && logo.starts_with(b"RIFF") && &logo[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+    // svg is not accepted, it can carry script served from the origin of the registry page
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Nat;
+
+    fn test_args() -> CreateTokenArgs {
+        CreateTokenArgs {
+            sub_account: None,
+            logo: None,
+            name: "Deland Token".to_string(),
+            symbol: "DLD".to_string(),
+            decimals: 18,
+            total_supply: 100,
+            fee: Fee {
+                minimum: Nat::from(1),
+                rate: Nat::from(10000),
+            },
+            template: None,
+            settings: None,
+        }
+    }
+
+    #[test]
+    fn test_validate_create_args() {
+        assert!(validate_create_args(&test_args()).is_ok());
+
+        let mut args = test_args();
+        args.name = " ".to_string();
+        assert!(matches!(
+            validate_create_args(&args).unwrap_err(),
+            ToolError::InvalidTokenName { .. }
+        ));
+        args.name = "a".repeat(MAX_TOKEN_NAME_LEN + 1);
+        assert!(matches!(
+            validate_create_args(&args).unwrap_err(),
+            ToolError::InvalidTokenName { .. }
+        ));
+        args.name = "bad\nname".to_string();
+        assert!(matches!(
+            validate_create_args(&args).unwrap_err(),
+            ToolError::InvalidTokenName { .. }
+        ));
+
+        let mut args = test_args();
+        args.symbol = "D-LD".to_string();
+        assert!(matches!(
+            validate_create_args(&args).unwrap_err(),
+            ToolError::InvalidTokenSymbol { .. }
+        ));
+        args.symbol = "D".repeat(MAX_TOKEN_SYMBOL_LEN + 1);
+        assert!(matches!(
+            validate_create_args(&args).unwrap_err(),
+            ToolError::InvalidTokenSymbol { .. }
+        ));
+
+        let mut args = test_args();
+        args.decimals = 19;
+        assert_eq!(
+            validate_create_args(&args).unwrap_err(),
+            ToolError::InvalidTokenDecimals {
+                decimals: 19,
+                max: MAX_TOKEN_DECIMALS,
+            }
+        );
+
+        let mut args = test_args();
+        args.total_supply = 0;
+        assert_eq!(validate_create_args(&args).unwrap_err(), ToolError::InvalidTotalSupply);
+
+        let mut args = test_args();
+        args.fee.rate = Nat::from(MAX_FEE_RATE);
+        assert!(validate_create_args(&args).is_ok());
+        args.fee.rate = Nat::from(MAX_FEE_RATE + 1);
+        assert_eq!(
+            validate_create_args(&args).unwrap_err(),
+            ToolError::InvalidFeeRate {
+                rate: (MAX_FEE_RATE + 1).to_string(),
+                max: MAX_FEE_RATE,
+            }
+        );
+    }
+
+    #[test]
+    fn test_validate_settings() {
+        let issuer =
+            Principal::from_text("czjfo-ddpvm-6sibl-6zbox-ee5zq-bx3hc-e336t-s6pka-dupmy-wcxqi-fae")
+                .unwrap();
+        let settings = TokenSettings {
+            controllers: Some(vec![issuer]),
+            immutable: None,
+            compute_allocation: Some(Nat::from(MAX_COMPUTE_ALLOCATION)),
+            memory_allocation: Some(Nat::from(MAX_MEMORY_ALLOCATION)),
+            freezing_threshold: Some(Nat::from(u64::MAX)),
+        };
+        let mut args = test_args();
+        args.settings = Some(settings.clone());
+        assert!(validate_create_args(&args).is_ok());
+        // blackholed token
+        args.settings = Some(TokenSettings {
+            controllers: Some(vec![]),
+            ..settings.clone()
+        });
+        assert!(validate_create_args(&args).is_ok());
+        args.settings = Some(TokenSettings {
+            controllers: None,
+            immutable: Some(true),
+            ..settings.clone()
+        });
+        assert!(validate_create_args(&args).is_ok());
+
+        let invalid = vec![
+            TokenSettings {
+                immutable: Some(true),
+                ..settings.clone()
+            },
+            TokenSettings {
+                controllers: Some(vec![issuer; 2]),
+                ..settings.clone()
+            },
+            TokenSettings {
+                controllers: Some(vec![Principal::anonymous()]),
+                ..settings.clone()
+            },
+            TokenSettings {
+                controllers: Some(
+                    (0..=MAX_CONTROLLERS as u8)
+                        .map(|i| Principal::from_slice(&[i]))
+                        .collect(),
+                ),
+                ..settings.clone()
+            },
+            TokenSettings {
+                compute_allocation: Some(Nat::from(MAX_COMPUTE_ALLOCATION + 1)),
+                ..settings.clone()
+            },
+            TokenSettings {
+                memory_allocation: Some(Nat::from(MAX_MEMORY_ALLOCATION + 1)),
+                ..settings.clone()
+            },
+            TokenSettings {
+                freezing_threshold: Some(Nat::from(u64::MAX) + Nat::from(1)),
+                ..settings
+            },
+        ];
+        for settings in invalid {
+            args.settings = Some(settings);
+            assert!(matches!(
+                validate_create_args(&args).unwrap_err(),
+                ToolError::InvalidTokenSettings { .. }
+            ));
+        }
+    }
+
+    #[test]
+    fn test_validate_logo() {
+        let mut args = test_args();
+        args.logo = Some(b"\x89PNG\r\n\x1a\n0000".to_vec());
+        assert!(validate_create_args(&args).is_ok());
+        args.logo = Some(b"not an image".to_vec());
+        assert_eq!(validate_create_args(&args).unwrap_err(), ToolError::UnsupportedLogoType);
+        let mut logo = b"GIF89a".to_vec();
+        logo.resize(MAX_LOGO_SIZE + 1, 0);
+        args.logo = Some(logo);
+        assert_eq!(
+            validate_create_args(&args).unwrap_err(),
+            ToolError::LogoTooLarge {
+                size: MAX_LOGO_SIZE as u64 + 1,
+                limit: MAX_LOGO_SIZE as u64,
+            }
+        );
+
+        assert_eq!(logo_content_type(&[0xff, 0xd8, 0xff, 0xe0]), Some("image/jpeg"));
+        assert_eq!(logo_content_type(b"RIFF0000WEBPVP8 "), Some("image/webp"));
+        assert_eq!(
+            logo_content_type(b"<?xml version=\"1.0\"?>\n<svg xmlns=\"http://www.w3.org/2000/svg\"/>"),
+            None
+        );
+        assert_eq!(logo_content_type(b"<html></html>"), None);
+    }
+}
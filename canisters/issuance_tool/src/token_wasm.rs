@@ -0,0 +1,272 @@
+use crate::types::*;
+use std::io::{Read, Write};
+
+// max size of a (decompressed) token wasm module, 8 MiB
+pub const MAX_TOKEN_WASM_SIZE: usize = 8 * 1024 * 1024;
+
+const WASM_MAGIC: [u8; 4] = [0x00, 0x61, 0x73, 0x6d];
+const WASM_VERSION: [u8; 4] = [0x01, 0x00, 0x00, 0x00];
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const EXPORT_SECTION_ID: u8 = 7;
+
+// methods a DFT token canister must export, besides canister_init
+const DFT_METHODS: [&str; 8] = [
+    "name",
+    "symbol",
+    "decimals",
+    "totalSupply",
+    "fee",
+    "meta",
+    "balanceOf",
+    "transfer",
+];
+
+pub fn is_gzip(bytes: &[u8]) -> bool {
+    bytes.starts_with(&GZIP_MAGIC)
+}
+
+// decompress a gzip token wasm, the decompressed size is limited by MAX_TOKEN_WASM_SIZE
+pub fn decompress(bytes: &[u8]) -> CommonResult<Vec<u8>> {
+    let decoder = libflate::gzip::Decoder::new(bytes).map_err(|e| ToolError::MalformedTokenWasm {
+        reason: format!("invalid gzip: {}", e),
+    })?;
+    let mut wasm = Vec::new();
+    decoder
+        .take(MAX_TOKEN_WASM_SIZE as u64 + 1)
+        .read_to_end(&mut wasm)
+        .map_err(|e| ToolError::MalformedTokenWasm {
+            reason: format!("invalid gzip: {}", e),
+        })?;
+    check_size(wasm.len())?;
+    Ok(wasm)
+}
+
+// gzip compress a token wasm module
+pub fn compress(wasm: &[u8]) -> Vec<u8> {
+    let mut encoder = libflate::gzip::Encoder::new(Vec::new()).expect("Failed to compress token wasm.");
+    encoder.write_all(wasm).expect("Failed to compress token wasm.");
+    encoder
+        .finish()
+        .into_result()
+        .expect("Failed to compress token wasm.")
+}
+
+// check the uploaded bytes is a (gzip compressed) wasm module of a DFT token,
+// returns the decompressed module
+pub fn validate_token_wasm(bytes: &[u8]) -> CommonResult<Vec<u8>> {
+    check_size(bytes.len())?;
+    if is_gzip(bytes) {
+        let wasm = decompress(bytes)?;
+        validate_module(&wasm)?;
+        return Ok(wasm);
+    }
+    validate_module(bytes)?;
+    Ok(bytes.to_vec())
+}
+
+fn check_size(size: usize) -> CommonResult<()> {
+    if size > MAX_TOKEN_WASM_SIZE {
+        return Err(ToolError::TokenWasmTooLarge {
+            size: size as u64,
+            limit: MAX_TOKEN_WASM_SIZE as u64,
+        });
+    }
+    Ok(())
+}
+
+fn validate_module(wasm: &[u8]) -> CommonResult<()> {
+    if wasm.len() < 8 || wasm[0..4] != WASM_MAGIC || wasm[4..8] != WASM_VERSION {
+        return Err(ToolError::InvalidTokenWasmHeader);
+    }
+    let exports = read_exports(&wasm[8..])?;
+    let has_export = |name: &str| exports.iter().any(|e| e == name);
+    if !has_export("canister_init") {
+        return Err(ToolError::TokenWasmMissingExport {
+            export: "canister_init".to_string(),
+        });
+    }
+    for method in DFT_METHODS.iter() {
+        let query = format!("canister_query {}", method);
+        let update = format!("canister_update {}", method);
+        if !has_export(&query) && !has_export(&update) {
+            return Err(ToolError::TokenWasmMissingExport {
+                export: method.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+// walk the sections of the module, and collect the names in the export section
+fn read_exports(sections: &[u8]) -> CommonResult<Vec<String>> {
+    let mut reader = Reader::new(sections);
+    let mut exports = Vec::new();
+    while !reader.is_empty() {
+        let id = reader.byte()?;
+        let size = reader.leb_u32()? as usize;
+        let content = reader.bytes(size)?;
+        if id != EXPORT_SECTION_ID {
+            continue;
+        }
+        let mut section = Reader::new(content);
+        let count = section.leb_u32()?;
+        for _ in 0..count {
+            let len = section.leb_u32()? as usize;
+            let name = String::from_utf8(section.bytes(len)?.to_vec()).map_err(|_| {
+                ToolError::MalformedTokenWasm {
+                    reason: "export name is not utf-8".to_string(),
+                }
+            })?;
+            // export kind and index
+            section.byte()?;
+            section.leb_u32()?;
+            exports.push(name);
+        }
+    }
+    Ok(exports)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes, offset: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.offset >= self.bytes.len()
+    }
+
+    fn byte(&mut self) -> CommonResult<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bytes(&mut self, len: usize) -> CommonResult<&'a [u8]> {
+        let end = self.offset.checked_add(len).filter(|end| *end <= self.bytes.len());
+        match end {
+            Some(end) => {
+                let bytes = &self.bytes[self.offset..end];
+                self.offset = end;
+                Ok(bytes)
+            }
+            None => Err(ToolError::MalformedTokenWasm {
+                reason: "unexpected end of module".to_string(),
+            }),
+        }
+    }
+
+    fn leb_u32(&mut self) -> CommonResult<u32> {
+        let mut result: u32 = 0;
+        for shift in (0..35).step_by(7) {
+            let byte = self.byte()?;
+            result |= ((byte & 0x7f) as u32) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+        }
+        Err(ToolError::MalformedTokenWasm {
+            reason: "invalid leb128 integer".to_string(),
+        })
+    }
+}
+
+// build a minimal module exporting the DFT methods, tagged by a custom section so the hash differs
+#[cfg(test)]
+pub fn test_token_wasm(tag: u8) -> Vec<u8> {
+    let mut names = vec!["canister_init".to_string()];
+    names.extend(DFT_METHODS.iter().map(|m| format!("canister_query {}", m)));
+    build_test_module(&names, tag)
+}
+
+#[cfg(test)]
+fn build_test_module(exports: &[String], tag: u8) -> Vec<u8> {
+    let mut export_section = vec![exports.len() as u8];
+    for (index, name) in exports.iter().enumerate() {
+        export_section.push(name.len() as u8);
+        export_section.extend_from_slice(name.as_bytes());
+        export_section.push(0x00);
+        export_section.push(index as u8);
+    }
+    let mut wasm = Vec::new();
+    wasm.extend_from_slice(&WASM_MAGIC);
+    wasm.extend_from_slice(&WASM_VERSION);
+    wasm.extend_from_slice(&[0x00, 0x05, 0x03, b't', b'a', b'g', tag]);
+    wasm.push(EXPORT_SECTION_ID);
+    let mut size = export_section.len();
+    loop {
+        let byte = (size & 0x7f) as u8;
+        size >>= 7;
+        if size == 0 {
+            wasm.push(byte);
+            break;
+        }
+        wasm.push(byte | 0x80);
+    }
+    wasm.extend(export_section);
+    wasm
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_token_wasm() {
+        assert!(validate_token_wasm(&test_token_wasm(0)).is_ok());
+
+        // not a wasm module
+        assert_eq!(
+            validate_token_wasm(&[1, 2, 3, 4, 5]).unwrap_err(),
+            ToolError::InvalidTokenWasmHeader
+        );
+        // truncated module
+        let wasm = test_token_wasm(0);
+        assert!(matches!(
+            validate_token_wasm(&wasm[..wasm.len() - 1]).unwrap_err(),
+            ToolError::MalformedTokenWasm { .. }
+        ));
+        // missing canister_init
+        let names: Vec<String> = DFT_METHODS.iter().map(|m| format!("canister_query {}", m)).collect();
+        assert_eq!(
+            validate_token_wasm(&build_test_module(&names, 0)).unwrap_err(),
+            ToolError::TokenWasmMissingExport {
+                export: "canister_init".to_string(),
+            }
+        );
+        // missing a DFT method
+        let names = vec!["canister_init".to_string(), "canister_query name".to_string()];
+        assert_eq!(
+            validate_token_wasm(&build_test_module(&names, 0)).unwrap_err(),
+            ToolError::TokenWasmMissingExport {
+                export: "symbol".to_string(),
+            }
+        );
+        // too large
+        let mut wasm = test_token_wasm(0);
+        wasm.resize(MAX_TOKEN_WASM_SIZE + 1, 0);
+        assert_eq!(
+            validate_token_wasm(&wasm).unwrap_err(),
+            ToolError::TokenWasmTooLarge {
+                size: MAX_TOKEN_WASM_SIZE as u64 + 1,
+                limit: MAX_TOKEN_WASM_SIZE as u64,
+            }
+        );
+    }
+
+    #[test]
+    fn test_validate_gzip_token_wasm() {
+        let gzip = compress(&test_token_wasm(0));
+        assert!(is_gzip(&gzip));
+        assert_eq!(validate_token_wasm(&gzip).unwrap(), test_token_wasm(0));
+        assert_eq!(decompress(&gzip).unwrap(), test_token_wasm(0));
+
+        // broken gzip stream
+        assert!(matches!(
+            validate_token_wasm(&gzip[..gzip.len() / 2]).unwrap_err(),
+            ToolError::MalformedTokenWasm { .. }
+        ));
+    }
+}
@@ -1,8 +1,27 @@
+use crate::certification::{certified_root, token_hash, tokens_witness, CertifiedTokenTree};
+use crate::locks::InFlightLocks;
+use crate::stable::RecordLog;
+use crate::token_args::{logo_content_type, validate_create_args, validate_logo, validate_symbol};
+use crate::token_wasm::{compress, decompress, is_gzip, validate_token_wasm, MAX_TOKEN_WASM_SIZE};
 use crate::types::*;
-use candid::{CandidType, Deserialize, Principal};
-use std::collections::HashMap;
+use candid::{CandidType, Decode, Deserialize, Encode, Principal};
+use serde_bytes::ByteBuf;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Bound;
 
 pub type TokenInfoMap = HashMap<Principal, TokenInfo>;
+pub type IssuanceOrderMap = HashMap<u64, IssuanceOrder>;
+pub type TokenUpgradeMap = HashMap<Principal, Vec<TokenUpgrade>>;
+pub type TokenWasmMap = HashMap<Vec<u8>, TokenWasm>;
+pub type WasmUploadMap = HashMap<u64, WasmUpload>;
+pub type IssuanceMap = BTreeMap<u64, Issuance>;
+pub type ProposalMap = BTreeMap<u64, Proposal>;
+
+const LEDGER_CANISTER_ID: &str = "ryjl3-tyaaa-aaaaa-aaaba-cai";
+const CYCLES_MINTING_CANISTER_ID: &str = "rkp4c-7iaaa-aaaaa-aaaca-cai";
+// version the token wasm of the first release is kept as
+const LEGACY_TOKEN_WASM_VERSION: &str = "0.0.0";
 
 #[derive(CandidType, PartialOrd, Eq, PartialEq, Clone, Deserialize, Debug)]
 pub struct TokenInfo {
@@ -15,15 +34,201 @@ pub struct TokenInfo {
     #[serde(rename = "totalSupply")]
     pub total_supply: u128,
     pub fee: Fee,
+    // the fields added after the first release default, so saved payloads still decode
+    #[serde(default = "default_token_template")]
+    pub template: String,
+    // controllers the token was handed over to
+    #[serde(default)]
+    pub controllers: Vec<Principal>,
+    // the token is handed over to a blackhole canister, and can't be upgraded by the tool
+    #[serde(default)]
+    pub immutable: bool,
+    // the blackhole canister of an immutable token, its status is read through it
+    #[serde(rename = "blackholeCanisterId", default)]
+    pub blackhole_canister_id: Option<Principal>,
+    // sha256 of the installed module
+    #[serde(rename = "moduleHash")]
+    pub module_hash: Option<Vec<u8>>,
+    pub timestamp: u64,
+}
+
+// the current state of a token compared to the state it was issued (or last upgraded) with
+#[derive(CandidType, Deserialize, PartialOrd, Eq, PartialEq, Clone, Debug)]
+pub struct TokenVerification {
+    #[serde(rename = "tokenId")]
+    pub token_id: Principal,
+    pub immutable: bool,
+    pub controllers: Vec<Principal>,
+    #[serde(rename = "moduleHash")]
+    pub module_hash: Option<Vec<u8>>,
+    #[serde(rename = "controllersMatch")]
+    pub controllers_match: bool,
+    #[serde(rename = "moduleHashMatch")]
+    pub module_hash_match: bool,
+    pub verified: bool,
+}
+
+// an upgrade of an issued token, module hashes are sha256 of the wasm module
+#[derive(CandidType, PartialOrd, Eq, PartialEq, Clone, Deserialize, Debug)]
+pub struct TokenUpgrade {
+    pub operator: Principal,
+    pub old_module_hash: Option<Vec<u8>>,
+    pub new_module_hash: Vec<u8>,
     pub timestamp: u64,
 }
 
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct TokenPage {
+    pub tokens: Vec<TokenInfo>,
+    pub total: u64,
+    #[serde(rename = "nextCursor")]
+    pub next_cursor: Option<Principal>,
+}
+
+// a token with the witness of its certified hash, token is none when the witness proves absence
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CertifiedToken {
+    pub token: Option<TokenInfo>,
+    pub certificate: Option<serde_bytes::ByteBuf>,
+    pub witness: serde_bytes::ByteBuf,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CertifiedTokens {
+    pub tokens: Vec<TokenInfo>,
+    pub certificate: Option<serde_bytes::ByteBuf>,
+    pub witness: serde_bytes::ByteBuf,
+}
+
+fn default_token_template() -> String {
+    DEFAULT_TOKEN_TEMPLATE.to_string()
+}
+
+// fields missing from a payload saved by an earlier version take the defaults of a new tool
 #[derive(CandidType, Deserialize, PartialOrd, Eq, PartialEq, Clone, Debug)]
+#[serde(default)]
 pub struct ToolPayload {
     pub owner: Principal,
     pub cycles_per_token: u64,
-    pub token_wasm: Vec<u8>,
+    pub token_wasms: Vec<TokenWasm>,
+    pub install_compressed_wasm: bool,
     pub tokens: Vec<(Principal, TokenInfo)>,
+    pub e8s_per_token: u64,
+    pub ledger_canister_id: Principal,
+    pub cmc_canister_id: Principal,
+    pub orders: Vec<(u64, IssuanceOrder)>,
+    pub next_order_id: u64,
+    pub public_issuance: bool,
+    pub issuer_quota: u64,
+    pub token_upgrades: Vec<(Principal, Vec<TokenUpgrade>)>,
+    pub unique_symbols: bool,
+    pub blocked_symbols: Vec<String>,
+    pub symbol_reservations: Vec<SymbolReservation>,
+    pub symbol_reservation_ttl: u64,
+    pub logos: Vec<(Principal, Vec<u8>)>,
+    pub issuances: Vec<Issuance>,
+    pub next_issuance_id: u64,
+    pub blackhole_canister_id: Option<Principal>,
+    pub roles: Vec<(Role, Vec<Principal>)>,
+    pub pending_owner: Option<OwnershipTransfer>,
+    pub multisig: Option<MultisigConfig>,
+    pub proposals: Vec<Proposal>,
+    pub next_proposal_id: u64,
+    pub events: Vec<Event>,
+}
+
+impl Default for ToolPayload {
+    fn default() -> Self {
+        IssuanceTool::new().to_payload()
+    }
+}
+
+// TokenInfo as saved by the first release
+#[derive(CandidType, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct LegacyTokenInfo {
+    pub issuer: Principal,
+    #[serde(rename = "tokenId")]
+    pub token_id: Principal,
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+    #[serde(rename = "totalSupply")]
+    pub total_supply: u128,
+    pub fee: Fee,
+    pub timestamp: u64,
+}
+
+impl From<LegacyTokenInfo> for TokenInfo {
+    fn from(info: LegacyTokenInfo) -> Self {
+        TokenInfo {
+            issuer: info.issuer,
+            token_id: info.token_id,
+            name: info.name,
+            symbol: info.symbol,
+            decimals: info.decimals,
+            total_supply: info.total_supply,
+            fee: info.fee,
+            template: DEFAULT_TOKEN_TEMPLATE.to_string(),
+            controllers: Vec::new(),
+            immutable: false,
+            blackhole_canister_id: None,
+            module_hash: None,
+            timestamp: info.timestamp,
+        }
+    }
+}
+
+// ToolPayload as saved by the first release, strict so a later payload is not mistaken for it
+#[derive(CandidType, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct LegacyToolPayload {
+    pub owner: Principal,
+    pub cycles_per_token: u64,
+    pub token_wasm: Vec<u8>,
+    pub tokens: Vec<(Principal, LegacyTokenInfo)>,
+}
+
+// the state not written to the record log as it changes, appended as a snapshot on upgrade
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ToolSettings {
+    pub owner: Principal,
+    pub pending_owner: Option<OwnershipTransfer>,
+    pub cycles_per_token: u64,
+    pub e8s_per_token: u64,
+    pub ledger_canister_id: Principal,
+    pub cmc_canister_id: Principal,
+    pub install_compressed_wasm: bool,
+    pub public_issuance: bool,
+    pub issuer_quota: u64,
+    pub unique_symbols: bool,
+    pub blocked_symbols: Vec<String>,
+    pub symbol_reservations: Vec<SymbolReservation>,
+    pub symbol_reservation_ttl: u64,
+    pub blackhole_canister_id: Option<Principal>,
+    pub roles: Vec<(Role, Vec<Principal>)>,
+    pub multisig: Option<MultisigConfig>,
+    pub next_order_id: u64,
+    pub next_issuance_id: u64,
+    pub next_proposal_id: u64,
+}
+
+// a change written to the record log, the log is replayed in order on upgrade and the last record of a key wins
+#[derive(CandidType, Deserialize)]
+enum StableRecord {
+    Token(TokenInfo),
+    TokenUpgrade(Principal, TokenUpgrade),
+    TokenWasm(TokenWasm),
+    Logo(Principal, ByteBuf),
+    Order(IssuanceOrder),
+    Issuance(Issuance),
+    Proposal(Proposal),
+    Event(Event),
+    Settings(ToolSettings),
+}
+
+fn append_record(store: &mut RecordLog, record: &StableRecord) {
+    store.append(&Encode!(record).expect("Failed to encode stable record."));
 }
 
 #[derive(CandidType, Deserialize)]
@@ -32,22 +237,129 @@ pub struct ToolStatus {
     pub cycles_per_token: u64,
     pub cycles: u64,
     pub issued_token_count: u128,
+    pub token_wasm_count: u64,
+    // total size of the decompressed token wasm modules
+    pub token_wasm_size: u64,
+    // total size of the token wasm modules as stored
+    pub token_wasm_compressed_size: u64,
+    pub install_compressed_wasm: bool,
+    pub e8s_per_token: u64,
+    pub ledger_canister_id: Principal,
+    pub cmc_canister_id: Principal,
+    pub public_issuance: bool,
+    pub issuer_quota: u64,
+    // bytes of stable memory used by the record log
+    pub stable_log_size: u64,
+    // bytes used by the record log after the last compaction
+    pub stable_log_compacted_size: u64,
 }
 
 pub struct IssuanceTool {
     pub owner: Principal,
     pub cycles_per_token: u64,
-    pub token_wasm: Vec<u8>,
+    // token wasm registry, keyed by sha256 of the module
+    pub token_wasms: TokenWasmMap,
+    pub install_compressed_wasm: bool,
     pub tokens: TokenInfoMap,
+    // token ids in the order the tokens were added, used for stable pagination
+    pub token_ids: Vec<Principal>,
+    // token id -> position in token_ids
+    pub token_positions: HashMap<Principal, usize>,
+    // (sort key, position in token_ids) of every token, one index per TokenSortBy, used by list_tokens
+    pub tokens_by_issue_time: BTreeSet<(u64, usize)>,
+    pub tokens_by_name: BTreeSet<(String, usize)>,
+    pub tokens_by_symbol: BTreeSet<(String, usize)>,
+    // issuer -> ids of the tokens issued, in insertion order, rebuilt when loading the payload
+    pub issuer_tokens: HashMap<Principal, Vec<Principal>>,
+    // lowercase suffixes of token names and symbols -> positions in token_ids, for substring search
+    pub search_index: BTreeMap<String, BTreeSet<usize>>,
+    // folded symbol -> ids of the tokens using it
+    pub symbol_tokens: HashMap<String, BTreeSet<Principal>>,
+    // token id -> hash of the token info, the root is set as certified data
+    pub certified_tokens: CertifiedTokenTree,
+    pub e8s_per_token: u64,
+    pub ledger_canister_id: Principal,
+    pub cmc_canister_id: Principal,
+    pub orders: IssuanceOrderMap,
+    pub next_order_id: u64,
+    // when enabled, any non-anonymous principal can issue tokens
+    pub public_issuance: bool,
+    // max tokens a non-owner issuer can issue, 0 means unlimited
+    pub issuer_quota: u64,
+    pub token_upgrades: TokenUpgradeMap,
+    // staging area of chunked wasm uploads, not saved on upgrade
+    pub wasm_uploads: WasmUploadMap,
+    pub next_upload_id: u64,
+    // when enabled, a symbol (case-folded) can only be used by one token
+    pub unique_symbols: bool,
+    // folded symbols only the owner and admins can issue
+    pub blocked_symbols: BTreeSet<String>,
+    // folded symbol -> reservation
+    pub symbol_reservations: HashMap<String, SymbolReservation>,
+    pub symbol_reservation_ttl: u64,
+    // token id -> logo image
+    pub logos: HashMap<Principal, Vec<u8>>,
+    pub issuances: IssuanceMap,
+    pub next_issuance_id: u64,
+    // locks of the calls in flight, not saved on upgrade
+    pub locks: InFlightLocks,
+    // controller of immutable tokens, a canister without controllers that only exposes canister_status
+    pub blackhole_canister_id: Option<Principal>,
+    pub roles: BTreeMap<Role, BTreeSet<Principal>>,
+    pub pending_owner: Option<OwnershipTransfer>,
+    // signers of the privileged operations, none to let the role holders execute them directly
+    pub multisig: Option<MultisigConfig>,
+    pub proposals: ProposalMap,
+    pub next_proposal_id: u64,
+    // append-only audit log, the event id is the index
+    pub events: Vec<Event>,
+    // stable memory the changes are written to, not attached in unit tests
+    pub store: Option<RecordLog>,
 }
 
 impl IssuanceTool {
     pub fn new() -> Self {
         IssuanceTool {
             owner: Principal::anonymous(),
-            cycles_per_token: 3000_000_000_000, // 3 T Cycles
-            token_wasm: Vec::new(),
+            cycles_per_token: 3_000_000_000_000, // 3 T Cycles
+            token_wasms: TokenWasmMap::new(),
+            install_compressed_wasm: false,
             tokens: TokenInfoMap::new(),
+            token_ids: Vec::new(),
+            token_positions: HashMap::new(),
+            tokens_by_issue_time: BTreeSet::new(),
+            tokens_by_name: BTreeSet::new(),
+            tokens_by_symbol: BTreeSet::new(),
+            issuer_tokens: HashMap::new(),
+            search_index: BTreeMap::new(),
+            symbol_tokens: HashMap::new(),
+            certified_tokens: CertifiedTokenTree::new(),
+            e8s_per_token: 100_000_000, // 1 ICP
+            ledger_canister_id: Principal::from_text(LEDGER_CANISTER_ID).unwrap(),
+            cmc_canister_id: Principal::from_text(CYCLES_MINTING_CANISTER_ID).unwrap(),
+            orders: IssuanceOrderMap::new(),
+            next_order_id: 1,
+            public_issuance: false,
+            issuer_quota: 0,
+            token_upgrades: TokenUpgradeMap::new(),
+            wasm_uploads: WasmUploadMap::new(),
+            next_upload_id: 1,
+            unique_symbols: false,
+            blocked_symbols: BTreeSet::new(),
+            symbol_reservations: HashMap::new(),
+            symbol_reservation_ttl: DEFAULT_SYMBOL_RESERVATION_TTL,
+            logos: HashMap::new(),
+            issuances: IssuanceMap::new(),
+            next_issuance_id: 1,
+            locks: InFlightLocks::new(),
+            blackhole_canister_id: None,
+            roles: BTreeMap::new(),
+            pending_owner: None,
+            multisig: None,
+            proposals: ProposalMap::new(),
+            next_proposal_id: 1,
+            events: Vec::new(),
+            store: None,
         }
     }
 
@@ -68,35 +380,358 @@ impl IssuanceTool {
         Ok(())
     }
 
+    // check if the caller holds the role, the owner holds every role and an admin every role but admin
+    pub fn has_role(&self, caller: &Principal, role: Role) -> bool {
+        let holds = |role: Role| self.roles.get(&role).map_or(false, |holders| holders.contains(caller));
+        &self.owner == caller || holds(role) || (role != Role::Admin && holds(Role::Admin))
+    }
+
+    pub fn only_role(&self, caller: &Principal, role: Role) -> CommonResult<()> {
+        self.not_allow_anonymous(caller)?;
+        if !self.has_role(caller, role) {
+            return Err(ToolError::RoleRequired { role });
+        }
+        Ok(())
+    }
+
+    // check the caller holds the role of a privileged operation, when multisig is enabled
+    // the operation can only be executed by an approved proposal
+    pub fn only_privileged(&self, caller: &Principal, role: Role) -> CommonResult<()> {
+        self.only_role(caller, role)?;
+        if self.multisig.is_some() {
+            return Err(ToolError::MultisigApprovalRequired);
+        }
+        Ok(())
+    }
+
+    // grant the role to the principal, only the owner can grant admin
+    pub fn grant_role(&mut self, caller: &Principal, role: Role, principal: Principal) -> CommonResult<bool> {
+        self.only_role_manager(caller, role)?;
+        self.not_allow_anonymous(&principal)?;
+        self.roles.entry(role).or_default().insert(principal);
+        Ok(true)
+    }
+
+    // revoke the role from the principal, only the owner can revoke admin
+    pub fn revoke_role(&mut self, caller: &Principal, role: Role, principal: Principal) -> CommonResult<bool> {
+        self.only_role_manager(caller, role)?;
+        if let Some(holders) = self.roles.get_mut(&role) {
+            holders.remove(&principal);
+            if holders.is_empty() {
+                self.roles.remove(&role);
+            }
+        }
+        Ok(true)
+    }
+
+    fn only_role_manager(&self, caller: &Principal, role: Role) -> CommonResult<()> {
+        if role == Role::Admin {
+            self.only_owner(caller)
+        } else {
+            self.only_role(caller, Role::Admin)
+        }
+    }
+
+    // get holders of the role, or of every role
+    pub fn get_role_holders(&self, role: Option<Role>) -> Vec<RoleHolders> {
+        self.roles
+            .iter()
+            .filter(|(r, _)| role.map_or(true, |role| role == **r))
+            .map(|(role, holders)| RoleHolders {
+                role: *role,
+                holders: holders.iter().cloned().collect(),
+            })
+            .collect()
+    }
+
+    // check if the caller is allowed to issue a token, allowlisted issuers can issue when public issuance is disabled
+    pub fn only_issuer(&self, caller: &Principal) -> CommonResult<()> {
+        self.not_allow_anonymous(caller)?;
+        if &self.owner == caller {
+            return Ok(());
+        }
+        if !self.public_issuance && !self.has_role(caller, Role::Issuer) {
+            return Err(ToolError::PublicIssuanceDisabled);
+        }
+        if self.issuer_quota > 0 {
+            // issuances not registered yet hold their share of the quota
+            let issued = self.issuer_tokens.get(caller).map_or(0, |ids| ids.len())
+                + self.unregistered_issuances().filter(|i| &i.issuer == caller).count();
+            if issued as u64 >= self.issuer_quota {
+                return Err(ToolError::IssuerQuotaExceeded {
+                    quota: self.issuer_quota,
+                });
+            }
+        }
+        Ok(())
+    }
+
     // get cycles per token
     pub fn cycles_per_token(&self) -> u64 {
         self.cycles_per_token
     }
     // get owner
     pub fn owner(&self) -> Principal {
-        self.owner.clone()
+        self.owner
     }
-    // set owner
+    // set the initial owner when the tool is installed, later changes go through an ownership transfer
     pub fn set_owner(&mut self, caller: &Principal, owner: Principal) -> CommonResult<()> {
         self.not_allow_anonymous(caller)?;
+        self.not_allow_anonymous(&owner)?;
         if self.owner != Principal::anonymous() {
             self.only_owner(caller)?;
+            return Err(ToolError::OwnerAlreadySet);
         }
         self.owner = owner;
         Ok(())
     }
 
+    // a tool saved before its owner was set can't get one through a call, the controller upgrading it becomes the owner
+    pub fn set_owner_if_unset(&mut self, upgrader: &Principal, now: u64) -> CommonResult<()> {
+        if self.owner != Principal::anonymous() {
+            return Ok(());
+        }
+        self.set_owner(upgrader, *upgrader)?;
+        self.save_settings();
+        self.record_event(*upgrader, ToolEvent::OwnerSet { owner: *upgrader }, now);
+        Ok(())
+    }
+
+    // propose a new owner, who must accept the ownership before it expires,
+    // a new proposal replaces the pending one
+    pub fn propose_owner(&mut self, caller: &Principal, new_owner: Principal, now: u64) -> CommonResult<OwnershipTransfer> {
+        self.only_owner(caller)?;
+        self.not_allow_anonymous(&new_owner)?;
+        let transfer = OwnershipTransfer {
+            new_owner,
+            proposed_at: now,
+            expires_at: now + OWNERSHIP_TRANSFER_TTL,
+        };
+        self.pending_owner = Some(transfer.clone());
+        Ok(transfer)
+    }
+
+    // accept the pending ownership transfer, called by the new owner
+    pub fn accept_ownership(&mut self, caller: &Principal, now: u64) -> CommonResult<()> {
+        self.not_allow_anonymous(caller)?;
+        let transfer = match &self.pending_owner {
+            Some(transfer) if &transfer.new_owner == caller => transfer.clone(),
+            _ => return Err(ToolError::OwnershipTransferNotFound),
+        };
+        if now >= transfer.expires_at {
+            self.pending_owner = None;
+            return Err(ToolError::OwnershipTransferExpired);
+        }
+        self.owner = transfer.new_owner;
+        self.pending_owner = None;
+        Ok(())
+    }
+
+    // cancel the pending ownership transfer
+    pub fn cancel_ownership_transfer(&mut self, caller: &Principal) -> CommonResult<()> {
+        self.only_owner(caller)?;
+        match self.pending_owner.take() {
+            Some(_) => Ok(()),
+            None => Err(ToolError::OwnershipTransferNotFound),
+        }
+    }
+
+    // get the pending ownership transfer, if not expired
+    pub fn get_pending_owner(&self, now: u64) -> Option<OwnershipTransfer> {
+        self.pending_owner.clone().filter(|t| now < t.expires_at)
+    }
+
     // set cycles per token
     pub fn set_cycles_per_token(
         &mut self,
         caller: &Principal,
         cycles_per_token: u64,
     ) -> CommonResult<bool> {
-        self.only_owner(caller)?;
+        self.only_privileged(caller, Role::FeeManager)?;
         self.cycles_per_token = cycles_per_token;
         Ok(true)
     }
 
+    // enable or disable public issuance
+    pub fn set_public_issuance(&mut self, caller: &Principal, enabled: bool) -> CommonResult<bool> {
+        self.only_role(caller, Role::Admin)?;
+        self.public_issuance = enabled;
+        Ok(true)
+    }
+
+    // set the max tokens a non-owner issuer can issue
+    pub fn set_issuer_quota(&mut self, caller: &Principal, quota: u64) -> CommonResult<bool> {
+        self.only_role(caller, Role::Admin)?;
+        self.issuer_quota = quota;
+        Ok(true)
+    }
+
+    // get e8s per token
+    pub fn e8s_per_token(&self) -> u64 {
+        self.e8s_per_token
+    }
+
+    // set e8s per token, the ICP price of an issuance order
+    pub fn set_e8s_per_token(&mut self, caller: &Principal, e8s_per_token: u64) -> CommonResult<bool> {
+        self.only_privileged(caller, Role::FeeManager)?;
+        self.e8s_per_token = e8s_per_token;
+        Ok(true)
+    }
+
+    // set the ledger and cycles minting canister used to accept payments
+    pub fn set_payment_canisters(
+        &mut self,
+        caller: &Principal,
+        ledger: Principal,
+        cmc: Principal,
+    ) -> CommonResult<bool> {
+        self.only_privileged(caller, Role::Admin)?;
+        self.ledger_canister_id = ledger;
+        self.cmc_canister_id = cmc;
+        Ok(true)
+    }
+
+    // place an issuance order, the order id is the memo of the payment
+    pub fn place_order(
+        &mut self,
+        caller: &Principal,
+        tool_id: &Principal,
+        args: CreateTokenArgs,
+        now: u64,
+    ) -> CommonResult<IssuanceOrder> {
+        self.only_issuer(caller)?;
+        validate_create_args(&args)?;
+        self.check_token_settings(&args)?;
+        self.check_symbol(caller, &args.symbol, None, now)?;
+        let order_id = self.next_order_id;
+        let subaccount = Subaccount::from(order_id);
+        let order = IssuanceOrder {
+            order_id,
+            issuer: *caller,
+            args,
+            price: ICPTs::from_e8s(self.e8s_per_token()),
+            subaccount,
+            payment_account: AccountIdentifier::new(tool_id, Some(subaccount)).to_hex(),
+            status: OrderStatus::Pending,
+            payment: None,
+            error: None,
+            timestamp: now,
+        };
+        self.orders.insert(order_id, order.clone());
+        self.next_order_id += 1;
+        self.persist(StableRecord::Order(order.clone()));
+        Ok(order)
+    }
+
+    // get order by id
+    pub fn get_order(&self, order_id: u64) -> CommonResult<IssuanceOrder> {
+        match self.orders.get(&order_id) {
+            Some(order) => Ok(order.clone()),
+            None => Err(ToolError::OrderNotFound),
+        }
+    }
+
+    // match a ledger notification to a pending order, and mark the order as paid
+    pub fn accept_payment(
+        &mut self,
+        caller: &Principal,
+        tool_id: &Principal,
+        notification: &TransactionNotification,
+    ) -> CommonResult<IssuanceOrder> {
+        if caller != &self.ledger_canister_id {
+            return Err(ToolError::OnlyLedgerAllowCallIt);
+        }
+        if &notification.to != tool_id {
+            return Err(ToolError::OrderNotFound);
+        }
+        // payments sent to an order's sub-account take precedence over the memo
+        let order_id = match notification.to_subaccount {
+            Some(subaccount) => self
+                .orders
+                .values()
+                .find(|order| order.subaccount == subaccount)
+                .map(|order| order.order_id)
+                .ok_or(ToolError::OrderNotFound)?,
+            None => notification.memo.0,
+        };
+        let order = self.orders.get_mut(&order_id).ok_or(ToolError::OrderNotFound)?;
+        if order.status != OrderStatus::Pending {
+            return Err(ToolError::OrderNotPending);
+        }
+        if notification.amount < order.price {
+            return Err(ToolError::InsufficientPayment {
+                required: order.price.e8s,
+                received: notification.amount.e8s,
+            });
+        }
+        order.status = OrderStatus::Paid {
+            block_height: notification.block_height,
+        };
+        order.payment = Some(OrderPayment {
+            payer: notification.from,
+            payer_subaccount: notification.from_subaccount,
+            subaccount: notification.to_subaccount,
+            amount: notification.amount,
+            block_height: notification.block_height,
+            excess_refund: None,
+        });
+        let order = order.clone();
+        self.persist(StableRecord::Order(order.clone()));
+        Ok(order)
+    }
+
+    // set order status
+    pub fn set_order_status(&mut self, order_id: u64, status: OrderStatus) -> CommonResult<()> {
+        let order = self.orders.get_mut(&order_id).ok_or(ToolError::OrderNotFound)?;
+        order.status = status;
+        order.error = None;
+        self.persist_order(order_id);
+        Ok(())
+    }
+
+    // record the refund of the amount paid over the price, the order stays paid
+    pub fn set_order_excess_refund(&mut self, order_id: u64, block_height: u64) -> CommonResult<()> {
+        let order = self.orders.get_mut(&order_id).ok_or(ToolError::OrderNotFound)?;
+        let payment = order.payment.as_mut().ok_or(ToolError::OrderNotRetryable)?;
+        payment.excess_refund = Some(block_height);
+        order.error = None;
+        self.persist_order(order_id);
+        Ok(())
+    }
+
+    // record the error of the last attempt, the order stays at its status
+    pub fn set_order_error(&mut self, order_id: u64, error: &ToolError) -> CommonResult<()> {
+        let order = self.orders.get_mut(&order_id).ok_or(ToolError::OrderNotFound)?;
+        order.error = Some(error.to_string());
+        self.persist_order(order_id);
+        Ok(())
+    }
+
+    // the issuer of the order and admins can retry or refund it
+    pub fn only_order_operator(&self, caller: &Principal, order_id: u64) -> CommonResult<IssuanceOrder> {
+        self.not_allow_anonymous(caller)?;
+        let order = self.get_order(order_id)?;
+        if caller != &order.issuer && !self.has_role(caller, Role::Admin) {
+            return Err(ToolError::OnlyOwnerAllowCallIt);
+        }
+        Ok(order)
+    }
+
+    // check the token of the order can still be issued, before its payment is converted
+    pub fn check_order_issuance(&self, order: &IssuanceOrder, now: u64) -> CommonResult<()> {
+        self.only_issuer(&order.issuer)?;
+        self.check_token_settings(&order.args)?;
+        self.check_symbol(&order.issuer, &order.args.symbol, None, now)
+    }
+
+    // the issuance started for the order
+    pub fn get_order_issuance(&self, order_id: u64) -> Option<u64> {
+        self.issuances
+            .values()
+            .find(|i| i.order_id == Some(order_id))
+            .map(|i| i.issuance_id)
+    }
+
     // get tokens count
     pub fn get_token_count(&self) -> CommonResult<u128> {
         Ok(self.tokens.len() as u128)
@@ -120,177 +755,2844 @@ impl IssuanceTool {
     ) -> CommonResult<Vec<TokenInfo>> {
         // max page size is 200
         let page_size = if page_size > 200 { 200 } else { page_size };
-        let mut token_list = Vec::new();
-        let mut index = 0;
-        for token_info in self.tokens.values() {
-            if index >= start_index as usize {
-                token_list.push(token_info.clone());
-            }
-            index += 1;
-            if index >= (start_index + page_size) as usize {
-                break;
-            }
-        }
+        let token_list = self
+            .token_ids
+            .iter()
+            .skip(start_index)
+            .take(page_size)
+            .map(|token_id| self.tokens[token_id].clone())
+            .collect();
         Ok(token_list)
     }
 
-    // add token
-    pub fn add_token(&mut self, caller: &Principal, token_info: TokenInfo) -> CommonResult<()> {
-        self.only_owner(caller)?;
-        self.tokens.insert(token_info.token_id, token_info);
-        Ok(())
+    // root hash of the certified token registry
+    pub fn certified_tokens_root(&self) -> [u8; 32] {
+        certified_root(&self.certified_tokens)
     }
 
-    // get token wasm
-    pub fn get_token_wasm(&self) -> CommonResult<Vec<u8>> {
-        // check wasm length
-        if self.token_wasm.len() == 0 {
-            return Err(ToolError::InvalidTokenWasmModule);
-        } else {
-            Ok(self.token_wasm.clone())
+    // get token with the witness of its certified hash
+    pub fn get_certified_token(&self, token_id: &Principal) -> CertifiedToken {
+        CertifiedToken {
+            token: self.tokens.get(token_id).cloned(),
+            certificate: None,
+            witness: serde_bytes::ByteBuf::from(tokens_witness(
+                &self.certified_tokens,
+                &[token_id.as_slice().to_vec()],
+            )),
         }
     }
 
-    // set token wasm
-    pub fn set_token_wasm(&mut self, caller: &Principal, token_wasm: Vec<u8>) -> CommonResult<()> {
-        self.only_owner(caller)?;
-        self.token_wasm = token_wasm;
-        Ok(())
+    // get token with page parameters, with the witness of their certified hashes
+    pub fn get_certified_tokens(&self, start_index: usize, page_size: usize) -> CommonResult<CertifiedTokens> {
+        let tokens = self.get_tokens(start_index, page_size)?;
+        let keys: Vec<Vec<u8>> = tokens.iter().map(|t| t.token_id.as_slice().to_vec()).collect();
+        Ok(CertifiedTokens {
+            tokens,
+            certificate: None,
+            witness: serde_bytes::ByteBuf::from(tokens_witness(&self.certified_tokens, &keys)),
+        })
     }
 
-    // convert to ToolPayload
-    pub fn to_payload(&self) -> ToolPayload {
-        ToolPayload {
-            owner: self.owner.clone(),
-            cycles_per_token: self.cycles_per_token,
-            token_wasm: self.token_wasm.clone(),
-            tokens: self
-                .tokens
-                .iter()
-                .map(|(k, v)| (k.clone(), v.clone()))
-                .collect(),
-        }
+    // get a page of tokens in the given order, the cursor is the id of the last token of the previous page
+    pub fn list_tokens(&self, args: ListTokensArgs) -> CommonResult<TokenPage> {
+        let page_size = if args.size > 200 { 200 } else { args.size as usize };
+        let sort_by = args.sort_by.unwrap_or(TokenSortBy::IssueTime);
+        let direction = args.direction.unwrap_or(SortDirection::Ascending);
+        // the page resumes right after the cursor token in the index of the sort key
+        let cursor = match args.cursor {
+            Some(cursor) => Some((
+                self.tokens.get(&cursor).ok_or(ToolError::TokenNotFound)?,
+                self.token_positions[&cursor],
+            )),
+            None => None,
+        };
+        let (positions, has_more) = match sort_by {
+            TokenSortBy::IssueTime => index_page(
+                &self.tokens_by_issue_time,
+                cursor.map(|(t, position)| (t.timestamp, position)),
+                direction,
+                page_size,
+            ),
+            TokenSortBy::Name => index_page(
+                &self.tokens_by_name,
+                cursor.map(|(t, position)| (t.name.to_lowercase(), position)),
+                direction,
+                page_size,
+            ),
+            TokenSortBy::Symbol => index_page(
+                &self.tokens_by_symbol,
+                cursor.map(|(t, position)| (t.symbol.to_lowercase(), position)),
+                direction,
+                page_size,
+            ),
+        };
+        let tokens: Vec<TokenInfo> = positions
+            .iter()
+            .map(|position| self.tokens[&self.token_ids[*position]].clone())
+            .collect();
+        let next_cursor = if has_more {
+            tokens.last().map(|t| t.token_id)
+        } else {
+            None
+        };
+        Ok(TokenPage {
+            tokens,
+            total: self.token_ids.len() as u64,
+            next_cursor,
+        })
     }
 
-    // get ToolStatus
-    pub fn get_status(&self) -> ToolStatus {
-        ToolStatus {
-            owner: self.owner.clone(),
-            cycles_per_token: self.cycles_per_token,
-            cycles: 0,
-            issued_token_count: self.get_token_count().unwrap(),
-        }
+    // add token with the checks of an issuance, issued tokens are registered by register_issuance
+    #[cfg(test)]
+    pub fn add_token(&mut self, caller: &Principal, token_info: TokenInfo) -> CommonResult<()> {
+        self.only_issuer(caller)?;
+        self.check_symbol(caller, &token_info.symbol, Some(&token_info.token_id), token_info.timestamp)?;
+        // the reservation is used up by the issuance
+        self.symbol_reservations.remove(&fold_symbol(&token_info.symbol));
+        self.register_token(token_info);
+        Ok(())
     }
 
-    // load from ToolPayload
-    pub fn load_from_payload(&mut self, payload: ToolPayload) {
-        self.owner = payload.owner;
-        self.cycles_per_token = payload.cycles_per_token;
-        self.token_wasm = payload.token_wasm;
-        self.tokens = payload.tokens.into_iter().map(|(k, v)| (k, v)).collect();
+    // add a token checked before it was installed
+    fn register_token(&mut self, token_info: TokenInfo) {
+        self.insert_token(token_info.clone());
+        self.persist(StableRecord::Token(token_info));
     }
-}
 
-//  IssuanceTool tests
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use candid::Nat;
-    use ic_types::Principal;
+    // check if the caller can issue a token with the symbol,
+    // token_id is the token being updated, which does not conflict with itself
+    pub fn check_symbol(
+        &self,
+        caller: &Principal,
+        symbol: &str,
+        token_id: Option<&Principal>,
+        now: u64,
+    ) -> CommonResult<()> {
+        let folded = fold_symbol(symbol);
+        if self.blocked_symbols.contains(&folded) && !self.has_role(caller, Role::Admin) {
+            return Err(ToolError::SymbolBlocked {
+                symbol: symbol.to_string(),
+            });
+        }
+        if let Some(reservation) = self.symbol_reservations.get(&folded) {
+            if reservation.expires_at > now && &reservation.holder != caller {
+                return Err(ToolError::SymbolReserved {
+                    symbol: symbol.to_string(),
+                });
+            }
+        }
+        if self.unique_symbols {
+            let used = self
+                .symbol_tokens
+                .get(&folded)
+                .map_or(false, |ids| ids.iter().any(|id| Some(id) != token_id))
+                || self.unregistered_issuances().any(|i| {
+                    fold_symbol(&i.args.symbol) == folded
+                        && (token_id.is_none() || i.canister_id.as_ref() != token_id)
+                });
+            if used {
+                return Err(ToolError::SymbolAlreadyExists {
+                    symbol: symbol.to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
 
-    // test get/set owner
-    #[test]
-    fn test_owner() {
-        let mut tool = IssuanceTool::new();
-        let owner =
-            Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe")
-                .unwrap();
+    // enable or disable the symbol uniqueness policy
+    pub fn set_unique_symbols(&mut self, caller: &Principal, enabled: bool) -> CommonResult<bool> {
+        self.only_role(caller, Role::Admin)?;
+        self.unique_symbols = enabled;
+        Ok(true)
+    }
 
-        // set owner by call anonymous
-        let result = tool.set_owner(&Principal::anonymous(), owner.clone());
-        // check result is err
-        assert!(result.is_err());
-        // check error code is NotAllowAnonymous
-        assert_eq!(result.unwrap_err(), ToolError::NotAllowAnonymous);
-        // check owner is anonymous
-        assert_eq!(tool.owner(), Principal::anonymous());
+    // block symbols, so only the owner and admins can issue them
+    pub fn block_symbols(&mut self, caller: &Principal, symbols: Vec<String>) -> CommonResult<bool> {
+        self.only_role(caller, Role::Moderator)?;
+        self.blocked_symbols
+            .extend(symbols.iter().map(|s| fold_symbol(s)));
+        Ok(true)
+    }
 
-        // set owner by owner
-        let result = tool.set_owner(&owner, owner.clone());
-        // check result is ok
-        assert!(result.is_ok());
-        // check owner is owner
-        assert_eq!(tool.owner(), owner);
-        let new_owner =
-            Principal::from_text("czjfo-ddpvm-6sibl-6zbox-ee5zq-bx3hc-e336t-s6pka-dupmy-wcxqi-fae")
-                .unwrap();
-        // set owner to new_owner
-        let result = tool.set_owner(&new_owner, new_owner.clone());
-        // check result is error
-        assert!(result.is_err());
-        // the error code is OnlyOwnerAllowCallIt
-        assert_eq!(result.unwrap_err(), ToolError::OnlyOwnerAllowCallIt);
+    // unblock symbols
+    pub fn unblock_symbols(&mut self, caller: &Principal, symbols: Vec<String>) -> CommonResult<bool> {
+        self.only_role(caller, Role::Moderator)?;
+        for symbol in symbols {
+            self.blocked_symbols.remove(&fold_symbol(&symbol));
+        }
+        Ok(true)
+    }
 
-        // set owner to new_owner
-        let result = tool.set_owner(&owner, new_owner.clone());
-        // check result is ok
-        assert!(result.is_ok());
-        // check owner is new_owner
-        assert_eq!(tool.owner(), new_owner);
+    // get blocked symbols
+    pub fn get_blocked_symbols(&self) -> Vec<String> {
+        self.blocked_symbols.iter().cloned().collect()
+    }
 
-        // check default value of cycles_per_token
-        assert_eq!(tool.cycles_per_token(), 3000_000_000_000);
+    // set how long a symbol reservation is held, in nanoseconds
+    pub fn set_symbol_reservation_ttl(&mut self, caller: &Principal, ttl: u64) -> CommonResult<bool> {
+        self.only_role(caller, Role::Admin)?;
+        self.symbol_reservation_ttl = ttl;
+        Ok(true)
     }
 
-    // test set cycles per token
-    #[test]
-    fn test_cycles_per_token() {
+    // reserve a symbol for the caller, renewing it if the caller already holds it
+    pub fn reserve_symbol(
+        &mut self,
+        caller: &Principal,
+        symbol: &str,
+        now: u64,
+    ) -> CommonResult<SymbolReservation> {
+        self.only_issuer(caller)?;
+        // a reservation of a symbol no token can use would never be released by an issuance
+        validate_symbol(symbol)?;
+        self.check_symbol(caller, symbol, None, now)?;
+        let folded = fold_symbol(symbol);
+        // drop expired reservations
+        self.symbol_reservations.retain(|_, r| r.expires_at > now);
+        let held = self
+            .symbol_reservations
+            .iter()
+            .filter(|(s, r)| &r.holder == caller && *s != &folded)
+            .count();
+        if held >= MAX_SYMBOL_RESERVATIONS {
+            return Err(ToolError::TooManySymbolReservations {
+                max: MAX_SYMBOL_RESERVATIONS as u64,
+            });
+        }
+        let reservation = SymbolReservation {
+            symbol: symbol.to_string(),
+            holder: *caller,
+            expires_at: now + self.symbol_reservation_ttl,
+        };
+        self.symbol_reservations.insert(folded, reservation.clone());
+        Ok(reservation)
+    }
+
+    // release a symbol reserved by the caller
+    pub fn release_symbol(&mut self, caller: &Principal, symbol: &str) -> CommonResult<()> {
+        let folded = fold_symbol(symbol);
+        match self.symbol_reservations.get(&folded) {
+            Some(reservation) if &reservation.holder == caller => {
+                self.symbol_reservations.remove(&folded);
+                Ok(())
+            }
+            _ => Err(ToolError::SymbolReservationNotFound),
+        }
+    }
+
+    // get the active reservation of the symbol
+    pub fn get_symbol_reservation(&self, symbol: &str, now: u64) -> Option<SymbolReservation> {
+        self.symbol_reservations
+            .get(&fold_symbol(symbol))
+            .filter(|r| r.expires_at > now)
+            .cloned()
+    }
+
+    // insert the token and keep the indexes in sync
+    fn insert_token(&mut self, token_info: TokenInfo) {
+        let position = match self.tokens.get(&token_info.token_id) {
+            Some(old) => {
+                let old = old.clone();
+                let position = self.token_positions[&token_info.token_id];
+                if old.issuer != token_info.issuer {
+                    if let Some(ids) = self.issuer_tokens.get_mut(&old.issuer) {
+                        ids.retain(|id| id != &token_info.token_id);
+                    }
+                    self.issuer_tokens
+                        .entry(token_info.issuer)
+                        .or_default()
+                        .push(token_info.token_id);
+                }
+                self.unindex_search_terms(&old, position);
+                self.tokens_by_issue_time.remove(&(old.timestamp, position));
+                self.tokens_by_name.remove(&(old.name.to_lowercase(), position));
+                self.tokens_by_symbol.remove(&(old.symbol.to_lowercase(), position));
+                if let Some(ids) = self.symbol_tokens.get_mut(&fold_symbol(&old.symbol)) {
+                    ids.remove(&old.token_id);
+                }
+                position
+            }
+            None => {
+                self.token_ids.push(token_info.token_id);
+                self.token_positions
+                    .insert(token_info.token_id, self.token_ids.len() - 1);
+                self.issuer_tokens
+                    .entry(token_info.issuer)
+                    .or_default()
+                    .push(token_info.token_id);
+                self.token_ids.len() - 1
+            }
+        };
+        for suffix in search_suffixes(&token_info) {
+            self.search_index.entry(suffix).or_default().insert(position);
+        }
+        self.tokens_by_issue_time.insert((token_info.timestamp, position));
+        self.tokens_by_name
+            .insert((token_info.name.to_lowercase(), position));
+        self.tokens_by_symbol
+            .insert((token_info.symbol.to_lowercase(), position));
+        self.symbol_tokens
+            .entry(fold_symbol(&token_info.symbol))
+            .or_default()
+            .insert(token_info.token_id);
+        self.certified_tokens
+            .insert(token_info.token_id.as_slice().to_vec(), token_hash(&token_info));
+        self.tokens.insert(token_info.token_id, token_info);
+    }
+
+    fn unindex_search_terms(&mut self, token_info: &TokenInfo, position: usize) {
+        for suffix in search_suffixes(token_info) {
+            if let Some(positions) = self.search_index.get_mut(&suffix) {
+                positions.remove(&position);
+                if positions.is_empty() {
+                    self.search_index.remove(&suffix);
+                }
+            }
+        }
+    }
+
+    // search tokens by name or symbol, case insensitive,
+    // prefix matches come first, then substring matches, each in insertion order
+    pub fn search_tokens(
+        &self,
+        query: &str,
+        start_index: usize,
+        page_size: usize,
+    ) -> CommonResult<Vec<TokenInfo>> {
+        // max page size is 200
+        let page_size = if page_size > 200 { 200 } else { page_size };
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut positions = BTreeSet::new();
+        for (_, matched) in self
+            .search_index
+            .range(query.clone()..)
+            .take_while(|(suffix, _)| suffix.starts_with(&query))
+        {
+            positions.extend(matched.iter().copied());
+        }
+        let (prefix_matches, substring_matches): (Vec<&TokenInfo>, Vec<&TokenInfo>) = positions
+            .iter()
+            .map(|position| &self.tokens[&self.token_ids[*position]])
+            .partition(|t| {
+                t.name.to_lowercase().starts_with(&query) || t.symbol.to_lowercase().starts_with(&query)
+            });
+        Ok(prefix_matches
+            .into_iter()
+            .chain(substring_matches)
+            .skip(start_index)
+            .take(page_size)
+            .cloned()
+            .collect())
+    }
+
+    // get tokens of the issuer with page parameters
+    pub fn get_tokens_of_issuer(
+        &self,
+        issuer: &Principal,
+        start_index: usize,
+        page_size: usize,
+    ) -> CommonResult<Vec<TokenInfo>> {
+        // max page size is 200
+        let page_size = if page_size > 200 { 200 } else { page_size };
+        let token_list = match self.issuer_tokens.get(issuer) {
+            Some(ids) => ids
+                .iter()
+                .skip(start_index)
+                .take(page_size)
+                .map(|token_id| self.tokens[token_id].clone())
+                .collect(),
+            None => Vec::new(),
+        };
+        Ok(token_list)
+    }
+
+    // check if the caller can upgrade the token, the issuer or a controller of the token
+    pub fn only_token_upgrader(
+        &self,
+        caller: &Principal,
+        token_id: &Principal,
+        controllers: &[Principal],
+    ) -> CommonResult<()> {
+        self.not_allow_anonymous(caller)?;
+        let token = self.get_token_by_id(token_id)?;
+        if token.immutable {
+            return Err(ToolError::TokenIsImmutable);
+        }
+        if &token.issuer != caller && !controllers.contains(caller) {
+            return Err(ToolError::CallerIsNotControllerOfToken);
+        }
+        Ok(())
+    }
+
+    // check the token is not upgraded to the module yet, returns false if the module is installed but the upgrade
+    // is not recorded, as the token was not handed back, so it is only handed back and recorded
+    pub fn check_token_upgrade(
+        &self,
+        token_id: &Principal,
+        installed_module_hash: Option<&Vec<u8>>,
+        new_module_hash: &[u8],
+    ) -> CommonResult<bool> {
+        let token = self.get_token_by_id(token_id)?;
+        if installed_module_hash.map(|hash| hash.as_slice()) != Some(new_module_hash) {
+            return Ok(true);
+        }
+        if token.module_hash.as_deref() == Some(new_module_hash) {
+            return Err(ToolError::TokenAlreadyUpToDate);
+        }
+        Ok(false)
+    }
+
+    // start an issuance, the token is installed to canister_id, or to a canister created by the tool,
+    // the token is handed over to the controllers in the args settings, or to the default controllers
+    pub fn begin_issuance(
+        &mut self,
+        issuer: &Principal,
+        canister_id: Option<Principal>,
+        args: CreateTokenArgs,
+        default_controllers: Vec<Principal>,
+        order_id: Option<u64>,
+        now: u64,
+    ) -> CommonResult<Issuance> {
+        // everything checked at registration is checked before the canister is paid for,
+        // the issuance holds the quota and the symbol until it is registered
+        self.only_issuer(issuer)?;
+        self.check_token_settings(&args)?;
+        self.check_symbol(issuer, &args.symbol, None, now)?;
+        self.symbol_reservations.remove(&fold_symbol(&args.symbol));
+        let immutable = args.settings.as_ref().and_then(|s| s.immutable) == Some(true);
+        let blackhole_canister_id = if immutable { self.blackhole_canister_id } else { None };
+        let controllers = if immutable {
+            blackhole_canister_id.into_iter().collect()
+        } else {
+            args.settings
+                .as_ref()
+                .and_then(|s| s.controllers.clone())
+                .unwrap_or(default_controllers)
+        };
+        let issuance = Issuance {
+            issuance_id: self.next_issuance_id,
+            issuer: *issuer,
+            canister_id,
+            args,
+            controllers,
+            immutable,
+            blackhole_canister_id,
+            module_hash: None,
+            order_id,
+            step: IssuanceStep::Pending,
+            error: None,
+            created_at: now,
+            updated_at: now,
+        };
+        self.issuances.insert(issuance.issuance_id, issuance.clone());
+        self.next_issuance_id += 1;
+        self.persist(StableRecord::Issuance(issuance.clone()));
+        Ok(issuance)
+    }
+
+    // check the settings can be applied by the tool
+    pub fn check_token_settings(&self, args: &CreateTokenArgs) -> CommonResult<()> {
+        let immutable = args.settings.as_ref().and_then(|s| s.immutable) == Some(true);
+        // without controllers the status of the token, and so its module hash, can't be read to verify it
+        if immutable && self.blackhole_canister_id.is_none() {
+            return Err(ToolError::InvalidTokenSettings {
+                reason: "immutable tokens require a blackhole canister".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    // issuances started but not added to the registry yet
+    fn unregistered_issuances(&self) -> impl Iterator<Item = &Issuance> {
+        self.issuances
+            .values()
+            .filter(|i| matches!(i.step, IssuanceStep::Pending | IssuanceStep::Installed))
+    }
+
+    pub fn get_issuance(&self, issuance_id: u64) -> CommonResult<Issuance> {
+        self.issuances
+            .get(&issuance_id)
+            .cloned()
+            .ok_or(ToolError::IssuanceNotFound)
+    }
+
+    // check if the caller can resume the issuance, the owner or the issuer
+    pub fn only_issuance_operator(&self, caller: &Principal, issuance_id: u64) -> CommonResult<Issuance> {
+        let issuance = self.get_issuance(issuance_id)?;
+        if caller != &issuance.issuer && !self.has_role(caller, Role::Admin) {
+            return Err(ToolError::OnlyOwnerAllowCallIt);
+        }
+        if issuance.step == IssuanceStep::Completed {
+            return Err(ToolError::IssuanceAlreadyCompleted);
+        }
+        Ok(issuance)
+    }
+
+    // record the canister created for the issuance
+    pub fn set_issuance_canister(&mut self, issuance_id: u64, canister_id: Principal, now: u64) -> CommonResult<()> {
+        let issuance = self
+            .issuances
+            .get_mut(&issuance_id)
+            .ok_or(ToolError::IssuanceNotFound)?;
+        issuance.canister_id = Some(canister_id);
+        issuance.updated_at = now;
+        self.persist_issuance(issuance_id);
+        Ok(())
+    }
+
+    // record the module installed for the issuance
+    pub fn set_issuance_installed(&mut self, issuance_id: u64, module_hash: Vec<u8>, now: u64) -> CommonResult<()> {
+        let issuance = self
+            .issuances
+            .get_mut(&issuance_id)
+            .ok_or(ToolError::IssuanceNotFound)?;
+        issuance.module_hash = Some(module_hash);
+        self.set_issuance_step(issuance_id, IssuanceStep::Installed, now)
+    }
+
+    // move the issuance to the step, the error of the last attempt is cleared
+    pub fn set_issuance_step(&mut self, issuance_id: u64, step: IssuanceStep, now: u64) -> CommonResult<()> {
+        let issuance = self
+            .issuances
+            .get_mut(&issuance_id)
+            .ok_or(ToolError::IssuanceNotFound)?;
+        issuance.step = step;
+        issuance.error = None;
+        issuance.updated_at = now;
+        if step == IssuanceStep::Completed {
+            if let (Some(order_id), Some(token_id)) = (issuance.order_id, issuance.canister_id) {
+                self.set_order_status(order_id, OrderStatus::Issued { token_id })?;
+            }
+        }
+        self.persist_issuance(issuance_id);
+        Ok(())
+    }
+
+    // record the error of the last attempt, the issuance stays at its step
+    pub fn set_issuance_error(&mut self, issuance_id: u64, error: &ToolError, now: u64) -> CommonResult<()> {
+        let issuance = self
+            .issuances
+            .get_mut(&issuance_id)
+            .ok_or(ToolError::IssuanceNotFound)?;
+        issuance.error = Some(error.to_string());
+        issuance.updated_at = now;
+        if let Some(order_id) = issuance.order_id {
+            self.set_order_error(order_id, error)?;
+        }
+        self.persist_issuance(issuance_id);
+        Ok(())
+    }
+
+    // add the installed token of the issuance to the registry
+    pub fn register_issuance(&mut self, issuance_id: u64, now: u64) -> CommonResult<()> {
+        let issuance = self.get_issuance(issuance_id)?;
+        let canister_id = issuance.canister_id.ok_or(ToolError::IssuanceNotFound)?;
+        let args = issuance.args;
+        let token_info = TokenInfo {
+            issuer: issuance.issuer,
+            token_id: canister_id,
+            name: args.name.clone(),
+            symbol: args.symbol.clone(),
+            decimals: args.decimals,
+            total_supply: args.total_supply,
+            fee: args.fee.clone(),
+            template: args.template(),
+            controllers: issuance.controllers,
+            immutable: issuance.immutable,
+            blackhole_canister_id: issuance.blackhole_canister_id,
+            module_hash: issuance.module_hash,
+            timestamp: now,
+        };
+        self.register_token(token_info);
+        if let Some(logo) = args.logo {
+            self.set_token_logo(&canister_id, logo)?;
+        }
+        self.set_issuance_step(issuance_id, IssuanceStep::Registered, now)
+    }
+
+    // issuances not completed yet, for the owner to retry
+    pub fn get_stuck_issuances(&self, caller: &Principal) -> CommonResult<Vec<Issuance>> {
+        self.only_role(caller, Role::Admin)?;
+        Ok(self
+            .issuances
+            .values()
+            .filter(|i| i.step != IssuanceStep::Completed)
+            .cloned()
+            .collect())
+    }
+
+    // store the logo of an issued token
+    pub fn set_token_logo(&mut self, token_id: &Principal, logo: Vec<u8>) -> CommonResult<()> {
+        self.get_token_by_id(token_id)?;
+        validate_logo(&logo)?;
+        self.logos.insert(*token_id, logo.clone());
+        self.persist(StableRecord::Logo(*token_id, ByteBuf::from(logo)));
+        Ok(())
+    }
+
+    // get the logo of the token, with the sniffed content type
+    pub fn get_token_logo(&self, token_id: &Principal) -> Option<TokenLogo> {
+        let logo = self.logos.get(token_id)?;
+        Some(TokenLogo {
+            content_type: logo_content_type(logo)?.to_string(),
+            data: serde_bytes::ByteBuf::from(logo.clone()),
+        })
+    }
+
+    // record an upgrade of the token, the token info keeps the hash of the new module
+    pub fn add_token_upgrade(&mut self, token_id: &Principal, upgrade: TokenUpgrade) -> CommonResult<()> {
+        let mut token = self.get_token_by_id(token_id)?;
+        token.module_hash = Some(upgrade.new_module_hash.clone());
+        self.insert_token(token.clone());
+        self.token_upgrades.entry(*token_id).or_default().push(upgrade.clone());
+        self.persist(StableRecord::Token(token));
+        self.persist(StableRecord::TokenUpgrade(*token_id, upgrade));
+        Ok(())
+    }
+
+    // set the blackhole canister immutable tokens are handed over to, none to disable immutable issuance
+    pub fn set_blackhole_canister(&mut self, caller: &Principal, canister_id: Option<Principal>) -> CommonResult<bool> {
+        self.only_privileged(caller, Role::Admin)?;
+        self.blackhole_canister_id = canister_id;
+        Ok(true)
+    }
+
+    // the canister to read the status of the token from, the blackhole the token was handed over to,
+    // or the management canister while the tool is a controller, other tokens can't be verified by the tool
+    pub fn get_status_canister(&self, token: &TokenInfo, tool_id: &Principal) -> CommonResult<Principal> {
+        match token.blackhole_canister_id {
+            Some(blackhole) => Ok(blackhole),
+            None if token.controllers.contains(tool_id) => Ok(Principal::management_canister()),
+            None => Err(ToolError::TokenNotVerifiable),
+        }
+    }
+
+    // compare the current status of the token to the state recorded when it was issued
+    pub fn verify_token(&self, token_id: &Principal, status: TokenCanisterStatus) -> CommonResult<TokenVerification> {
+        let token = self.get_token_by_id(token_id)?;
+        let recorded: BTreeSet<&Principal> = token.controllers.iter().collect();
+        let current: BTreeSet<&Principal> = status.settings.controllers.iter().collect();
+        let controllers_match = recorded == current;
+        let module_hash_match = token.module_hash.is_some() && token.module_hash == status.module_hash;
+        Ok(TokenVerification {
+            token_id: *token_id,
+            immutable: token.immutable,
+            controllers: status.settings.controllers.clone(),
+            module_hash: status.module_hash,
+            controllers_match,
+            module_hash_match,
+            verified: controllers_match && module_hash_match,
+        })
+    }
+
+    // get upgrade history of the token
+    pub fn get_token_upgrades(&self, token_id: &Principal) -> CommonResult<Vec<TokenUpgrade>> {
+        self.get_token_by_id(token_id)?;
+        Ok(self.token_upgrades.get(token_id).cloned().unwrap_or_default())
+    }
+
+    // get the latest token wasm of the template
+    pub fn get_token_wasm(&self, template: &str) -> CommonResult<TokenWasm> {
+        self.token_wasms
+            .values()
+            .filter(|w| w.template == template)
+            .max_by_key(|w| w.timestamp)
+            .cloned()
+            .ok_or_else(|| ToolError::TokenTemplateNotFound {
+                template: template.to_string(),
+            })
+    }
+
+    // get the module to install for the template, gzip compressed if the management canister accepts it
+    pub fn get_install_module(&self, template: &str) -> CommonResult<Vec<u8>> {
+        let token_wasm = self.get_token_wasm(template)?;
+        if self.install_compressed_wasm {
+            Ok(token_wasm.wasm_module)
+        } else {
+            decompress(&token_wasm.wasm_module)
+        }
+    }
+
+    // install token wasm gzip compressed or decompressed
+    pub fn set_install_compressed_wasm(&mut self, caller: &Principal, enabled: bool) -> CommonResult<bool> {
+        self.only_privileged(caller, Role::Admin)?;
+        self.install_compressed_wasm = enabled;
+        Ok(true)
+    }
+
+    // get versions of the token wasm registry, ordered by template and upload time
+    pub fn get_token_wasm_versions(&self, template: Option<String>) -> Vec<TokenWasmInfo> {
+        let mut versions: Vec<TokenWasmInfo> = self
+            .token_wasms
+            .values()
+            .filter(|w| template.as_ref().map_or(true, |t| &w.template == t))
+            .map(TokenWasmInfo::from)
+            .collect();
+        versions.sort_by(|a, b| {
+            a.template
+                .cmp(&b.template)
+                .then(a.timestamp.cmp(&b.timestamp))
+        });
+        versions
+    }
+
+    // add a token wasm to the registry
+    pub fn set_token_wasm(
+        &mut self,
+        caller: &Principal,
+        args: StoreWASMArgs,
+        now: u64,
+    ) -> CommonResult<TokenWasmInfo> {
+        self.only_privileged(caller, Role::WasmPublisher)?;
+        self.store_token_wasm(caller, args, now)
+    }
+
+    fn store_token_wasm(&mut self, uploader: &Principal, args: StoreWASMArgs, now: u64) -> CommonResult<TokenWasmInfo> {
+        if args.template.is_empty() || args.version.is_empty() {
+            return Err(ToolError::InvalidTokenWasmModule);
+        }
+        let module = validate_token_wasm(&args.wasm_module)?;
+        let hash = Sha256::digest(&module).to_vec();
+        if self.token_wasms.contains_key(&hash) {
+            return Err(ToolError::TokenWasmAlreadyExists);
+        }
+        if self
+            .token_wasms
+            .values()
+            .any(|w| w.template == args.template && w.version == args.version)
+        {
+            return Err(ToolError::TokenWasmVersionAlreadyExists {
+                template: args.template,
+                version: args.version,
+            });
+        }
+        let token_wasm = TokenWasm {
+            template: args.template,
+            version: args.version,
+            hash: hash.clone(),
+            uploader: *uploader,
+            release_notes: args.release_notes,
+            timestamp: now,
+            size: module.len() as u64,
+            // keep the uploaded gzip as is, so it can be installed with the same module hash
+            wasm_module: if is_gzip(&args.wasm_module) {
+                args.wasm_module
+            } else {
+                compress(&module)
+            },
+        };
+        let info = TokenWasmInfo::from(&token_wasm);
+        self.token_wasms.insert(hash, token_wasm.clone());
+        self.persist(StableRecord::TokenWasm(token_wasm));
+        Ok(info)
+    }
+
+    // begin a chunked token wasm upload, returns the upload id
+    pub fn begin_wasm_upload(
+        &mut self,
+        caller: &Principal,
+        args: BeginWasmUploadArgs,
+        now: u64,
+    ) -> CommonResult<u64> {
+        self.only_wasm_stager(caller)?;
+        if self.wasm_uploads.len() >= MAX_WASM_UPLOADS {
+            return Err(ToolError::TooManyWasmUploads {
+                limit: MAX_WASM_UPLOADS as u64,
+            });
+        }
+        let upload_id = self.next_upload_id;
+        self.wasm_uploads.insert(
+            upload_id,
+            WasmUpload {
+                uploader: *caller,
+                template: args.template,
+                version: args.version,
+                release_notes: args.release_notes,
+                wasm_module: Vec::new(),
+                timestamp: now,
+            },
+        );
+        self.next_upload_id += 1;
+        Ok(upload_id)
+    }
+
+    // append a chunk to the upload, returns the uploaded size
+    pub fn append_wasm_chunk(
+        &mut self,
+        caller: &Principal,
+        upload_id: u64,
+        chunk: Vec<u8>,
+    ) -> CommonResult<u64> {
+        self.only_uploader(caller, upload_id)?;
+        let staged_size = self.wasm_uploads.values().map(|u| u.wasm_module.len()).sum::<usize>() + chunk.len();
+        if staged_size > MAX_STAGED_WASM_SIZE {
+            return Err(ToolError::StagedWasmTooLarge {
+                size: staged_size as u64,
+                limit: MAX_STAGED_WASM_SIZE as u64,
+            });
+        }
+        let upload = self.wasm_uploads.get_mut(&upload_id).unwrap();
+        let size = upload.wasm_module.len() + chunk.len();
+        if size > MAX_TOKEN_WASM_SIZE {
+            return Err(ToolError::TokenWasmTooLarge {
+                size: size as u64,
+                limit: MAX_TOKEN_WASM_SIZE as u64,
+            });
+        }
+        upload.wasm_module.extend(chunk);
+        Ok(size as u64)
+    }
+
+    // move the upload into the registry if its sha256 is the expected hash
+    pub fn commit_wasm_upload(
+        &mut self,
+        caller: &Principal,
+        upload_id: u64,
+        expected_hash: Vec<u8>,
+        now: u64,
+    ) -> CommonResult<TokenWasmInfo> {
+        self.only_privileged(caller, Role::WasmPublisher)?;
+        self.only_uploader(caller, upload_id)?;
+        self.commit_upload(upload_id, expected_hash, now)
+    }
+
+    fn commit_upload(&mut self, upload_id: u64, expected_hash: Vec<u8>, now: u64) -> CommonResult<TokenWasmInfo> {
+        let (uploader, args) = self.take_upload(upload_id, expected_hash)?;
+        self.store_token_wasm(&uploader, args, now)
+    }
+
+    // remove the upload from the staging area if its sha256 is the expected hash
+    fn take_upload(&mut self, upload_id: u64, expected_hash: Vec<u8>) -> CommonResult<(Principal, StoreWASMArgs)> {
+        let upload = self
+            .wasm_uploads
+            .remove(&upload_id)
+            .ok_or(ToolError::WasmUploadNotFound)?;
+        let actual = Sha256::digest(&upload.wasm_module).to_vec();
+        if actual != expected_hash {
+            return Err(ToolError::TokenWasmHashMismatch {
+                expected: expected_hash,
+                actual,
+            });
+        }
+        let args = StoreWASMArgs {
+            template: upload.template,
+            version: upload.version,
+            release_notes: upload.release_notes,
+            wasm_module: upload.wasm_module,
+        };
+        Ok((upload.uploader, args))
+    }
+
+    // id of the staged upload with the sha256
+    fn staged_upload_id(&self, wasm_hash: &[u8]) -> CommonResult<u64> {
+        self.wasm_uploads
+            .iter()
+            .find(|(_, upload)| Sha256::digest(&upload.wasm_module)[..] == *wasm_hash)
+            .map(|(upload_id, _)| *upload_id)
+            .ok_or(ToolError::WasmUploadNotFound)
+    }
+
+    // wasm publishers and the signers of the multisig can stage uploads
+    fn only_wasm_stager(&self, caller: &Principal) -> CommonResult<()> {
+        if self.multisig.as_ref().map_or(false, |config| config.signers.contains(caller)) {
+            return Ok(());
+        }
+        self.only_role(caller, Role::WasmPublisher)
+    }
+
+    // the stager who began the upload can write to it, commit or abort it
+    fn only_uploader(&self, caller: &Principal, upload_id: u64) -> CommonResult<()> {
+        self.only_wasm_stager(caller)?;
+        let upload = self
+            .wasm_uploads
+            .get(&upload_id)
+            .ok_or(ToolError::WasmUploadNotFound)?;
+        if &upload.uploader != caller {
+            return Err(ToolError::NotUploader);
+        }
+        Ok(())
+    }
+
+    // get the uploads in the staging area
+    pub fn get_wasm_uploads(&self) -> Vec<WasmUploadInfo> {
+        let mut uploads: Vec<WasmUploadInfo> = self
+            .wasm_uploads
+            .iter()
+            .map(|(upload_id, upload)| WasmUploadInfo {
+                upload_id: *upload_id,
+                uploader: upload.uploader,
+                template: upload.template.clone(),
+                version: upload.version.clone(),
+                size: upload.wasm_module.len() as u64,
+                timestamp: upload.timestamp,
+            })
+            .collect();
+        uploads.sort_by_key(|u| u.upload_id);
+        uploads
+    }
+
+    // drop the upload from the staging area
+    pub fn abort_wasm_upload(&mut self, caller: &Principal, upload_id: u64) -> CommonResult<()> {
+        self.only_uploader(caller, upload_id)?;
+        self.wasm_uploads.remove(&upload_id);
+        Ok(())
+    }
+
+    // enable or disable the multisig, once enabled it can only be changed by a proposal
+    pub fn set_multisig(&mut self, caller: &Principal, config: Option<MultisigConfig>) -> CommonResult<bool> {
+        self.only_owner(caller)?;
+        if self.multisig.is_some() {
+            return Err(ToolError::MultisigApprovalRequired);
+        }
+        if let Some(config) = &config {
+            validate_multisig_config(config)?;
+        }
+        self.multisig = config;
+        Ok(true)
+    }
+
+    pub fn get_multisig(&self) -> Option<MultisigConfig> {
+        self.multisig.clone()
+    }
+
+    fn only_signer(&self, caller: &Principal) -> CommonResult<&MultisigConfig> {
+        self.not_allow_anonymous(caller)?;
+        match &self.multisig {
+            Some(config) if config.signers.contains(caller) => Ok(config),
+            _ => Err(ToolError::OnlySignerAllowCallIt),
+        }
+    }
+
+    // propose a privileged operation, the proposer approves it and it executes once the threshold is met
+    pub fn propose(&mut self, caller: &Principal, action: ProposalAction, now: u64) -> CommonResult<Proposal> {
+        let config = self.only_signer(caller)?;
+        let ttl = config.proposal_ttl.unwrap_or(DEFAULT_PROPOSAL_TTL);
+        match &action {
+            ProposalAction::SetTokenWasm { wasm_hash } => {
+                let upload_id = self.staged_upload_id(wasm_hash)?;
+                validate_token_wasm(&self.wasm_uploads[&upload_id].wasm_module)?;
+            }
+            ProposalAction::SetMultisig(Some(config)) => validate_multisig_config(config)?,
+            _ => {}
+        }
+        let action_hash = Sha256::digest(&Encode!(&action).expect("Failed to encode proposal action.")).to_vec();
+        let proposal_id = self.next_proposal_id;
+        self.next_proposal_id += 1;
+        self.proposals.insert(
+            proposal_id,
+            Proposal {
+                proposal_id,
+                proposer: *caller,
+                action,
+                action_hash: action_hash.clone(),
+                approvals: Vec::new(),
+                status: ProposalStatus::Open,
+                created_at: now,
+                expires_at: now + ttl,
+                closed_at: None,
+            },
+        );
+        self.record_event(*caller, ToolEvent::ProposalCreated { proposal_id, action_hash }, now);
+        self.approve_proposal(caller, proposal_id, now)
+    }
+
+    // approve the proposal, it executes when the approvals of the current signers meet the threshold
+    pub fn approve_proposal(&mut self, caller: &Principal, proposal_id: u64, now: u64) -> CommonResult<Proposal> {
+        let config = self.only_signer(caller)?.clone();
+        let proposal = self.open_proposal(proposal_id, now)?;
+        if proposal.approvals.iter().any(|a| &a.signer == caller) {
+            return Err(ToolError::ProposalAlreadyApproved);
+        }
+        proposal.approvals.push(ProposalApproval {
+            signer: *caller,
+            approved_at: now,
+        });
+        let approved = proposal
+            .approvals
+            .iter()
+            .filter(|a| config.signers.contains(&a.signer))
+            .count() as u64;
+        let action = proposal.action.clone();
+        let proposer = proposal.proposer;
+        self.persist_proposal(proposal_id);
+        self.record_event(*caller, ToolEvent::ProposalApproved { proposal_id }, now);
+        if approved >= config.threshold {
+            let status = match self.execute_action(&proposer, action, now) {
+                Ok(event) => {
+                    self.record_event(*caller, event, now);
+                    self.record_event(*caller, ToolEvent::ProposalExecuted { proposal_id }, now);
+                    ProposalStatus::Executed
+                }
+                Err(e) => {
+                    let error = e.to_string();
+                    self.record_event(
+                        *caller,
+                        ToolEvent::ProposalFailed {
+                            proposal_id,
+                            error: error.clone(),
+                        },
+                        now,
+                    );
+                    ProposalStatus::Failed { error }
+                }
+            };
+            self.close_proposal(proposal_id, status, now);
+        }
+        self.get_proposal(proposal_id, now)
+    }
+
+    // cancel the proposal, only by its proposer
+    pub fn cancel_proposal(&mut self, caller: &Principal, proposal_id: u64, now: u64) -> CommonResult<()> {
+        self.not_allow_anonymous(caller)?;
+        let proposal = self.open_proposal(proposal_id, now)?;
+        if &proposal.proposer != caller {
+            return Err(ToolError::OnlyProposerAllowCallIt);
+        }
+        self.close_proposal(proposal_id, ProposalStatus::Cancelled, now);
+        self.record_event(*caller, ToolEvent::ProposalCancelled { proposal_id }, now);
+        Ok(())
+    }
+
+    // get the proposal, an open proposal past its expiry is reported as expired
+    pub fn get_proposal(&self, proposal_id: u64, now: u64) -> CommonResult<Proposal> {
+        let mut proposal = self
+            .proposals
+            .get(&proposal_id)
+            .cloned()
+            .ok_or(ToolError::ProposalNotFound)?;
+        if proposal.status == ProposalStatus::Open && now >= proposal.expires_at {
+            proposal.status = ProposalStatus::Expired;
+        }
+        Ok(proposal)
+    }
+
+    // get the open proposals, ordered by id
+    pub fn get_open_proposals(&self, now: u64) -> Vec<Proposal> {
+        self.proposals
+            .values()
+            .filter(|p| p.status == ProposalStatus::Open && now < p.expires_at)
+            .cloned()
+            .collect()
+    }
+
+    fn open_proposal(&mut self, proposal_id: u64, now: u64) -> CommonResult<&mut Proposal> {
+        let proposal = self
+            .proposals
+            .get(&proposal_id)
+            .ok_or(ToolError::ProposalNotFound)?;
+        if proposal.status != ProposalStatus::Open {
+            return Err(ToolError::ProposalNotOpen);
+        }
+        if now >= proposal.expires_at {
+            self.close_proposal(proposal_id, ProposalStatus::Expired, now);
+            return Err(ToolError::ProposalExpired);
+        }
+        Ok(self.proposals.get_mut(&proposal_id).unwrap())
+    }
+
+    fn close_proposal(&mut self, proposal_id: u64, status: ProposalStatus, now: u64) {
+        if let Some(proposal) = self.proposals.get_mut(&proposal_id) {
+            proposal.status = status;
+            proposal.closed_at = Some(now);
+        }
+        self.persist_proposal(proposal_id);
+    }
+
+    // execute the action, returns the event recording the change
+    fn execute_action(&mut self, proposer: &Principal, action: ProposalAction, now: u64) -> CommonResult<ToolEvent> {
+        let event = match action {
+            ProposalAction::SetTokenWasm { wasm_hash } => {
+                let upload_id = self.staged_upload_id(&wasm_hash)?;
+                let (_, args) = self.take_upload(upload_id, wasm_hash)?;
+                wasm_uploaded_event(self.store_token_wasm(proposer, args, now)?)
+            }
+            ProposalAction::SetCyclesPerToken(cycles_per_token) => {
+                self.cycles_per_token = cycles_per_token;
+                ToolEvent::CyclesPerTokenSet { cycles_per_token }
+            }
+            ProposalAction::SetE8sPerToken(e8s_per_token) => {
+                self.e8s_per_token = e8s_per_token;
+                ToolEvent::E8sPerTokenSet { e8s_per_token }
+            }
+            ProposalAction::SetPaymentCanisters { ledger, cmc } => {
+                self.ledger_canister_id = ledger;
+                self.cmc_canister_id = cmc;
+                payment_canisters_event(&ledger, &cmc)
+            }
+            ProposalAction::SetInstallCompressedWasm(enabled) => {
+                self.install_compressed_wasm = enabled;
+                setting_event("installCompressedWasm", enabled)
+            }
+            ProposalAction::SetBlackholeCanister(canister_id) => {
+                self.blackhole_canister_id = canister_id;
+                blackhole_canister_event(&canister_id)
+            }
+            ProposalAction::SetMultisig(config) => {
+                self.multisig = config.clone();
+                ToolEvent::MultisigSet { config }
+            }
+        };
+        Ok(event)
+    }
+
+    // append the event to the audit log
+    pub fn record_event(&mut self, caller: Principal, event: ToolEvent, now: u64) {
+        let event = Event {
+            event_id: self.events.len() as u64,
+            caller,
+            timestamp: now,
+            event,
+        };
+        self.events.push(event.clone());
+        self.persist(StableRecord::Event(event));
+    }
+
+    // get the events from the event id start, optionally filtered by event type and principal
+    pub fn get_events(&self, start: u64, size: u64, filter: Option<EventFilter>) -> EventPage {
+        // max page size is 200
+        let page_size = if size > 200 { 200 } else { size as usize };
+        let filter = filter.unwrap_or_default();
+        let mut matching = self
+            .events
+            .iter()
+            .skip(start as usize)
+            .filter(|e| filter.matches(e));
+        let events: Vec<Event> = matching.by_ref().take(page_size).cloned().collect();
+        EventPage {
+            events,
+            total: self.events.len() as u64,
+            next_start: matching.next().map(|e| e.event_id),
+        }
+    }
+
+    // write the changes to the record log from now on
+    pub fn attach_store(&mut self, store: RecordLog) {
+        self.store = Some(store);
+    }
+
+    // append the record of a change, called once the change is applied to the state,
+    // so a settings snapshot or a compaction never writes a state older than the log
+    fn persist(&mut self, record: StableRecord) {
+        if let Some(store) = self.store.as_mut() {
+            append_record(store, &record);
+        }
+    }
+
+    // rewrite the log with the live state only, dropping the superseded records, returns the size of the log,
+    // only called by the owner as it writes the whole state
+    pub fn compact_store(&mut self, caller: &Principal) -> CommonResult<u64> {
+        self.only_owner(caller)?;
+        if let Some(store) = self.store.as_mut() {
+            store.clear();
+        }
+        self.save_all();
+        Ok(match self.store.as_mut() {
+            Some(store) => {
+                store.set_compacted();
+                store.size()
+            }
+            None => 0,
+        })
+    }
+
+    fn persist_order(&mut self, order_id: u64) {
+        if let Some(order) = self.orders.get(&order_id).cloned() {
+            self.persist(StableRecord::Order(order));
+        }
+    }
+
+    fn persist_issuance(&mut self, issuance_id: u64) {
+        if let Some(issuance) = self.issuances.get(&issuance_id).cloned() {
+            self.persist(StableRecord::Issuance(issuance));
+        }
+    }
+
+    fn persist_proposal(&mut self, proposal_id: u64) {
+        if let Some(proposal) = self.proposals.get(&proposal_id).cloned() {
+            self.persist(StableRecord::Proposal(proposal));
+        }
+    }
+
+    // append a snapshot of the settings, the only write on upgrade
+    pub fn save_settings(&mut self) {
+        let settings = self.to_settings();
+        self.persist(StableRecord::Settings(settings));
+    }
+
+    // write the whole state to the record log, when migrating from the ToolPayload layout and on compaction
+    pub fn save_all(&mut self) {
+        let settings = self.to_settings();
+        let store = match self.store.as_mut() {
+            Some(store) => store,
+            None => return,
+        };
+        for token_wasm in self.token_wasms.values() {
+            append_record(store, &StableRecord::TokenWasm(token_wasm.clone()));
+        }
+        for token_id in &self.token_ids {
+            append_record(store, &StableRecord::Token(self.tokens[token_id].clone()));
+        }
+        for (token_id, upgrades) in &self.token_upgrades {
+            for upgrade in upgrades {
+                append_record(store, &StableRecord::TokenUpgrade(*token_id, upgrade.clone()));
+            }
+        }
+        for (token_id, logo) in &self.logos {
+            append_record(store, &StableRecord::Logo(*token_id, ByteBuf::from(logo.clone())));
+        }
+        for order in self.orders.values() {
+            append_record(store, &StableRecord::Order(order.clone()));
+        }
+        for issuance in self.issuances.values() {
+            append_record(store, &StableRecord::Issuance(issuance.clone()));
+        }
+        for proposal in self.proposals.values() {
+            append_record(store, &StableRecord::Proposal(proposal.clone()));
+        }
+        for event in &self.events {
+            append_record(store, &StableRecord::Event(event.clone()));
+        }
+        append_record(store, &StableRecord::Settings(settings));
+    }
+
+    // rebuild the state by replaying the record log written before the upgrade
+    pub fn load_from_store(&mut self, store: RecordLog) -> Result<(), String> {
+        for bytes in store.records() {
+            let record = Decode!(&bytes, StableRecord).map_err(|e| e.to_string())?;
+            match record {
+                StableRecord::Token(token_info) => self.insert_token(token_info),
+                StableRecord::TokenUpgrade(token_id, upgrade) => {
+                    self.token_upgrades.entry(token_id).or_default().push(upgrade)
+                }
+                StableRecord::TokenWasm(token_wasm) => {
+                    self.token_wasms.insert(token_wasm.hash.clone(), token_wasm);
+                }
+                StableRecord::Logo(token_id, logo) => {
+                    self.logos.insert(token_id, logo.into_vec());
+                }
+                StableRecord::Order(order) => {
+                    self.orders.insert(order.order_id, order);
+                }
+                StableRecord::Issuance(issuance) => {
+                    self.issuances.insert(issuance.issuance_id, issuance);
+                }
+                StableRecord::Proposal(proposal) => {
+                    self.proposals.insert(proposal.proposal_id, proposal);
+                }
+                StableRecord::Event(event) => self.events.push(event),
+                StableRecord::Settings(settings) => self.load_settings(settings),
+            }
+        }
+        self.store = Some(store);
+        Ok(())
+    }
+
+    fn to_settings(&self) -> ToolSettings {
+        ToolSettings {
+            owner: self.owner,
+            pending_owner: self.pending_owner.clone(),
+            cycles_per_token: self.cycles_per_token,
+            e8s_per_token: self.e8s_per_token,
+            ledger_canister_id: self.ledger_canister_id,
+            cmc_canister_id: self.cmc_canister_id,
+            install_compressed_wasm: self.install_compressed_wasm,
+            public_issuance: self.public_issuance,
+            issuer_quota: self.issuer_quota,
+            unique_symbols: self.unique_symbols,
+            blocked_symbols: self.get_blocked_symbols(),
+            symbol_reservations: self.symbol_reservations.values().cloned().collect(),
+            symbol_reservation_ttl: self.symbol_reservation_ttl,
+            blackhole_canister_id: self.blackhole_canister_id,
+            roles: self
+                .get_role_holders(None)
+                .into_iter()
+                .map(|r| (r.role, r.holders))
+                .collect(),
+            multisig: self.multisig.clone(),
+            next_order_id: self.next_order_id,
+            next_issuance_id: self.next_issuance_id,
+            next_proposal_id: self.next_proposal_id,
+        }
+    }
+
+    fn load_settings(&mut self, settings: ToolSettings) {
+        self.owner = settings.owner;
+        self.pending_owner = settings.pending_owner;
+        self.cycles_per_token = settings.cycles_per_token;
+        self.e8s_per_token = settings.e8s_per_token;
+        self.ledger_canister_id = settings.ledger_canister_id;
+        self.cmc_canister_id = settings.cmc_canister_id;
+        self.install_compressed_wasm = settings.install_compressed_wasm;
+        self.public_issuance = settings.public_issuance;
+        self.issuer_quota = settings.issuer_quota;
+        self.unique_symbols = settings.unique_symbols;
+        self.blocked_symbols = settings.blocked_symbols.into_iter().collect();
+        self.symbol_reservations = settings
+            .symbol_reservations
+            .into_iter()
+            .map(|r| (fold_symbol(&r.symbol), r))
+            .collect();
+        self.symbol_reservation_ttl = settings.symbol_reservation_ttl;
+        self.blackhole_canister_id = settings.blackhole_canister_id;
+        self.roles = settings
+            .roles
+            .into_iter()
+            .map(|(role, holders)| (role, holders.into_iter().collect()))
+            .collect();
+        self.multisig = settings.multisig;
+        self.next_order_id = settings.next_order_id;
+        self.next_issuance_id = settings.next_issuance_id;
+        self.next_proposal_id = settings.next_proposal_id;
+    }
+
+    // convert to ToolPayload, the layout saved on upgrade before the record log
+    pub fn to_payload(&self) -> ToolPayload {
+        ToolPayload {
+            owner: self.owner,
+            cycles_per_token: self.cycles_per_token,
+            token_wasms: self.token_wasms.values().cloned().collect(),
+            install_compressed_wasm: self.install_compressed_wasm,
+            tokens: self
+                .token_ids
+                .iter()
+                .map(|k| (*k, self.tokens[k].clone()))
+                .collect(),
+            e8s_per_token: self.e8s_per_token,
+            ledger_canister_id: self.ledger_canister_id,
+            cmc_canister_id: self.cmc_canister_id,
+            orders: self
+                .orders
+                .iter()
+                .map(|(k, v)| (*k, v.clone()))
+                .collect(),
+            next_order_id: self.next_order_id,
+            public_issuance: self.public_issuance,
+            issuer_quota: self.issuer_quota,
+            token_upgrades: self
+                .token_upgrades
+                .iter()
+                .map(|(k, v)| (*k, v.clone()))
+                .collect(),
+            unique_symbols: self.unique_symbols,
+            blocked_symbols: self.get_blocked_symbols(),
+            symbol_reservations: self.symbol_reservations.values().cloned().collect(),
+            symbol_reservation_ttl: self.symbol_reservation_ttl,
+            logos: self.logos.iter().map(|(k, v)| (*k, v.clone())).collect(),
+            issuances: self.issuances.values().cloned().collect(),
+            next_issuance_id: self.next_issuance_id,
+            blackhole_canister_id: self.blackhole_canister_id,
+            roles: self
+                .get_role_holders(None)
+                .into_iter()
+                .map(|r| (r.role, r.holders))
+                .collect(),
+            pending_owner: self.pending_owner.clone(),
+            multisig: self.multisig.clone(),
+            proposals: self.proposals.values().cloned().collect(),
+            next_proposal_id: self.next_proposal_id,
+            events: self.events.clone(),
+        }
+    }
+
+    // get ToolStatus
+    pub fn get_status(&self) -> ToolStatus {
+        ToolStatus {
+            owner: self.owner,
+            cycles_per_token: self.cycles_per_token,
+            cycles: 0,
+            issued_token_count: self.get_token_count().unwrap(),
+            token_wasm_count: self.token_wasms.len() as u64,
+            token_wasm_size: self.token_wasms.values().map(|w| w.size).sum(),
+            token_wasm_compressed_size: self
+                .token_wasms
+                .values()
+                .map(|w| w.wasm_module.len() as u64)
+                .sum(),
+            install_compressed_wasm: self.install_compressed_wasm,
+            e8s_per_token: self.e8s_per_token,
+            ledger_canister_id: self.ledger_canister_id,
+            cmc_canister_id: self.cmc_canister_id,
+            public_issuance: self.public_issuance,
+            issuer_quota: self.issuer_quota,
+            stable_log_size: self.store.as_ref().map_or(0, |store| store.size()),
+            stable_log_compacted_size: self.store.as_ref().map_or(0, |store| store.compacted_size()),
+        }
+    }
+
+    // load from ToolPayload, to migrate to the record log
+    pub fn load_from_payload(&mut self, payload: ToolPayload) {
+        self.owner = payload.owner;
+        self.cycles_per_token = payload.cycles_per_token;
+        self.token_wasms = payload
+            .token_wasms
+            .into_iter()
+            .map(|w| (w.hash.clone(), w))
+            .collect();
+        self.install_compressed_wasm = payload.install_compressed_wasm;
+        self.tokens = TokenInfoMap::new();
+        self.token_ids = Vec::new();
+        self.token_positions = HashMap::new();
+        self.tokens_by_issue_time = BTreeSet::new();
+        self.tokens_by_name = BTreeSet::new();
+        self.tokens_by_symbol = BTreeSet::new();
+        self.issuer_tokens = HashMap::new();
+        self.search_index = BTreeMap::new();
+        self.symbol_tokens = HashMap::new();
+        self.certified_tokens = CertifiedTokenTree::new();
+        for (_, token_info) in payload.tokens {
+            self.insert_token(token_info);
+        }
+        self.e8s_per_token = payload.e8s_per_token;
+        self.ledger_canister_id = payload.ledger_canister_id;
+        self.cmc_canister_id = payload.cmc_canister_id;
+        self.orders = payload.orders.into_iter().collect();
+        self.next_order_id = payload.next_order_id;
+        self.public_issuance = payload.public_issuance;
+        self.issuer_quota = payload.issuer_quota;
+        self.token_upgrades = payload.token_upgrades.into_iter().collect();
+        self.unique_symbols = payload.unique_symbols;
+        self.blocked_symbols = payload.blocked_symbols.into_iter().collect();
+        self.symbol_reservations = payload
+            .symbol_reservations
+            .into_iter()
+            .map(|r| (fold_symbol(&r.symbol), r))
+            .collect();
+        self.symbol_reservation_ttl = payload.symbol_reservation_ttl;
+        self.logos = payload.logos.into_iter().collect();
+        self.issuances = payload
+            .issuances
+            .into_iter()
+            .map(|i| (i.issuance_id, i))
+            .collect();
+        self.next_issuance_id = payload.next_issuance_id;
+        self.blackhole_canister_id = payload.blackhole_canister_id;
+        self.roles = payload
+            .roles
+            .into_iter()
+            .map(|(role, holders)| (role, holders.into_iter().collect()))
+            .collect();
+        self.pending_owner = payload.pending_owner;
+        self.multisig = payload.multisig;
+        self.proposals = payload
+            .proposals
+            .into_iter()
+            .map(|p| (p.proposal_id, p))
+            .collect();
+        self.next_proposal_id = payload.next_proposal_id;
+        self.events = payload.events;
+    }
+
+    // load from the ToolPayload of the first release, its token wasm is kept as the default template
+    // if it is still a valid token module, otherwise the owner has to upload one
+    pub fn load_from_legacy_payload(&mut self, payload: LegacyToolPayload, upgrader: &Principal, now: u64) -> CommonResult<()> {
+        self.load_from_payload(ToolPayload {
+            owner: payload.owner,
+            cycles_per_token: payload.cycles_per_token,
+            tokens: payload
+                .tokens
+                .into_iter()
+                .map(|(token_id, token_info)| (token_id, token_info.into()))
+                .collect(),
+            ..ToolPayload::default()
+        });
+        // the token wasm is uploaded by the owner
+        self.set_owner_if_unset(upgrader, now)?;
+        if !payload.token_wasm.is_empty() {
+            let args = StoreWASMArgs {
+                template: DEFAULT_TOKEN_TEMPLATE.to_string(),
+                version: LEGACY_TOKEN_WASM_VERSION.to_string(),
+                release_notes: None,
+                wasm_module: payload.token_wasm,
+            };
+            let owner = self.owner;
+            let _ = self.store_token_wasm(&owner, args, now);
+        }
+        Ok(())
+    }
+}
+
+pub fn wasm_uploaded_event(info: TokenWasmInfo) -> ToolEvent {
+    ToolEvent::TokenWasmUploaded {
+        template: info.template,
+        version: info.version,
+        hash: info.hash,
+    }
+}
+
+pub fn setting_event(name: &str, value: impl ToString) -> ToolEvent {
+    ToolEvent::SettingChanged {
+        name: name.to_string(),
+        value: value.to_string(),
+    }
+}
+
+pub fn payment_canisters_event(ledger: &Principal, cmc: &Principal) -> ToolEvent {
+    setting_event("paymentCanisters", format!("{}, {}", ledger, cmc))
+}
+
+pub fn blackhole_canister_event(canister_id: &Option<Principal>) -> ToolEvent {
+    setting_event(
+        "blackholeCanister",
+        canister_id.map_or("none".to_string(), |c| c.to_text()),
+    )
+}
+
+fn validate_multisig_config(config: &MultisigConfig) -> CommonResult<()> {
+    let invalid = |reason: &str| {
+        Err(ToolError::InvalidMultisigConfig {
+            reason: reason.to_string(),
+        })
+    };
+    if config.signers.is_empty() || config.signers.len() > MAX_MULTISIG_SIGNERS {
+        return invalid(&format!("expected 1 to {} signers", MAX_MULTISIG_SIGNERS));
+    }
+    if config.signers.contains(&Principal::anonymous()) {
+        return invalid("anonymous signer");
+    }
+    let unique: BTreeSet<&Principal> = config.signers.iter().collect();
+    if unique.len() != config.signers.len() {
+        return invalid("duplicate signers");
+    }
+    if config.threshold == 0 || config.threshold > config.signers.len() as u64 {
+        return invalid("threshold must be between 1 and the number of signers");
+    }
+    if config.proposal_ttl == Some(0) {
+        return invalid("proposal ttl must be positive");
+    }
+    Ok(())
+}
+
+// all suffixes of the lowercase name and symbol, a substring of them is a prefix of a suffix
+fn search_suffixes(token_info: &TokenInfo) -> BTreeSet<String> {
+    let mut suffixes = BTreeSet::new();
+    for term in [&token_info.name, &token_info.symbol].iter() {
+        let term = term.to_lowercase();
+        for (i, _) in term.char_indices() {
+            suffixes.insert(term[i..].to_string());
+        }
+    }
+    suffixes
+}
+
+// positions of up to `size` entries of the index after the cursor entry, in the given direction,
+// and whether more entries follow, ties of the sort key are ordered by the position
+fn index_page<K: Ord>(
+    index: &BTreeSet<(K, usize)>,
+    cursor: Option<(K, usize)>,
+    direction: SortDirection,
+    size: usize,
+) -> (Vec<usize>, bool) {
+    let entries: Box<dyn Iterator<Item = &(K, usize)> + '_> = match (direction, cursor) {
+        (SortDirection::Ascending, None) => Box::new(index.iter()),
+        (SortDirection::Ascending, Some(cursor)) => {
+            Box::new(index.range((Bound::Excluded(cursor), Bound::Unbounded)))
+        }
+        (SortDirection::Descending, None) => Box::new(index.iter().rev()),
+        (SortDirection::Descending, Some(cursor)) => Box::new(index.range(..cursor).rev()),
+    };
+    let mut positions: Vec<usize> = entries.take(size + 1).map(|(_, position)| *position).collect();
+    let has_more = positions.len() > size;
+    positions.truncate(size);
+    (positions, has_more)
+}
+
+//  IssuanceTool tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stable::VecMemory;
+    use crate::token_wasm::test_token_wasm;
+    use candid::Nat;
+    use ic_types::Principal;
+
+    // test get/set owner
+    #[test]
+    fn test_owner() {
+        let mut tool = IssuanceTool::new();
+        let owner =
+            Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe")
+                .unwrap();
+
+        // set owner by call anonymous
+        let result = tool.set_owner(&Principal::anonymous(), owner);
+        // check result is err
+        assert!(result.is_err());
+        // check error code is NotAllowAnonymous
+        assert_eq!(result.unwrap_err(), ToolError::NotAllowAnonymous);
+        // check owner is anonymous
+        assert_eq!(tool.owner(), Principal::anonymous());
+
+        // set owner by owner
+        let result = tool.set_owner(&owner, owner);
+        // check result is ok
+        assert!(result.is_ok());
+        // check owner is owner
+        assert_eq!(tool.owner(), owner);
+        let new_owner =
+            Principal::from_text("czjfo-ddpvm-6sibl-6zbox-ee5zq-bx3hc-e336t-s6pka-dupmy-wcxqi-fae")
+                .unwrap();
+        // set owner to new_owner
+        let result = tool.set_owner(&new_owner, new_owner);
+        // check result is error
+        assert!(result.is_err());
+        // the error code is OnlyOwnerAllowCallIt
+        assert_eq!(result.unwrap_err(), ToolError::OnlyOwnerAllowCallIt);
+
+        // the owner can't be replaced directly
+        let result = tool.set_owner(&owner, new_owner);
+        assert_eq!(result.unwrap_err(), ToolError::OwnerAlreadySet);
+        assert_eq!(tool.owner(), owner);
+
+        // propose new_owner, only the owner can propose
+        let result = tool.propose_owner(&new_owner, new_owner, 0);
+        assert_eq!(result.unwrap_err(), ToolError::OnlyOwnerAllowCallIt);
+        let transfer = tool.propose_owner(&owner, new_owner, 0).unwrap();
+        assert_eq!(transfer.expires_at, OWNERSHIP_TRANSFER_TTL);
+        assert_eq!(tool.get_pending_owner(1), Some(transfer));
+        assert_eq!(tool.owner(), owner);
+        // only the proposed owner can accept
+        let result = tool.accept_ownership(&owner, 1);
+        assert_eq!(result.unwrap_err(), ToolError::OwnershipTransferNotFound);
+
+        // cancel the transfer
+        let result = tool.cancel_ownership_transfer(&owner);
+        assert!(result.is_ok());
+        let result = tool.accept_ownership(&new_owner, 1);
+        assert_eq!(result.unwrap_err(), ToolError::OwnershipTransferNotFound);
+        let result = tool.cancel_ownership_transfer(&owner);
+        assert_eq!(result.unwrap_err(), ToolError::OwnershipTransferNotFound);
+
+        // an expired transfer can't be accepted
+        let result = tool.propose_owner(&owner, new_owner, 0);
+        assert!(result.is_ok());
+        assert_eq!(tool.get_pending_owner(OWNERSHIP_TRANSFER_TTL), None);
+        let result = tool.accept_ownership(&new_owner, OWNERSHIP_TRANSFER_TTL);
+        assert_eq!(result.unwrap_err(), ToolError::OwnershipTransferExpired);
+        assert_eq!(tool.owner(), owner);
+
+        // accept the transfer
+        let result = tool.propose_owner(&owner, new_owner, 10);
+        assert!(result.is_ok());
+        let result = tool.accept_ownership(&new_owner, 11);
+        assert!(result.is_ok());
+        assert_eq!(tool.owner(), new_owner);
+        assert_eq!(tool.get_pending_owner(11), None);
+
+        // check default value of cycles_per_token
+        assert_eq!(tool.cycles_per_token(), 3_000_000_000_000);
+    }
+
+    // test set cycles per token
+    #[test]
+    fn test_cycles_per_token() {
+        let mut tool = IssuanceTool::new();
+        let owner =
+            Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe")
+                .unwrap();
+        // set cycles by anonymous will fail
+        let result = tool.set_cycles_per_token(&Principal::anonymous(), 1);
+        // check result is err
+        assert!(result.is_err());
+        // check error code is NotAllowAnonymous
+        assert_eq!(result.unwrap_err(), ToolError::NotAllowAnonymous);
+
+        // set cycles by not owner will fail
+        let new_owner =
+            Principal::from_text("czjfo-ddpvm-6sibl-6zbox-ee5zq-bx3hc-e336t-s6pka-dupmy-wcxqi-fae")
+                .unwrap();
+        let result = tool.set_cycles_per_token(&new_owner, 1);
+        // check result is err
+        assert!(result.is_err());
+        // check error code is OnlyOwnerAllowCallIt
+        assert_eq!(result.unwrap_err(), ToolError::RoleRequired { role: Role::FeeManager });
+        let result = tool.set_owner(&owner, owner);
+        assert!(result.is_ok());
+        let result = tool.set_cycles_per_token(&owner, 100);
+        assert!(result.is_ok());
+        assert_eq!(tool.cycles_per_token, 100);
+    }
+
+    // test add token
+    #[test]
+    fn test_add_token() {
+        let mut tool = IssuanceTool::new();
+        let owner =
+            Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe")
+                .unwrap();
+        let new_owner =
+            Principal::from_text("czjfo-ddpvm-6sibl-6zbox-ee5zq-bx3hc-e336t-s6pka-dupmy-wcxqi-fae")
+                .unwrap();
+        let result = tool.set_owner(&owner, owner);
+        assert!(result.is_ok());
+        let token_id = Principal::from_text("g7cye-cyaaa-aaaak-aaa5a-cai").unwrap();
+
+        // build a instance of TokenInfo
+
+        let token_info = TokenInfo {
+            issuer: owner,
+            token_id,
+            name: "test".to_string(),
+            symbol: "TST".to_string(),
+            decimals: 18,
+            total_supply: 100,
+            fee: Fee {
+                minimum: Nat::from(1),
+                rate: Nat::from(10000),
+            },
+            template: DEFAULT_TOKEN_TEMPLATE.to_string(),
+            controllers: vec![],
+            immutable: false,
+            blackhole_canister_id: None,
+            module_hash: None,
+            timestamp: 0,
+        };
+        let result = tool.add_token(&owner, token_info.clone());
+        assert!(result.is_ok());
+        assert_eq!(tool.tokens.len(), 1);
+        // get token by id, then check token info
+        let token = tool.get_token_by_id(&token_id).unwrap();
+        assert_eq!(token.token_id, token_id);
+        assert_eq!(token.name, "test".to_string());
+        assert_eq!(token.symbol, "TST".to_string());
+        assert_eq!(token.decimals, 18);
+        assert_eq!(token.total_supply, 100);
+        assert_eq!(token.fee.minimum, Nat::from(1));
+        assert_eq!(token.fee.rate, Nat::from(10000));
+        assert_eq!(token.timestamp, 0);
+        assert_eq!(token.issuer, owner);
+        // add token by not owner will fail while public issuance is off
+        let result = tool.add_token(&new_owner, token_info.clone());
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err(), ToolError::PublicIssuanceDisabled);
+    }
+
+    // test public issuance and issuer quota
+    #[test]
+    fn test_public_issuance() {
         let mut tool = IssuanceTool::new();
         let owner =
             Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe")
                 .unwrap();
-        // set cycles by anonymous will fail
-        let result = tool.set_cycles_per_token(&Principal::anonymous(), 1);
-        // check result is err
-        assert!(result.is_err());
-        // check error code is NotAllowAnonymous
+        let issuer =
+            Principal::from_text("czjfo-ddpvm-6sibl-6zbox-ee5zq-bx3hc-e336t-s6pka-dupmy-wcxqi-fae")
+                .unwrap();
+        let result = tool.set_owner(&owner, owner);
+        assert!(result.is_ok());
+        let token_info = TokenInfo {
+            issuer,
+            token_id: Principal::from_text("g7cye-cyaaa-aaaak-aaa5a-cai").unwrap(),
+            name: "test".to_string(),
+            symbol: "TST".to_string(),
+            decimals: 18,
+            total_supply: 100,
+            fee: Fee {
+                minimum: Nat::from(1),
+                rate: Nat::from(10000),
+            },
+            template: DEFAULT_TOKEN_TEMPLATE.to_string(),
+            controllers: vec![],
+            immutable: false,
+            blackhole_canister_id: None,
+            module_hash: None,
+            timestamp: 0,
+        };
+
+        // only owner can issue by default
+        assert_eq!(tool.only_issuer(&issuer).unwrap_err(), ToolError::PublicIssuanceDisabled);
+        // only owner can enable public issuance
+        let result = tool.set_public_issuance(&issuer, true);
+        assert_eq!(result.unwrap_err(), ToolError::RoleRequired { role: Role::Admin });
+        let result = tool.set_public_issuance(&owner, true);
+        assert!(result.is_ok());
+        // anonymous is still not allowed
+        assert_eq!(
+            tool.only_issuer(&Principal::anonymous()).unwrap_err(),
+            ToolError::NotAllowAnonymous
+        );
+        let result = tool.add_token(&issuer, token_info.clone());
+        assert!(result.is_ok());
+        assert_eq!(tool.get_token_by_id(&token_info.token_id).unwrap().issuer, issuer);
+
+        // issuer quota
+        let result = tool.set_issuer_quota(&owner, 1);
+        assert!(result.is_ok());
+        let mut token_info2 = token_info;
+        token_info2.token_id = Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap();
+        let result = tool.add_token(&issuer, token_info2.clone());
+        assert_eq!(result.unwrap_err(), ToolError::IssuerQuotaExceeded { quota: 1 });
+        // the owner is not limited by the quota
+        token_info2.issuer = owner;
+        let result = tool.add_token(&owner, token_info2);
+        assert!(result.is_ok());
+    }
+
+    // test token upgrade permission and history
+    #[test]
+    fn test_token_upgrade() {
+        let mut tool = IssuanceTool::new();
+        let owner =
+            Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe")
+                .unwrap();
+        let controller =
+            Principal::from_text("czjfo-ddpvm-6sibl-6zbox-ee5zq-bx3hc-e336t-s6pka-dupmy-wcxqi-fae")
+                .unwrap();
+        let token_id = Principal::from_text("g7cye-cyaaa-aaaak-aaa5a-cai").unwrap();
+        let result = tool.set_owner(&owner, owner);
+        assert!(result.is_ok());
+        let token_info = TokenInfo {
+            issuer: owner,
+            token_id,
+            name: "test".to_string(),
+            symbol: "TST".to_string(),
+            decimals: 18,
+            total_supply: 100,
+            fee: Fee {
+                minimum: Nat::from(1),
+                rate: Nat::from(10000),
+            },
+            template: DEFAULT_TOKEN_TEMPLATE.to_string(),
+            controllers: vec![],
+            immutable: false,
+            blackhole_canister_id: None,
+            module_hash: None,
+            timestamp: 0,
+        };
+        let result = tool.add_token(&owner, token_info);
+        assert!(result.is_ok());
+
+        // the issuer and the controllers of the token can upgrade it
+        assert!(tool.only_token_upgrader(&owner, &token_id, &[]).is_ok());
+        assert!(tool.only_token_upgrader(&controller, &token_id, &[controller]).is_ok());
+        assert_eq!(
+            tool.only_token_upgrader(&controller, &token_id, &[owner]).unwrap_err(),
+            ToolError::CallerIsNotControllerOfToken
+        );
+        let unknown_token = Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap();
+        assert_eq!(
+            tool.only_token_upgrader(&owner, &unknown_token, &[]).unwrap_err(),
+            ToolError::TokenNotFound
+        );
+
+        let args = StoreWASMArgs {
+            template: DEFAULT_TOKEN_TEMPLATE.to_string(),
+            version: "0.1.0".to_string(),
+            release_notes: None,
+            wasm_module: test_token_wasm(1),
+        };
+        let result = tool.set_token_wasm(&owner, args, 0);
+        assert!(result.is_ok());
+        let hash = tool.get_token_wasm(DEFAULT_TOKEN_TEMPLATE).unwrap().hash;
+        assert_eq!(hash.len(), 32);
+        let upgrade = TokenUpgrade {
+            operator: controller,
+            old_module_hash: Some(vec![0; 32]),
+            new_module_hash: hash.clone(),
+            timestamp: 1,
+        };
+        // an installed module is recorded once the token is handed back, until then the upgrade is retried
+        assert_eq!(tool.check_token_upgrade(&token_id, Some(&vec![0; 32]), &hash), Ok(true));
+        assert_eq!(tool.check_token_upgrade(&token_id, None, &hash), Ok(true));
+        assert_eq!(tool.check_token_upgrade(&token_id, Some(&hash), &hash), Ok(false));
+        let root = tool.certified_tokens_root();
+        let result = tool.add_token_upgrade(&token_id, upgrade.clone());
+        assert!(result.is_ok());
+        assert_eq!(tool.get_token_upgrades(&token_id).unwrap(), vec![upgrade.clone()]);
+        // the new module hash is certified
+        let token = tool.get_token_by_id(&token_id).unwrap();
+        assert_eq!(token.module_hash, Some(upgrade.new_module_hash));
+        assert_ne!(tool.certified_tokens_root(), root);
+        assert_eq!(
+            tool.certified_tokens.get(token_id.as_slice()),
+            Some(&crate::certification::token_hash(&token))
+        );
+        let hash = token.module_hash.unwrap();
+        assert_eq!(
+            tool.check_token_upgrade(&token_id, Some(&hash), &hash),
+            Err(ToolError::TokenAlreadyUpToDate)
+        );
+        let result = tool.add_token_upgrade(&unknown_token, TokenUpgrade {
+            operator: controller,
+            old_module_hash: None,
+            new_module_hash: vec![],
+            timestamp: 1,
+        });
+        assert_eq!(result.unwrap_err(), ToolError::TokenNotFound);
+    }
+
+    // test token pagination is stable and sortable
+    #[test]
+    fn test_list_tokens() {
+        let mut tool = IssuanceTool::new();
+        let owner =
+            Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe")
+                .unwrap();
+        let result = tool.set_owner(&owner, owner);
+        assert!(result.is_ok());
+        let token_ids = vec![
+            Principal::from_text("g7cye-cyaaa-aaaak-aaa5a-cai").unwrap(),
+            Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap(),
+            Principal::from_text("ryjl3-tyaaa-aaaaa-aaaba-cai").unwrap(),
+            Principal::from_text("rkp4c-7iaaa-aaaaa-aaaca-cai").unwrap(),
+            Principal::from_text("el45q-hyaaa-aaaak-aaata-cai").unwrap(),
+        ];
+        let names = ["delta", "Alpha", "echo", "charlie", "bravo"];
+        for (i, token_id) in token_ids.iter().enumerate() {
+            let token_info = TokenInfo {
+                issuer: owner,
+                token_id: *token_id,
+                name: names[i].to_string(),
+                symbol: names[i].to_uppercase(),
+                decimals: 18,
+                total_supply: 100,
+                fee: Fee {
+                    minimum: Nat::from(1),
+                    rate: Nat::from(10000),
+                },
+                template: DEFAULT_TOKEN_TEMPLATE.to_string(),
+                controllers: vec![],
+                immutable: false,
+                blackhole_canister_id: None,
+                module_hash: None,
+                timestamp: i as u64,
+            };
+            let result = tool.add_token(&owner, token_info);
+            assert!(result.is_ok());
+        }
+
+        // tokens are listed in insertion order, also after reloading the payload
+        let ids = |tokens: Vec<TokenInfo>| tokens.iter().map(|t| t.token_id).collect::<Vec<_>>();
+        assert_eq!(ids(tool.get_tokens(0, 3).unwrap()), token_ids[0..3].to_vec());
+        assert_eq!(ids(tool.get_tokens(3, 3).unwrap()), token_ids[3..5].to_vec());
+        tool.load_from_payload(tool.to_payload());
+        assert_eq!(ids(tool.get_tokens(0, 5).unwrap()), token_ids);
+
+        // page through by cursor
+        let args = |cursor, sort_by, direction| ListTokensArgs {
+            cursor,
+            size: 2,
+            sort_by,
+            direction,
+        };
+        let page = tool.list_tokens(args(None, None, None)).unwrap();
+        assert_eq!(page.total, 5);
+        assert_eq!(ids(page.tokens), token_ids[0..2].to_vec());
+        assert_eq!(page.next_cursor, Some(token_ids[1]));
+        let page = tool.list_tokens(args(page.next_cursor, None, None)).unwrap();
+        assert_eq!(ids(page.tokens), token_ids[2..4].to_vec());
+        let page = tool.list_tokens(args(page.next_cursor, None, None)).unwrap();
+        assert_eq!(ids(page.tokens), token_ids[4..5].to_vec());
+        assert_eq!(page.next_cursor, None);
+
+        // sort by name, case insensitive
+        let page = tool
+            .list_tokens(args(None, Some(TokenSortBy::Name), None))
+            .unwrap();
+        assert_eq!(ids(page.tokens), vec![token_ids[1], token_ids[4]]);
+        // sort by symbol descending
+        let page = tool
+            .list_tokens(args(None, Some(TokenSortBy::Symbol), Some(SortDirection::Descending)))
+            .unwrap();
+        assert_eq!(ids(page.tokens), vec![token_ids[2], token_ids[0]]);
+        // sort by issue time descending
+        let page = tool
+            .list_tokens(args(None, Some(TokenSortBy::IssueTime), Some(SortDirection::Descending)))
+            .unwrap();
+        assert_eq!(ids(page.tokens), vec![token_ids[4], token_ids[3]]);
+        let page = tool
+            .list_tokens(args(page.next_cursor, Some(TokenSortBy::IssueTime), Some(SortDirection::Descending)))
+            .unwrap();
+        assert_eq!(ids(page.tokens), vec![token_ids[2], token_ids[1]]);
+        let page = tool
+            .list_tokens(args(page.next_cursor, Some(TokenSortBy::IssueTime), Some(SortDirection::Descending)))
+            .unwrap();
+        assert_eq!(ids(page.tokens), vec![token_ids[0]]);
+        assert_eq!(page.next_cursor, None);
+
+        // an updated token moves in the index of its sort key
+        let mut token_info = tool.get_token_by_id(&token_ids[4]).unwrap();
+        token_info.name = "foxtrot".to_string();
+        tool.insert_token(token_info);
+        let page = tool
+            .list_tokens(args(Some(token_ids[0]), Some(TokenSortBy::Name), None))
+            .unwrap();
+        assert_eq!(page.total, 5);
+        assert_eq!(ids(page.tokens), vec![token_ids[2], token_ids[4]]);
+        assert_eq!(page.next_cursor, None);
+
+        // unknown cursor will fail
+        let result = tool.list_tokens(args(Some(owner), None, None));
+        assert_eq!(result.unwrap_err(), ToolError::TokenNotFound);
+    }
+
+    // test tokens of issuer
+    #[test]
+    fn test_tokens_of_issuer() {
+        let mut tool = IssuanceTool::new();
+        let owner =
+            Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe")
+                .unwrap();
+        let issuer =
+            Principal::from_text("czjfo-ddpvm-6sibl-6zbox-ee5zq-bx3hc-e336t-s6pka-dupmy-wcxqi-fae")
+                .unwrap();
+        let result = tool.set_owner(&owner, owner);
+        assert!(result.is_ok());
+        let result = tool.set_public_issuance(&owner, true);
+        assert!(result.is_ok());
+        let token_ids = [
+            Principal::from_text("g7cye-cyaaa-aaaak-aaa5a-cai").unwrap(),
+            Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap(),
+            Principal::from_text("ryjl3-tyaaa-aaaaa-aaaba-cai").unwrap(),
+        ];
+        let issuers = [issuer, owner, issuer];
+        for (i, token_id) in token_ids.iter().enumerate() {
+            let token_info = TokenInfo {
+                issuer: issuers[i],
+                token_id: *token_id,
+                name: "test".to_string(),
+                symbol: "TST".to_string(),
+                decimals: 18,
+                total_supply: 100,
+                fee: Fee {
+                    minimum: Nat::from(1),
+                    rate: Nat::from(10000),
+                },
+                template: DEFAULT_TOKEN_TEMPLATE.to_string(),
+                controllers: vec![],
+                immutable: false,
+                blackhole_canister_id: None,
+                module_hash: None,
+                timestamp: 0,
+            };
+            let result = tool.add_token(&issuers[i], token_info);
+            assert!(result.is_ok());
+        }
+
+        let ids = |tokens: Vec<TokenInfo>| tokens.iter().map(|t| t.token_id).collect::<Vec<_>>();
+        let tokens = tool.get_tokens_of_issuer(&issuer, 0, 10).unwrap();
+        assert_eq!(ids(tokens), vec![token_ids[0], token_ids[2]]);
+        let tokens = tool.get_tokens_of_issuer(&issuer, 1, 10).unwrap();
+        assert_eq!(ids(tokens), vec![token_ids[2]]);
+        let tokens = tool.get_tokens_of_issuer(&owner, 0, 10).unwrap();
+        assert_eq!(ids(tokens), vec![token_ids[1]]);
+        let tokens = tool.get_tokens_of_issuer(&Principal::anonymous(), 0, 10).unwrap();
+        assert!(tokens.is_empty());
+
+        // the index is rebuilt when loading the payload
+        tool.load_from_payload(tool.to_payload());
+        let tokens = tool.get_tokens_of_issuer(&issuer, 0, 10).unwrap();
+        assert_eq!(ids(tokens), vec![token_ids[0], token_ids[2]]);
+    }
+
+    // test search tokens by name and symbol
+    #[test]
+    fn test_search_tokens() {
+        let mut tool = IssuanceTool::new();
+        let owner =
+            Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe")
+                .unwrap();
+        let result = tool.set_owner(&owner, owner);
+        assert!(result.is_ok());
+        let token_ids = [
+            Principal::from_text("g7cye-cyaaa-aaaak-aaa5a-cai").unwrap(),
+            Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap(),
+            Principal::from_text("ryjl3-tyaaa-aaaaa-aaaba-cai").unwrap(),
+        ];
+        let tokens = [("Deland Token", "DLD"), ("Island Coin", "ISL"), ("Land", "LND")];
+        for (i, token_id) in token_ids.iter().enumerate() {
+            let token_info = TokenInfo {
+                issuer: owner,
+                token_id: *token_id,
+                name: tokens[i].0.to_string(),
+                symbol: tokens[i].1.to_string(),
+                decimals: 18,
+                total_supply: 100,
+                fee: Fee {
+                    minimum: Nat::from(1),
+                    rate: Nat::from(10000),
+                },
+                template: DEFAULT_TOKEN_TEMPLATE.to_string(),
+                controllers: vec![],
+                immutable: false,
+                blackhole_canister_id: None,
+                module_hash: None,
+                timestamp: 0,
+            };
+            let result = tool.add_token(&owner, token_info);
+            assert!(result.is_ok());
+        }
+
+        let ids = |tokens: Vec<TokenInfo>| tokens.iter().map(|t| t.token_id).collect::<Vec<_>>();
+        // prefix match comes before substring matches
+        let result = tool.search_tokens("LAND", 0, 10).unwrap();
+        assert_eq!(ids(result), vec![token_ids[2], token_ids[0], token_ids[1]]);
+        let result = tool.search_tokens("land", 1, 1).unwrap();
+        assert_eq!(ids(result), vec![token_ids[0]]);
+        // match by symbol
+        let result = tool.search_tokens("dl", 0, 10).unwrap();
+        assert_eq!(ids(result), vec![token_ids[0]]);
+        let result = tool.search_tokens("coin", 0, 10).unwrap();
+        assert_eq!(ids(result), vec![token_ids[1]]);
+        assert!(tool.search_tokens("xyz", 0, 10).unwrap().is_empty());
+        assert!(tool.search_tokens("  ", 0, 10).unwrap().is_empty());
+
+        // the index follows updates of the token
+        let mut token_info = tool.get_token_by_id(&token_ids[1]).unwrap();
+        token_info.name = "Sea Coin".to_string();
+        let result = tool.add_token(&owner, token_info);
+        assert!(result.is_ok());
+        let result = tool.search_tokens("land", 0, 10).unwrap();
+        assert_eq!(ids(result), vec![token_ids[2], token_ids[0]]);
+        let result = tool.search_tokens("sea", 0, 10).unwrap();
+        assert_eq!(ids(result), vec![token_ids[1]]);
+
+        // the index is rebuilt when loading the payload
+        tool.load_from_payload(tool.to_payload());
+        let result = tool.search_tokens("coin", 0, 10).unwrap();
+        assert_eq!(ids(result), vec![token_ids[1]]);
+    }
+
+    // test granting and revoking roles
+    #[test]
+    fn test_roles() {
+        let mut tool = IssuanceTool::new();
+        let owner =
+            Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe")
+                .unwrap();
+        let admin =
+            Principal::from_text("czjfo-ddpvm-6sibl-6zbox-ee5zq-bx3hc-e336t-s6pka-dupmy-wcxqi-fae")
+                .unwrap();
+        let user = Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap();
+        let result = tool.set_owner(&owner, owner);
+        assert!(result.is_ok());
+
+        // only the owner can grant admin
+        let result = tool.grant_role(&admin, Role::Admin, admin);
+        assert_eq!(result.unwrap_err(), ToolError::OnlyOwnerAllowCallIt);
+        let result = tool.grant_role(&owner, Role::Admin, admin);
+        assert!(result.is_ok());
+        let result = tool.grant_role(&admin, Role::Admin, user);
+        assert_eq!(result.unwrap_err(), ToolError::OnlyOwnerAllowCallIt);
+        let result = tool.grant_role(&owner, Role::Issuer, Principal::anonymous());
         assert_eq!(result.unwrap_err(), ToolError::NotAllowAnonymous);
 
-        // set cycles by not owner will fail
-        let new_owner =
+        // an admin holds every other role, and grants them
+        assert!(tool.has_role(&admin, Role::WasmPublisher));
+        assert!(tool.set_public_issuance(&admin, false).is_ok());
+        let result = tool.grant_role(&admin, Role::FeeManager, user);
+        assert!(result.is_ok());
+        assert!(tool.set_cycles_per_token(&user, 1).is_ok());
+        assert_eq!(
+            tool.set_public_issuance(&user, true).unwrap_err(),
+            ToolError::RoleRequired { role: Role::Admin }
+        );
+        assert_eq!(
+            tool.block_symbols(&user, vec!["ICP".to_string()]).unwrap_err(),
+            ToolError::RoleRequired { role: Role::Moderator }
+        );
+
+        // allowlisted issuers can issue when public issuance is disabled
+        assert_eq!(tool.only_issuer(&user).unwrap_err(), ToolError::PublicIssuanceDisabled);
+        let result = tool.grant_role(&admin, Role::Issuer, user);
+        assert!(result.is_ok());
+        assert!(tool.only_issuer(&user).is_ok());
+
+        assert_eq!(
+            tool.get_role_holders(None),
+            vec![
+                RoleHolders {
+                    role: Role::Admin,
+                    holders: vec![admin],
+                },
+                RoleHolders {
+                    role: Role::FeeManager,
+                    holders: vec![user],
+                },
+                RoleHolders {
+                    role: Role::Issuer,
+                    holders: vec![user],
+                },
+            ]
+        );
+        assert_eq!(
+            tool.get_role_holders(Some(Role::Issuer)),
+            vec![RoleHolders {
+                role: Role::Issuer,
+                holders: vec![user],
+            }]
+        );
+
+        // roles are saved on upgrade
+        let mut tool2 = IssuanceTool::new();
+        tool2.load_from_payload(tool.to_payload());
+        assert_eq!(tool2.get_role_holders(None), tool.get_role_holders(None));
+
+        // revoke roles
+        let result = tool.revoke_role(&user, Role::Issuer, user);
+        assert_eq!(result.unwrap_err(), ToolError::RoleRequired { role: Role::Admin });
+        let result = tool.revoke_role(&admin, Role::Issuer, user);
+        assert!(result.is_ok());
+        assert_eq!(tool.only_issuer(&user).unwrap_err(), ToolError::PublicIssuanceDisabled);
+        let result = tool.revoke_role(&owner, Role::Admin, admin);
+        assert!(result.is_ok());
+        assert!(!tool.has_role(&admin, Role::WasmPublisher));
+        assert_eq!(tool.get_role_holders(Some(Role::Admin)), vec![]);
+    }
+
+    // test M-of-N approval of the privileged operations
+    #[test]
+    fn test_multisig() {
+        let mut tool = IssuanceTool::new();
+        let owner =
+            Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe")
+                .unwrap();
+        let signer =
             Principal::from_text("czjfo-ddpvm-6sibl-6zbox-ee5zq-bx3hc-e336t-s6pka-dupmy-wcxqi-fae")
                 .unwrap();
-        let result = tool.set_cycles_per_token(&new_owner, 1);
-        // check result is err
-        assert!(result.is_err());
-        // check error code is OnlyOwnerAllowCallIt
+        let user = Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap();
+        let result = tool.set_owner(&owner, owner);
+        assert!(result.is_ok());
+
+        // invalid configs are rejected
+        let config = |signers: Vec<Principal>, threshold: u64| MultisigConfig {
+            signers,
+            threshold,
+            proposal_ttl: Some(100),
+        };
+        for invalid in [
+            config(vec![], 0),
+            config(vec![owner, signer], 3),
+            config(vec![owner, owner], 1),
+            config(vec![owner, Principal::anonymous()], 1),
+        ] {
+            let result = tool.set_multisig(&owner, Some(invalid));
+            assert!(matches!(result, Err(ToolError::InvalidMultisigConfig { .. })));
+        }
+        let result = tool.set_multisig(&signer, Some(config(vec![owner, signer], 2)));
         assert_eq!(result.unwrap_err(), ToolError::OnlyOwnerAllowCallIt);
-        let result = tool.set_owner(&owner, owner.clone());
+        let result = tool.set_multisig(&owner, Some(config(vec![owner, signer], 2)));
+        assert!(result.is_ok());
+
+        // privileged operations need a proposal
+        assert_eq!(
+            tool.set_cycles_per_token(&owner, 1).unwrap_err(),
+            ToolError::MultisigApprovalRequired
+        );
+        let args = StoreWASMArgs {
+            template: DEFAULT_TOKEN_TEMPLATE.to_string(),
+            version: "0.1.0".to_string(),
+            release_notes: None,
+            wasm_module: test_token_wasm(1),
+        };
+        assert_eq!(
+            tool.set_token_wasm(&owner, args.clone(), 1).unwrap_err(),
+            ToolError::MultisigApprovalRequired
+        );
+        assert_eq!(
+            tool.set_multisig(&owner, None).unwrap_err(),
+            ToolError::MultisigApprovalRequired
+        );
+        // other operations are not affected
+        assert!(tool.set_public_issuance(&owner, true).is_ok());
+
+        // only signers propose and approve
+        let result = tool.propose(&user, ProposalAction::SetCyclesPerToken(1), 1);
+        assert_eq!(result.unwrap_err(), ToolError::OnlySignerAllowCallIt);
+        let proposal = tool
+            .propose(&owner, ProposalAction::SetCyclesPerToken(1), 1)
+            .unwrap();
+        assert_eq!(proposal.status, ProposalStatus::Open);
+        assert_eq!(proposal.expires_at, 101);
+        assert_eq!(proposal.approvals.len(), 1);
+        assert_ne!(tool.cycles_per_token(), 1);
+        let result = tool.approve_proposal(&owner, proposal.proposal_id, 2);
+        assert_eq!(result.unwrap_err(), ToolError::ProposalAlreadyApproved);
+        let result = tool.approve_proposal(&user, proposal.proposal_id, 2);
+        assert_eq!(result.unwrap_err(), ToolError::OnlySignerAllowCallIt);
+        assert_eq!(tool.get_open_proposals(2), vec![proposal.clone()]);
+
+        // executed once the threshold is met
+        let executed = tool.approve_proposal(&signer, proposal.proposal_id, 3).unwrap();
+        assert_eq!(executed.status, ProposalStatus::Executed);
+        assert_eq!(executed.closed_at, Some(3));
+        assert_eq!(
+            executed.approvals,
+            vec![
+                ProposalApproval {
+                    signer: owner,
+                    approved_at: 1,
+                },
+                ProposalApproval {
+                    signer,
+                    approved_at: 3,
+                },
+            ]
+        );
+        assert_eq!(tool.cycles_per_token(), 1);
+        let result = tool.approve_proposal(&signer, proposal.proposal_id, 4);
+        assert_eq!(result.unwrap_err(), ToolError::ProposalNotOpen);
+        assert!(tool.get_open_proposals(4).is_empty());
+
+        // the token wasm is staged by a signer, the proposal refers to it by its hash
+        let wasm_hash = Sha256::digest(&args.wasm_module).to_vec();
+        let result = tool.propose(&signer, ProposalAction::SetTokenWasm { wasm_hash: wasm_hash.clone() }, 5);
+        assert_eq!(result.unwrap_err(), ToolError::WasmUploadNotFound);
+        let upload_args = || BeginWasmUploadArgs {
+            template: args.template.clone(),
+            version: args.version.clone(),
+            release_notes: None,
+        };
+        let result = tool.begin_wasm_upload(&user, upload_args(), 5);
+        assert_eq!(result.unwrap_err(), ToolError::RoleRequired { role: Role::WasmPublisher });
+        let upload_id = tool.begin_wasm_upload(&signer, upload_args(), 5).unwrap();
+        let result = tool.append_wasm_chunk(&owner, upload_id, args.wasm_module.clone());
+        assert_eq!(result.unwrap_err(), ToolError::NotUploader);
+        assert!(tool.append_wasm_chunk(&signer, upload_id, args.wasm_module.clone()).is_ok());
+        let proposal = tool
+            .propose(&signer, ProposalAction::SetTokenWasm { wasm_hash }, 5)
+            .unwrap();
+        let executed = tool.approve_proposal(&owner, proposal.proposal_id, 6).unwrap();
+        assert_eq!(executed.status, ProposalStatus::Executed);
+        assert_eq!(executed.action_hash, proposal.action_hash);
+        assert!(tool.get_wasm_uploads().is_empty());
+        // the proposer is recorded as the uploader
+        assert_eq!(tool.get_token_wasm(DEFAULT_TOKEN_TEMPLATE).unwrap().uploader, signer);
+
+        // a failing action is recorded, the upload is aborted before the proposal is approved
+        let wasm_module = test_token_wasm(3);
+        let upload_id = tool.begin_wasm_upload(&signer, upload_args(), 7).unwrap();
+        assert!(tool.append_wasm_chunk(&signer, upload_id, wasm_module.clone()).is_ok());
+        let wasm_hash = Sha256::digest(&wasm_module).to_vec();
+        let proposal = tool
+            .propose(&owner, ProposalAction::SetTokenWasm { wasm_hash }, 7)
+            .unwrap();
+        assert!(tool.abort_wasm_upload(&signer, upload_id).is_ok());
+        let failed = tool.approve_proposal(&signer, proposal.proposal_id, 8).unwrap();
+        assert_eq!(
+            failed.status,
+            ProposalStatus::Failed {
+                error: ToolError::WasmUploadNotFound.to_string()
+            }
+        );
+
+        // expired and cancelled proposals can't be approved
+        let proposal = tool
+            .propose(&owner, ProposalAction::SetE8sPerToken(1), 10)
+            .unwrap();
+        assert_eq!(tool.get_proposal(proposal.proposal_id, 110).unwrap().status, ProposalStatus::Expired);
+        let result = tool.approve_proposal(&signer, proposal.proposal_id, 110);
+        assert_eq!(result.unwrap_err(), ToolError::ProposalExpired);
+        let proposal = tool
+            .propose(&signer, ProposalAction::SetE8sPerToken(1), 120)
+            .unwrap();
+        // only the proposer can cancel it, the owner has no veto over the signers
+        let result = tool.cancel_proposal(&user, proposal.proposal_id, 121);
+        assert_eq!(result.unwrap_err(), ToolError::OnlyProposerAllowCallIt);
+        let result = tool.cancel_proposal(&owner, proposal.proposal_id, 121);
+        assert_eq!(result.unwrap_err(), ToolError::OnlyProposerAllowCallIt);
+        let result = tool.cancel_proposal(&signer, proposal.proposal_id, 121);
+        assert!(result.is_ok());
+        let result = tool.approve_proposal(&owner, proposal.proposal_id, 122);
+        assert_eq!(result.unwrap_err(), ToolError::ProposalNotOpen);
+        assert_ne!(tool.e8s_per_token(), 1);
+
+        // proposals are saved on upgrade
+        let mut tool2 = IssuanceTool::new();
+        tool2.load_from_payload(tool.to_payload());
+        assert_eq!(tool2.get_proposal(1, 200), tool.get_proposal(1, 200));
+        assert_eq!(tool2.get_multisig(), tool.get_multisig());
+
+        // disable the multisig by a proposal
+        let proposal = tool
+            .propose(&owner, ProposalAction::SetMultisig(None), 200)
+            .unwrap();
+        tool.approve_proposal(&signer, proposal.proposal_id, 201).unwrap();
+        assert_eq!(tool.get_multisig(), None);
+        assert!(tool.set_e8s_per_token(&owner, 1).is_ok());
+    }
+
+    // test the audit log pagination and filters
+    #[test]
+    fn test_events() {
+        let mut tool = IssuanceTool::new();
+        let owner =
+            Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe")
+                .unwrap();
+        let signer =
+            Principal::from_text("czjfo-ddpvm-6sibl-6zbox-ee5zq-bx3hc-e336t-s6pka-dupmy-wcxqi-fae")
+                .unwrap();
+        let token_id = Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap();
+        tool.record_event(owner, ToolEvent::OwnerSet { owner }, 1);
+        tool.record_event(owner, ToolEvent::CyclesPerTokenSet { cycles_per_token: 1 }, 2);
+        tool.record_event(
+            owner,
+            ToolEvent::RoleGranted {
+                role: Role::Issuer,
+                principal: signer,
+            },
+            3,
+        );
+        tool.record_event(
+            signer,
+            ToolEvent::TokenIssued {
+                issuance_id: 1,
+                token_id,
+            },
+            4,
+        );
+        tool.record_event(owner, ToolEvent::E8sPerTokenSet { e8s_per_token: 1 }, 5);
+
+        // pages of the whole log
+        let page = tool.get_events(0, 2, None);
+        assert_eq!(page.total, 5);
+        assert_eq!(page.events.iter().map(|e| e.event_id).collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(page.events[1].timestamp, 2);
+        assert_eq!(page.next_start, Some(2));
+        let page = tool.get_events(4, 2, None);
+        assert_eq!(page.events.len(), 1);
+        assert_eq!(page.next_start, None);
+        assert!(tool.get_events(5, 2, None).events.is_empty());
+
+        // filter by event type
+        let filter = EventFilter {
+            event_type: Some(EventType::Price),
+            principal: None,
+        };
+        let page = tool.get_events(0, 1, Some(filter.clone()));
+        assert_eq!(page.events[0].event, ToolEvent::CyclesPerTokenSet { cycles_per_token: 1 });
+        assert_eq!(page.next_start, Some(4));
+        let page = tool.get_events(4, 1, Some(filter));
+        assert_eq!(page.events[0].event, ToolEvent::E8sPerTokenSet { e8s_per_token: 1 });
+        assert_eq!(page.next_start, None);
+
+        // filter by the caller or a principal of the event
+        let filter = EventFilter {
+            event_type: None,
+            principal: Some(signer),
+        };
+        let page = tool.get_events(0, 10, Some(filter));
+        assert_eq!(page.events.iter().map(|e| e.event_id).collect::<Vec<_>>(), vec![2, 3]);
+        let filter = EventFilter {
+            event_type: Some(EventType::Issuance),
+            principal: Some(token_id),
+        };
+        assert_eq!(tool.get_events(0, 10, Some(filter)).events.len(), 1);
+
+        // proposals are recorded
+        let result = tool.set_owner(&owner, owner);
+        assert!(result.is_ok());
+        let result = tool.set_multisig(
+            &owner,
+            Some(MultisigConfig {
+                signers: vec![owner, signer],
+                threshold: 2,
+                proposal_ttl: None,
+            }),
+        );
+        assert!(result.is_ok());
+        let proposal = tool
+            .propose(&owner, ProposalAction::SetCyclesPerToken(2), 6)
+            .unwrap();
+        tool.approve_proposal(&signer, proposal.proposal_id, 7).unwrap();
+        let filter = EventFilter {
+            event_type: None,
+            principal: Some(signer),
+        };
+        let events: Vec<ToolEvent> = tool
+            .get_events(5, 10, Some(filter))
+            .events
+            .into_iter()
+            .map(|e| e.event)
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                ToolEvent::ProposalApproved {
+                    proposal_id: proposal.proposal_id
+                },
+                ToolEvent::CyclesPerTokenSet { cycles_per_token: 2 },
+                ToolEvent::ProposalExecuted {
+                    proposal_id: proposal.proposal_id
+                },
+            ]
+        );
+        assert_eq!(
+            tool.get_events(5, 1, None).events[0].event,
+            ToolEvent::ProposalCreated {
+                proposal_id: proposal.proposal_id,
+                action_hash: proposal.action_hash,
+            }
+        );
+
+        // the log is saved on upgrade
+        let mut tool2 = IssuanceTool::new();
+        tool2.load_from_payload(tool.to_payload());
+        assert_eq!(tool2.get_events(0, 200, None).events, tool.get_events(0, 200, None).events);
+    }
+
+    // test symbol uniqueness, blocked symbols and reservations
+    #[test]
+    fn test_symbol_policy() {
+        let mut tool = IssuanceTool::new();
+        let owner =
+            Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe")
+                .unwrap();
+        let issuer =
+            Principal::from_text("czjfo-ddpvm-6sibl-6zbox-ee5zq-bx3hc-e336t-s6pka-dupmy-wcxqi-fae")
+                .unwrap();
+        let result = tool.set_owner(&owner, owner);
+        assert!(result.is_ok());
+        let result = tool.set_public_issuance(&owner, true);
+        assert!(result.is_ok());
+        let new_token = |issuer: Principal, token_id: &str, symbol: &str, timestamp: u64| TokenInfo {
+            issuer,
+            token_id: Principal::from_text(token_id).unwrap(),
+            name: "test".to_string(),
+            symbol: symbol.to_string(),
+            decimals: 18,
+            total_supply: 100,
+            fee: Fee {
+                minimum: Nat::from(1),
+                rate: Nat::from(10000),
+            },
+            template: DEFAULT_TOKEN_TEMPLATE.to_string(),
+            controllers: vec![],
+            immutable: false,
+            blackhole_canister_id: None,
+            module_hash: None,
+            timestamp,
+        };
+
+        // duplicate symbols are allowed by default
+        let result = tool.add_token(&owner, new_token(owner, "g7cye-cyaaa-aaaak-aaa5a-cai", "DLD", 0));
+        assert!(result.is_ok());
+        let result = tool.add_token(&issuer, new_token(issuer, "rrkah-fqaaa-aaaaa-aaaaq-cai", "dld", 0));
+        assert!(result.is_ok());
+
+        // with the unique policy, the symbol is compared case-insensitively
+        let result = tool.set_unique_symbols(&owner, true);
+        assert!(result.is_ok());
+        let result = tool.add_token(&issuer, new_token(issuer, "ryjl3-tyaaa-aaaaa-aaaba-cai", " Dld", 0));
+        assert_eq!(
+            result.unwrap_err(),
+            ToolError::SymbolAlreadyExists {
+                symbol: " Dld".to_string(),
+            }
+        );
+        // updating the token itself does not conflict
+        assert!(tool
+            .check_symbol(&owner, "DLD", Some(&Principal::from_text("g7cye-cyaaa-aaaak-aaa5a-cai").unwrap()), 0)
+            .is_err());
+        let result = tool.set_unique_symbols(&owner, false);
+        assert!(result.is_ok());
+        assert!(tool
+            .check_symbol(&owner, "DLD", Some(&Principal::from_text("g7cye-cyaaa-aaaak-aaa5a-cai").unwrap()), 0)
+            .is_ok());
+
+        // blocked symbols can only be issued by the owner
+        let result = tool.block_symbols(&issuer, vec!["ICP".to_string()]);
+        assert_eq!(result.unwrap_err(), ToolError::RoleRequired { role: Role::Moderator });
+        let result = tool.block_symbols(&owner, vec!["ICP".to_string()]);
+        assert!(result.is_ok());
+        assert_eq!(tool.get_blocked_symbols(), vec!["icp".to_string()]);
+        assert_eq!(
+            tool.check_symbol(&issuer, "icp", None, 0).unwrap_err(),
+            ToolError::SymbolBlocked {
+                symbol: "icp".to_string(),
+            }
+        );
+        assert!(tool.check_symbol(&owner, "icp", None, 0).is_ok());
+        let result = tool.unblock_symbols(&owner, vec!["icp".to_string()]);
+        assert!(result.is_ok());
+        assert!(tool.check_symbol(&issuer, "icp", None, 0).is_ok());
+
+        // reserve a symbol
+        let result = tool.set_symbol_reservation_ttl(&owner, 100);
+        assert!(result.is_ok());
+        let reservation = tool.reserve_symbol(&issuer, "NEW", 10).unwrap();
+        assert_eq!(reservation.holder, issuer);
+        assert_eq!(reservation.expires_at, 110);
+        assert_eq!(tool.get_symbol_reservation("new", 50), Some(reservation));
+        // a symbol no token can use can't be reserved
+        let result = tool.reserve_symbol(&issuer, " NEW2", 10);
+        assert!(matches!(result.unwrap_err(), ToolError::InvalidTokenSymbol { .. }));
+        let result = tool.reserve_symbol(&owner, "new", 50);
+        assert_eq!(
+            result.unwrap_err(),
+            ToolError::SymbolReserved {
+                symbol: "new".to_string(),
+            }
+        );
+        let result = tool.add_token(&owner, new_token(owner, "ryjl3-tyaaa-aaaaa-aaaba-cai", "NEW", 50));
+        assert!(result.is_err());
+        // the reservation expires
+        assert_eq!(tool.get_symbol_reservation("new", 110), None);
+        assert!(tool.check_symbol(&owner, "new", None, 110).is_ok());
+        // the holder issues the token, the reservation is used up
+        let result = tool.add_token(&issuer, new_token(issuer, "ryjl3-tyaaa-aaaaa-aaaba-cai", "NEW", 50));
+        assert!(result.is_ok());
+        assert_eq!(tool.get_symbol_reservation("new", 50), None);
+
+        // release a reservation
+        let result = tool.reserve_symbol(&issuer, "REL", 0);
+        assert!(result.is_ok());
+        assert_eq!(
+            tool.release_symbol(&owner, "REL").unwrap_err(),
+            ToolError::SymbolReservationNotFound
+        );
+        assert!(tool.release_symbol(&issuer, "rel").is_ok());
+        assert_eq!(tool.get_symbol_reservation("rel", 0), None);
+
+        // limited reservations per principal
+        for i in 0..MAX_SYMBOL_RESERVATIONS {
+            let result = tool.reserve_symbol(&issuer, &format!("R{}", i), 0);
+            assert!(result.is_ok());
+        }
+        let result = tool.reserve_symbol(&issuer, "RX", 0);
+        assert_eq!(
+            result.unwrap_err(),
+            ToolError::TooManySymbolReservations {
+                max: MAX_SYMBOL_RESERVATIONS as u64,
+            }
+        );
+    }
+
+    // test certified token registry
+    #[test]
+    fn test_certified_tokens() {
+        let mut tool = IssuanceTool::new();
+        let owner =
+            Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe")
+                .unwrap();
+        let token_id = Principal::from_text("g7cye-cyaaa-aaaak-aaa5a-cai").unwrap();
+        let result = tool.set_owner(&owner, owner);
+        assert!(result.is_ok());
+        let empty_root = tool.certified_tokens_root();
+        let mut token_info = TokenInfo {
+            issuer: owner,
+            token_id,
+            name: "test".to_string(),
+            symbol: "TST".to_string(),
+            decimals: 18,
+            total_supply: 100,
+            fee: Fee {
+                minimum: Nat::from(1),
+                rate: Nat::from(10000),
+            },
+            template: DEFAULT_TOKEN_TEMPLATE.to_string(),
+            controllers: vec![],
+            immutable: false,
+            blackhole_canister_id: None,
+            module_hash: None,
+            timestamp: 0,
+        };
+        let result = tool.add_token(&owner, token_info.clone());
         assert!(result.is_ok());
-        let result = tool.set_cycles_per_token(&owner, 100);
+        let root = tool.certified_tokens_root();
+        assert_ne!(root, empty_root);
+        assert_eq!(
+            tool.certified_tokens.get(token_id.as_slice()),
+            Some(&crate::certification::token_hash(&token_info))
+        );
+
+        let certified = tool.get_certified_token(&token_id);
+        assert_eq!(certified.token, Some(token_info.clone()));
+        assert!(!certified.witness.is_empty());
+        let certified = tool.get_certified_token(&owner);
+        assert_eq!(certified.token, None);
+        let certified = tool.get_certified_tokens(0, 10).unwrap();
+        assert_eq!(certified.tokens, vec![token_info.clone()]);
+
+        // updating the token changes the root, reloading keeps it
+        token_info.name = "test2".to_string();
+        let result = tool.add_token(&owner, token_info);
         assert!(result.is_ok());
-        assert_eq!(tool.cycles_per_token, 100);
+        let root = tool.certified_tokens_root();
+        let mut tool2 = IssuanceTool::new();
+        tool2.load_from_payload(tool.to_payload());
+        assert_eq!(tool2.certified_tokens_root(), root);
     }
 
-    // test add token
+    // test issuance steps
     #[test]
-    fn test_add_token() {
+    fn test_issuance() {
         let mut tool = IssuanceTool::new();
         let owner =
             Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe")
                 .unwrap();
-        let new_owner =
+        let issuer =
             Principal::from_text("czjfo-ddpvm-6sibl-6zbox-ee5zq-bx3hc-e336t-s6pka-dupmy-wcxqi-fae")
                 .unwrap();
-        let result = tool.set_owner(&owner, owner.clone());
-        assert!(result.is_ok());
+        let tool_id = Principal::from_text("rkp4c-7iaaa-aaaaa-aaaca-cai").unwrap();
         let token_id = Principal::from_text("g7cye-cyaaa-aaaak-aaa5a-cai").unwrap();
+        let result = tool.set_owner(&owner, owner);
+        assert!(result.is_ok());
+        let result = tool.set_public_issuance(&owner, true);
+        assert!(result.is_ok());
+        let args = CreateTokenArgs {
+            sub_account: None,
+            logo: Some(b"GIF89a0000".to_vec()),
+            name: "test".to_string(),
+            symbol: "TST".to_string(),
+            decimals: 18,
+            total_supply: 100,
+            fee: Fee {
+                minimum: Nat::from(1),
+                rate: Nat::from(10000),
+            },
+            template: None,
+            settings: None,
+        };
+        let order = tool.place_order(&issuer, &tool_id, args.clone(), 0).unwrap();
 
-        // build a instance of TokenInfo
+        let issuance = tool.begin_issuance(&issuer, None, args, vec![issuer], Some(order.order_id), 1).unwrap();
+        assert_eq!(issuance.issuance_id, 1);
+        assert_eq!(issuance.step, IssuanceStep::Pending);
+        assert_eq!(tool.get_issuance(1).unwrap(), issuance);
+        assert_eq!(tool.get_issuance(2).unwrap_err(), ToolError::IssuanceNotFound);
+
+        // only the owner and the issuer can resume the issuance
+        assert!(tool.only_issuance_operator(&owner, 1).is_ok());
+        assert!(tool.only_issuance_operator(&issuer, 1).is_ok());
+        assert_eq!(
+            tool.only_issuance_operator(&tool_id, 1).unwrap_err(),
+            ToolError::OnlyOwnerAllowCallIt
+        );
+
+        // a failed step keeps the issuance at its step
+        let result = tool.set_issuance_canister(1, token_id, 2);
+        assert!(result.is_ok());
+        let error = ToolError::InstallTokenCodeFailed {
+            reason: "trapped".to_string(),
+        };
+        let result = tool.set_issuance_error(1, &error, 3);
+        assert!(result.is_ok());
+        let issuance = tool.get_issuance(1).unwrap();
+        assert_eq!(issuance.canister_id, Some(token_id));
+        assert_eq!(issuance.step, IssuanceStep::Pending);
+        assert_eq!(issuance.error, Some(error.to_string()));
+        assert_eq!(issuance.updated_at, 3);
+        // the order keeps its status, with the error, so it can be resumed by retryOrder
+        let failed_order = tool.get_order(order.order_id).unwrap();
+        assert_eq!(failed_order.status, OrderStatus::Pending);
+        assert_eq!(failed_order.error, Some(error.to_string()));
+        assert_eq!(tool.get_order_issuance(order.order_id), Some(1));
+        assert_eq!(tool.get_stuck_issuances(&owner).unwrap(), vec![issuance]);
+        assert_eq!(
+            tool.get_stuck_issuances(&issuer).unwrap_err(),
+            ToolError::RoleRequired { role: Role::Admin }
+        );
+
+        // the issuance holds the quota of the issuer and its symbol until it is registered
+        assert!(tool.set_issuer_quota(&owner, 1).is_ok());
+        assert!(tool.set_unique_symbols(&owner, true).is_ok());
+        let args = tool.get_issuance(1).unwrap().args;
+        let mut args2 = args.clone();
+        args2.symbol = "TS2".to_string();
+        assert_eq!(
+            tool.begin_issuance(&issuer, None, args2, vec![issuer], None, 3).unwrap_err(),
+            ToolError::IssuerQuotaExceeded { quota: 1 }
+        );
+        assert_eq!(
+            tool.begin_issuance(&owner, None, args, vec![owner], None, 3).unwrap_err(),
+            ToolError::SymbolAlreadyExists {
+                symbol: "TST".to_string(),
+            }
+        );
+        // policy changes after the issuance started don't stop its registration
+        assert!(tool.block_symbols(&owner, vec!["TST".to_string()]).is_ok());
+
+        // resume the issuance
+        let result = tool.set_issuance_installed(1, vec![1; 32], 4);
+        assert!(result.is_ok());
+        assert_eq!(tool.get_issuance(1).unwrap().step, IssuanceStep::Installed);
+        assert_eq!(tool.get_issuance(1).unwrap().error, None);
+        let result = tool.register_issuance(1, 5);
+        assert!(result.is_ok());
+        let token = tool.get_token_by_id(&token_id).unwrap();
+        assert_eq!(token.issuer, issuer);
+        assert_eq!(token.controllers, vec![issuer]);
+        assert_eq!(token.module_hash, Some(vec![1; 32]));
+        assert!(!token.immutable);
+        assert_eq!(token.timestamp, 5);
+        assert!(tool.get_token_logo(&token_id).is_some());
+        assert_eq!(tool.get_issuance(1).unwrap().step, IssuanceStep::Registered);
+        assert!(tool.unblock_symbols(&owner, vec!["TST".to_string()]).is_ok());
+        assert!(tool.set_unique_symbols(&owner, false).is_ok());
+        assert!(tool.set_issuer_quota(&owner, 0).is_ok());
+        let result = tool.set_issuance_step(1, IssuanceStep::Completed, 6);
+        assert!(result.is_ok());
+        assert_eq!(
+            tool.get_order(order.order_id).unwrap().status,
+            OrderStatus::Issued { token_id }
+        );
+        assert_eq!(tool.get_stuck_issuances(&owner).unwrap(), vec![]);
+        assert_eq!(
+            tool.only_issuance_operator(&issuer, 1).unwrap_err(),
+            ToolError::IssuanceAlreadyCompleted
+        );
+
+        // the controllers in the settings replace the default controllers
+        let mut args = tool.get_issuance(1).unwrap().args;
+        args.settings = Some(TokenSettings {
+            controllers: Some(vec![]),
+            immutable: None,
+            compute_allocation: None,
+            memory_allocation: None,
+            freezing_threshold: Some(Nat::from(2_592_000)),
+        });
+        let issuance = tool.begin_issuance(&issuer, None, args, vec![issuer], None, 7).unwrap();
+        assert_eq!(issuance.controllers, vec![]);
+
+        // immutable tokens are handed over to the blackhole canister, and require one
+        let mut args = issuance.args.clone();
+        args.settings = Some(TokenSettings {
+            controllers: None,
+            immutable: Some(true),
+            compute_allocation: None,
+            memory_allocation: None,
+            freezing_threshold: None,
+        });
+        let result = tool.begin_issuance(&issuer, None, args.clone(), vec![issuer], None, 8);
+        assert!(matches!(result.unwrap_err(), ToolError::InvalidTokenSettings { .. }));
+        let result = tool.place_order(&issuer, &owner, args.clone(), 8);
+        assert!(matches!(result.unwrap_err(), ToolError::InvalidTokenSettings { .. }));
+        let blackhole = Principal::from_text("e3mmv-5qaaa-aaaah-aadma-cai").unwrap();
+        let result = tool.set_blackhole_canister(&issuer, Some(blackhole));
+        assert_eq!(result.unwrap_err(), ToolError::RoleRequired { role: Role::Admin });
+        let result = tool.set_blackhole_canister(&owner, Some(blackhole));
+        assert!(result.is_ok());
+        let issuance = tool.begin_issuance(&issuer, None, args, vec![issuer], None, 8).unwrap();
+        assert!(issuance.immutable);
+        assert_eq!(issuance.controllers, vec![blackhole]);
+        assert_eq!(issuance.blackhole_canister_id, Some(blackhole));
 
+        // issuances are saved on upgrade
+        let mut tool2 = IssuanceTool::new();
+        tool2.load_from_payload(tool.to_payload());
+        assert_eq!(tool2.get_issuance(1).unwrap(), tool.get_issuance(1).unwrap());
+        assert_eq!(tool2.next_issuance_id, 4);
+        assert_eq!(tool2.blackhole_canister_id, Some(blackhole));
+    }
+
+    // test verifying a token against its recorded state
+    #[test]
+    fn test_verify_token() {
+        let mut tool = IssuanceTool::new();
+        let owner =
+            Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe")
+                .unwrap();
+        let blackhole = Principal::from_text("e3mmv-5qaaa-aaaah-aadma-cai").unwrap();
+        let token_id = Principal::from_text("g7cye-cyaaa-aaaak-aaa5a-cai").unwrap();
+        let result = tool.set_owner(&owner, owner);
+        assert!(result.is_ok());
+        let result = tool.set_blackhole_canister(&owner, Some(blackhole));
+        assert!(result.is_ok());
         let token_info = TokenInfo {
-            issuer: owner.clone(),
-            token_id: token_id.clone(),
+            issuer: owner,
+            token_id,
             name: "test".to_string(),
             symbol: "TST".to_string(),
             decimals: 18,
@@ -299,45 +3601,436 @@ mod tests {
                 minimum: Nat::from(1),
                 rate: Nat::from(10000),
             },
+            template: DEFAULT_TOKEN_TEMPLATE.to_string(),
+            controllers: vec![blackhole],
+            immutable: true,
+            blackhole_canister_id: Some(blackhole),
+            module_hash: Some(vec![1; 32]),
             timestamp: 0,
         };
         let result = tool.add_token(&owner, token_info.clone());
         assert!(result.is_ok());
-        assert_eq!(tool.tokens.len(), 1);
-        // get token by id, then check token info
-        let token = tool.get_token_by_id(&token_id).unwrap();
-        assert_eq!(token.token_id, token_id);
-        assert_eq!(token.name, "test".to_string());
-        assert_eq!(token.symbol, "TST".to_string());
-        assert_eq!(token.decimals, 18);
-        assert_eq!(token.total_supply, 100);
-        assert_eq!(token.fee.minimum, Nat::from(1));
-        assert_eq!(token.fee.rate, Nat::from(10000));
-        assert_eq!(token.timestamp, 0);
-        assert_eq!(token.issuer, owner);
-        // add token by not owner will fail
-        let result = tool.add_token(&new_owner, token_info.clone());
-        assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), ToolError::OnlyOwnerAllowCallIt);
+        // the status of a blackholed token is read through its blackhole, even once the tool uses another one
+        let tool_id = Principal::from_text("rkp4c-7iaaa-aaaaa-aaaca-cai").unwrap();
+        assert!(tool.set_blackhole_canister(&owner, None).is_ok());
+        assert_eq!(tool.get_status_canister(&token_info, &tool_id), Ok(blackhole));
+        // a token handed over to other controllers can't be verified by the tool, unless it is one of them
+        let mut handed_over = token_info.clone();
+        handed_over.immutable = false;
+        handed_over.blackhole_canister_id = None;
+        handed_over.controllers = vec![owner];
+        assert_eq!(
+            tool.get_status_canister(&handed_over, &tool_id),
+            Err(ToolError::TokenNotVerifiable)
+        );
+        handed_over.controllers = vec![owner, tool_id];
+        assert_eq!(
+            tool.get_status_canister(&handed_over, &tool_id),
+            Ok(Principal::management_canister())
+        );
+        let status = |controllers: Vec<Principal>, module_hash: Option<Vec<u8>>| TokenCanisterStatus {
+            status: CanisterStatus::Running,
+            module_hash,
+            settings: DefiniteCanisterSettingsArgs {
+                controllers,
+                compute_allocation: Nat::from(0),
+                memory_allocation: Nat::from(0),
+                freezing_threshold: Nat::from(0),
+            },
+        };
+
+        let verification = tool
+            .verify_token(&token_id, status(vec![blackhole], Some(vec![1; 32])))
+            .unwrap();
+        assert!(verification.verified);
+        assert!(verification.immutable);
+        let verification = tool
+            .verify_token(&token_id, status(vec![blackhole, owner], Some(vec![1; 32])))
+            .unwrap();
+        assert!(!verification.controllers_match);
+        assert!(!verification.verified);
+        let verification = tool
+            .verify_token(&token_id, status(vec![blackhole], Some(vec![2; 32])))
+            .unwrap();
+        assert!(!verification.module_hash_match);
+        assert!(!verification.verified);
+
+        // immutable tokens can't be upgraded through the tool
+        assert_eq!(
+            tool.only_token_upgrader(&owner, &token_id, &[owner]).unwrap_err(),
+            ToolError::TokenIsImmutable
+        );
     }
 
-    // test get set token wasm
+        // test get set token wasm
     #[test]
     fn test_get_set_token_wasm() {
         let mut tool = IssuanceTool::new();
         let owner =
             Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe")
                 .unwrap();
-        let result = tool.set_owner(&owner, owner.clone());
+        let result = tool.set_owner(&owner, owner);
         assert!(result.is_ok());
         // set token wasm, check result is ok
-        let token_wasm = vec![1, 2, 3, 4, 5];
-        let result = tool.set_token_wasm(&owner, token_wasm.clone());
+        let token_wasm = test_token_wasm(1);
+        let args = StoreWASMArgs {
+            template: DEFAULT_TOKEN_TEMPLATE.to_string(),
+            version: "0.1.0".to_string(),
+            release_notes: Some("first release".to_string()),
+            wasm_module: token_wasm.clone(),
+        };
+        let result = tool.set_token_wasm(&owner, args, 1);
         // check result is ok
         assert!(result.is_ok());
+        let info = result.unwrap();
+        assert_eq!(info.uploader, owner);
+        assert_eq!(info.size, token_wasm.len() as u64);
+        assert_eq!(info.hash, Sha256::digest(&token_wasm).to_vec());
         // get token wasm, check the wasm is equal token_wasm
-        let token_wasm2 = tool.get_token_wasm().unwrap();
+        let token_wasm2 = tool.get_install_module(DEFAULT_TOKEN_TEMPLATE).unwrap();
         assert_eq!(token_wasm, token_wasm2);
+
+        // upload the same module again will fail
+        let args = StoreWASMArgs {
+            template: DEFAULT_TOKEN_TEMPLATE.to_string(),
+            version: "0.2.0".to_string(),
+            release_notes: None,
+            wasm_module: token_wasm.clone(),
+        };
+        let result = tool.set_token_wasm(&owner, args, 2);
+        assert_eq!(result.unwrap_err(), ToolError::TokenWasmAlreadyExists);
+        // upload an existing version will fail
+        let args = StoreWASMArgs {
+            template: DEFAULT_TOKEN_TEMPLATE.to_string(),
+            version: "0.1.0".to_string(),
+            release_notes: None,
+            wasm_module: test_token_wasm(2),
+        };
+        let result = tool.set_token_wasm(&owner, args, 2);
+        assert_eq!(
+            result.unwrap_err(),
+            ToolError::TokenWasmVersionAlreadyExists {
+                template: DEFAULT_TOKEN_TEMPLATE.to_string(),
+                version: "0.1.0".to_string(),
+            }
+        );
+
+        // the latest version of the template is installed
+        let args = StoreWASMArgs {
+            template: DEFAULT_TOKEN_TEMPLATE.to_string(),
+            version: "0.2.0".to_string(),
+            release_notes: None,
+            wasm_module: test_token_wasm(2),
+        };
+        let result = tool.set_token_wasm(&owner, args, 3);
+        assert!(result.is_ok());
+        let args = StoreWASMArgs {
+            template: "dft_mintable".to_string(),
+            version: "0.1.0".to_string(),
+            release_notes: None,
+            wasm_module: test_token_wasm(3),
+        };
+        let result = tool.set_token_wasm(&owner, args, 4);
+        assert!(result.is_ok());
+        assert_eq!(
+            tool.get_install_module(DEFAULT_TOKEN_TEMPLATE).unwrap(),
+            test_token_wasm(2)
+        );
+        assert_eq!(tool.get_install_module("dft_mintable").unwrap(), test_token_wasm(3));
+        assert_eq!(
+            tool.get_token_wasm("dft_burnable").unwrap_err(),
+            ToolError::TokenTemplateNotFound {
+                template: "dft_burnable".to_string(),
+            }
+        );
+
+        // list versions
+        let versions = tool.get_token_wasm_versions(None);
+        assert_eq!(versions.len(), 3);
+        let versions = tool.get_token_wasm_versions(Some(DEFAULT_TOKEN_TEMPLATE.to_string()));
+        assert_eq!(
+            versions.iter().map(|v| v.version.as_str()).collect::<Vec<_>>(),
+            vec!["0.1.0", "0.2.0"]
+        );
+
+        // set token wasm by not owner will fail
+        let new_owner =
+            Principal::from_text("czjfo-ddpvm-6sibl-6zbox-ee5zq-bx3hc-e336t-s6pka-dupmy-wcxqi-fae")
+                .unwrap();
+        let args = StoreWASMArgs {
+            template: DEFAULT_TOKEN_TEMPLATE.to_string(),
+            version: "0.3.0".to_string(),
+            release_notes: None,
+            wasm_module: test_token_wasm(4),
+        };
+        let result = tool.set_token_wasm(&new_owner, args, 5);
+        assert_eq!(result.unwrap_err(), ToolError::RoleRequired { role: Role::WasmPublisher });
+
+        // invalid wasm module will fail
+        let args = StoreWASMArgs {
+            template: DEFAULT_TOKEN_TEMPLATE.to_string(),
+            version: "0.3.0".to_string(),
+            release_notes: None,
+            wasm_module: vec![1, 2, 3, 4, 5],
+        };
+        let result = tool.set_token_wasm(&owner, args, 5);
+        assert_eq!(result.unwrap_err(), ToolError::InvalidTokenWasmHeader);
+    }
+
+    // test chunked token wasm upload
+    #[test]
+    fn test_chunked_wasm_upload() {
+        let mut tool = IssuanceTool::new();
+        let owner =
+            Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe")
+                .unwrap();
+        let result = tool.set_owner(&owner, owner);
+        assert!(result.is_ok());
+        let token_wasm = test_token_wasm(1);
+        let begin_args = || BeginWasmUploadArgs {
+            template: DEFAULT_TOKEN_TEMPLATE.to_string(),
+            version: "0.1.0".to_string(),
+            release_notes: None,
+        };
+
+        // upload in chunks, then commit
+        let upload_id = tool.begin_wasm_upload(&owner, begin_args(), 1).unwrap();
+        for chunk in token_wasm.chunks(16) {
+            let result = tool.append_wasm_chunk(&owner, upload_id, chunk.to_vec());
+            assert!(result.is_ok());
+        }
+        // the staged upload is not in the registry
+        assert!(tool.get_token_wasm(DEFAULT_TOKEN_TEMPLATE).is_err());
+        let uploads = tool.get_wasm_uploads();
+        assert_eq!(uploads.len(), 1);
+        assert_eq!(uploads[0].size, token_wasm.len() as u64);
+        let hash = Sha256::digest(&token_wasm).to_vec();
+        let info = tool.commit_wasm_upload(&owner, upload_id, hash, 2).unwrap();
+        assert_eq!(info.size, token_wasm.len() as u64);
+        assert_eq!(tool.get_install_module(DEFAULT_TOKEN_TEMPLATE).unwrap(), token_wasm);
+        assert!(tool.wasm_uploads.is_empty());
+
+        // commit with a wrong hash will fail
+        let upload_id = tool.begin_wasm_upload(&owner, begin_args(), 3).unwrap();
+        let result = tool.append_wasm_chunk(&owner, upload_id, test_token_wasm(2));
+        assert!(result.is_ok());
+        let result = tool.commit_wasm_upload(&owner, upload_id, vec![0; 32], 4);
+        assert!(matches!(result.unwrap_err(), ToolError::TokenWasmHashMismatch { .. }));
+
+        // abort an upload
+        let upload_id = tool.begin_wasm_upload(&owner, begin_args(), 5).unwrap();
+        assert!(tool.abort_wasm_upload(&owner, upload_id).is_ok());
+        assert_eq!(
+            tool.append_wasm_chunk(&owner, upload_id, vec![1]).unwrap_err(),
+            ToolError::WasmUploadNotFound
+        );
+
+        // too large upload will fail
+        let upload_id = tool.begin_wasm_upload(&owner, begin_args(), 6).unwrap();
+        let result = tool.append_wasm_chunk(&owner, upload_id, vec![0; MAX_TOKEN_WASM_SIZE + 1]);
+        assert!(matches!(result.unwrap_err(), ToolError::TokenWasmTooLarge { .. }));
+
+        // only owner can upload
+        let new_owner =
+            Principal::from_text("czjfo-ddpvm-6sibl-6zbox-ee5zq-bx3hc-e336t-s6pka-dupmy-wcxqi-fae")
+                .unwrap();
+        let result = tool.begin_wasm_upload(&new_owner, begin_args(), 7);
+        assert_eq!(result.unwrap_err(), ToolError::RoleRequired { role: Role::WasmPublisher });
+
+        // only the uploader can write to, commit or abort the upload
+        assert!(tool.grant_role(&owner, Role::WasmPublisher, new_owner).is_ok());
+        assert_eq!(
+            tool.append_wasm_chunk(&new_owner, upload_id, vec![1]).unwrap_err(),
+            ToolError::NotUploader
+        );
+        assert_eq!(
+            tool.commit_wasm_upload(&new_owner, upload_id, vec![0; 32], 8).unwrap_err(),
+            ToolError::NotUploader
+        );
+        assert_eq!(tool.abort_wasm_upload(&new_owner, upload_id).unwrap_err(), ToolError::NotUploader);
+
+        // the staged uploads are limited in number and total size
+        let mut upload_ids = Vec::new();
+        while tool.wasm_uploads.len() < MAX_WASM_UPLOADS {
+            upload_ids.push(tool.begin_wasm_upload(&new_owner, begin_args(), 9).unwrap());
+        }
+        assert_eq!(
+            tool.begin_wasm_upload(&owner, begin_args(), 10).unwrap_err(),
+            ToolError::TooManyWasmUploads {
+                limit: MAX_WASM_UPLOADS as u64
+            }
+        );
+        assert!(tool.append_wasm_chunk(&owner, upload_id, vec![0; MAX_TOKEN_WASM_SIZE]).is_ok());
+        let result = tool.append_wasm_chunk(&new_owner, upload_ids[0], vec![0; MAX_TOKEN_WASM_SIZE]);
+        assert!(result.is_ok());
+        let result = tool.append_wasm_chunk(&new_owner, upload_ids[1], vec![0]);
+        assert!(matches!(result.unwrap_err(), ToolError::StagedWasmTooLarge { .. }));
+        assert!(tool.abort_wasm_upload(&owner, upload_id).is_ok());
+        assert!(tool.append_wasm_chunk(&new_owner, upload_ids[1], vec![0]).is_ok());
+    }
+
+    // test gzip compressed token wasm
+    #[test]
+    fn test_compressed_token_wasm() {
+        let mut tool = IssuanceTool::new();
+        let owner =
+            Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe")
+                .unwrap();
+        let result = tool.set_owner(&owner, owner);
+        assert!(result.is_ok());
+
+        // upload a gzip compressed module
+        let token_wasm = test_token_wasm(1);
+        let gzip = compress(&token_wasm);
+        let args = StoreWASMArgs {
+            template: DEFAULT_TOKEN_TEMPLATE.to_string(),
+            version: "0.1.0".to_string(),
+            release_notes: None,
+            wasm_module: gzip.clone(),
+        };
+        let info = tool.set_token_wasm(&owner, args, 1).unwrap();
+        // keyed by the hash of the decompressed module
+        assert_eq!(info.hash, Sha256::digest(&token_wasm).to_vec());
+        assert_eq!(info.size, token_wasm.len() as u64);
+        assert_eq!(info.compressed_size, gzip.len() as u64);
+
+        // the same module uncompressed is a duplicate
+        let args = StoreWASMArgs {
+            template: DEFAULT_TOKEN_TEMPLATE.to_string(),
+            version: "0.2.0".to_string(),
+            release_notes: None,
+            wasm_module: token_wasm.clone(),
+        };
+        let result = tool.set_token_wasm(&owner, args, 2);
+        assert_eq!(result.unwrap_err(), ToolError::TokenWasmAlreadyExists);
+
+        // uncompressed upload is stored compressed
+        let args = StoreWASMArgs {
+            template: "dft_mintable".to_string(),
+            version: "0.1.0".to_string(),
+            release_notes: None,
+            wasm_module: test_token_wasm(2),
+        };
+        let result = tool.set_token_wasm(&owner, args, 3);
+        assert!(result.is_ok());
+        assert!(is_gzip(&tool.get_token_wasm("dft_mintable").unwrap().wasm_module));
+
+        // install decompressed by default, compressed when enabled
+        assert_eq!(tool.get_install_module(DEFAULT_TOKEN_TEMPLATE).unwrap(), token_wasm);
+        let result = tool.set_install_compressed_wasm(&owner, true);
+        assert!(result.is_ok());
+        assert_eq!(tool.get_install_module(DEFAULT_TOKEN_TEMPLATE).unwrap(), gzip);
+
+        let status = tool.get_status();
+        assert_eq!(status.token_wasm_count, 2);
+        assert_eq!(
+            status.token_wasm_size,
+            (token_wasm.len() + test_token_wasm(2).len()) as u64
+        );
+        assert!(status.install_compressed_wasm);
+    }
+
+    // test place order and accept the payment notified by the ledger
+    #[test]
+    fn test_order_payment() {
+        let mut tool = IssuanceTool::new();
+        let owner =
+            Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe")
+                .unwrap();
+        let tool_id = Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap();
+        let ledger = Principal::from_text("ryjl3-tyaaa-aaaaa-aaaba-cai").unwrap();
+        let result = tool.set_owner(&owner, owner);
+        assert!(result.is_ok());
+        let result = tool.set_e8s_per_token(&owner, 200_000_000);
+        assert!(result.is_ok());
+        assert_eq!(tool.e8s_per_token(), 200_000_000);
+        let args = CreateTokenArgs {
+            sub_account: None,
+            logo: None,
+            name: "test".to_string(),
+            symbol: "TST".to_string(),
+            decimals: 18,
+            total_supply: 100,
+            fee: Fee {
+                minimum: Nat::from(1),
+                rate: Nat::from(10000),
+            },
+            template: None,
+            settings: None,
+        };
+        let order = tool.place_order(&owner, &tool_id, args.clone(), 0).unwrap();
+        assert_eq!(order.order_id, 1);
+        assert_eq!(order.price, ICPTs::from_e8s(200_000_000));
+        assert_eq!(order.status, OrderStatus::Pending);
+        assert_eq!(order.payment_account.len(), 64);
+        let order2 = tool.place_order(&owner, &tool_id, args, 0).unwrap();
+        assert_eq!(order2.order_id, 2);
+
+        let mut notification = TransactionNotification {
+            from: owner,
+            from_subaccount: None,
+            to: tool_id,
+            to_subaccount: None,
+            block_height: 10,
+            amount: ICPTs::from_e8s(200_000_000),
+            memo: Memo(order.order_id),
+        };
+        // notification not from the ledger will fail
+        let result = tool.accept_payment(&owner, &tool_id, &notification);
+        assert_eq!(result.unwrap_err(), ToolError::OnlyLedgerAllowCallIt);
+        // unknown memo will fail
+        notification.memo = Memo(100);
+        let result = tool.accept_payment(&ledger, &tool_id, &notification);
+        assert_eq!(result.unwrap_err(), ToolError::OrderNotFound);
+        // insufficient payment will fail
+        notification.memo = Memo(order.order_id);
+        notification.amount = ICPTs::from_e8s(100_000_000);
+        let result = tool.accept_payment(&ledger, &tool_id, &notification);
+        assert_eq!(
+            result.unwrap_err(),
+            ToolError::InsufficientPayment {
+                required: 200_000_000,
+                received: 100_000_000,
+            }
+        );
+        // match by memo
+        notification.amount = ICPTs::from_e8s(200_000_000);
+        let paid = tool.accept_payment(&ledger, &tool_id, &notification).unwrap();
+        assert_eq!(paid.order_id, order.order_id);
+        assert_eq!(paid.status, OrderStatus::Paid { block_height: 10 });
+        assert_eq!(
+            paid.payment,
+            Some(OrderPayment {
+                payer: owner,
+                payer_subaccount: None,
+                subaccount: None,
+                amount: ICPTs::from_e8s(200_000_000),
+                block_height: 10,
+                excess_refund: None,
+            })
+        );
+        assert!(tool.check_order_issuance(&paid, 0).is_ok());
+        // the issuer and admins operate the order
+        assert!(tool.only_order_operator(&owner, order.order_id).is_ok());
+        assert_eq!(
+            tool.only_order_operator(&ledger, order.order_id).unwrap_err(),
+            ToolError::OnlyOwnerAllowCallIt
+        );
+        // the same order can not be paid twice
+        let result = tool.accept_payment(&ledger, &tool_id, &notification);
+        assert_eq!(result.unwrap_err(), ToolError::OrderNotPending);
+        // match by sub-account
+        notification.memo = Memo(0);
+        notification.to_subaccount = Some(order2.subaccount);
+        let paid = tool.accept_payment(&ledger, &tool_id, &notification).unwrap();
+        assert_eq!(paid.order_id, order2.order_id);
+
+        let result = tool.set_order_status(order2.order_id, OrderStatus::Issued { token_id: tool_id });
+        assert!(result.is_ok());
+        assert_eq!(
+            tool.get_order(order2.order_id).unwrap().status,
+            OrderStatus::Issued { token_id: tool_id }
+        );
     }
 
     // test to payload / load from payload
@@ -349,8 +4042,8 @@ mod tests {
                 .unwrap();
         let token_id = Principal::from_text("g7cye-cyaaa-aaaak-aaa5a-cai").unwrap();
         let token_info = TokenInfo {
-            issuer: owner.clone(),
-            token_id: token_id.clone(),
+            issuer: owner,
+            token_id,
             name: "test".to_string(),
             symbol: "TST".to_string(),
             decimals: 18,
@@ -359,9 +4052,14 @@ mod tests {
                 minimum: Nat::from(1),
                 rate: Nat::from(10000),
             },
+            template: DEFAULT_TOKEN_TEMPLATE.to_string(),
+            controllers: vec![],
+            immutable: false,
+            blackhole_canister_id: None,
+            module_hash: None,
             timestamp: 0,
         };
-        let result = tool.set_owner(&owner, owner.clone());
+        let result = tool.set_owner(&owner, owner);
         assert!(result.is_ok());
         let result = tool.add_token(&owner, token_info.clone());
         assert!(result.is_ok());
@@ -371,4 +4069,367 @@ mod tests {
         // check payload is equal
         assert_eq!(payload, payload2);
     }
+
+    // the payload with the vectors collected from hash maps sorted, to compare the state of two tools
+    fn sorted_payload(tool: &IssuanceTool) -> ToolPayload {
+        let mut payload = tool.to_payload();
+        payload.token_wasms.sort_by(|a, b| a.hash.cmp(&b.hash));
+        payload.orders.sort_by_key(|(order_id, _)| *order_id);
+        payload.token_upgrades.sort_by_key(|(token_id, _)| *token_id);
+        payload.symbol_reservations.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        payload.logos.sort_by_key(|(token_id, _)| *token_id);
+        payload
+    }
+
+    // test the state is rebuilt from the record log after an upgrade
+    #[test]
+    fn test_stable_store() {
+        let memory = VecMemory::default();
+        let mut tool = IssuanceTool::new();
+        tool.attach_store(RecordLog::init(Box::new(memory.clone())));
+        let owner =
+            Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe")
+                .unwrap();
+        let issuer =
+            Principal::from_text("czjfo-ddpvm-6sibl-6zbox-ee5zq-bx3hc-e336t-s6pka-dupmy-wcxqi-fae")
+                .unwrap();
+        let tool_id = Principal::from_text("rkp4c-7iaaa-aaaaa-aaaca-cai").unwrap();
+        let token_id = Principal::from_text("g7cye-cyaaa-aaaak-aaa5a-cai").unwrap();
+        let result = tool.set_owner(&owner, owner);
+        assert!(result.is_ok());
+        assert!(tool.set_public_issuance(&owner, true).is_ok());
+        assert!(tool.grant_role(&owner, Role::Moderator, issuer).is_ok());
+        assert!(tool.block_symbols(&owner, vec!["ICP".to_string()]).is_ok());
+        assert!(tool.reserve_symbol(&issuer, "TST", 0).is_ok());
+        let args = StoreWASMArgs {
+            template: DEFAULT_TOKEN_TEMPLATE.to_string(),
+            version: "0.1.0".to_string(),
+            release_notes: None,
+            wasm_module: test_token_wasm(1),
+        };
+        assert!(tool.set_token_wasm(&owner, args, 1).is_ok());
+
+        // an issuance through every step, with its order
+        let args = CreateTokenArgs {
+            sub_account: None,
+            logo: Some(b"GIF89a0000".to_vec()),
+            name: "test".to_string(),
+            symbol: "TST".to_string(),
+            decimals: 18,
+            total_supply: 100,
+            fee: Fee {
+                minimum: Nat::from(1),
+                rate: Nat::from(10000),
+            },
+            template: None,
+            settings: None,
+        };
+        let order = tool.place_order(&issuer, &tool_id, args.clone(), 2).unwrap();
+        let issuance = tool.begin_issuance(&issuer, None, args, vec![issuer], Some(order.order_id), 3).unwrap();
+        assert!(tool.set_issuance_canister(issuance.issuance_id, token_id, 4).is_ok());
+        assert!(tool.set_issuance_installed(issuance.issuance_id, vec![1], 5).is_ok());
+        assert!(tool.register_issuance(issuance.issuance_id, 6).is_ok());
+        assert!(tool
+            .set_issuance_step(issuance.issuance_id, IssuanceStep::Completed, 7)
+            .is_ok());
+        let upgrade = TokenUpgrade {
+            operator: issuer,
+            old_module_hash: Some(vec![1]),
+            new_module_hash: vec![2],
+            timestamp: 8,
+        };
+        assert!(tool.add_token_upgrade(&token_id, upgrade).is_ok());
+        tool.record_event(owner, ToolEvent::OwnerSet { owner }, 9);
+
+        // an open proposal
+        let result = tool.set_multisig(
+            &owner,
+            Some(MultisigConfig {
+                signers: vec![owner, issuer],
+                threshold: 2,
+                proposal_ttl: None,
+            }),
+        );
+        assert!(result.is_ok());
+        assert!(tool.propose(&owner, ProposalAction::SetCyclesPerToken(1), 10).is_ok());
+
+        // upgrade, only the settings are written by pre_upgrade
+        tool.save_settings();
+        let mut tool2 = IssuanceTool::new();
+        let result = tool2.load_from_store(RecordLog::open(Box::new(memory.clone())).unwrap());
+        assert!(result.is_ok());
+        assert_eq!(sorted_payload(&tool2), sorted_payload(&tool));
+        assert_eq!(tool2.certified_tokens_root(), tool.certified_tokens_root());
+        assert_eq!(tool2.search_tokens("tes", 0, 10).unwrap().len(), 1);
+        assert_eq!(tool2.get_status().stable_log_size, memory_log_size(&memory));
+
+        // the reopened log keeps recording, and a second upgrade sees both
+        assert!(tool2.approve_proposal(&issuer, 1, 11).is_ok());
+        tool2.save_settings();
+        let mut tool3 = IssuanceTool::new();
+        let result = tool3.load_from_store(RecordLog::open(Box::new(memory.clone())).unwrap());
+        assert!(result.is_ok());
+        assert_eq!(tool3.cycles_per_token(), 1);
+        assert_eq!(sorted_payload(&tool3), sorted_payload(&tool2));
+
+        // updates only append, the owner compacts the log to drop the superseded records
+        let live_size = memory_log_size(&memory);
+        for _ in 0..100 {
+            let error = ToolError::TopUpCyclesFailed {
+                reason: "x".repeat(1000),
+            };
+            assert!(tool3.set_order_error(order.order_id, &error).is_ok());
+        }
+        assert!(memory_log_size(&memory) > live_size + 100 * 1000);
+        assert_eq!(tool3.compact_store(&issuer).unwrap_err(), ToolError::OnlyOwnerAllowCallIt);
+        let size = tool3.compact_store(&owner).unwrap();
+        assert_eq!(size, memory_log_size(&memory));
+        assert!(size < live_size + 2048);
+        assert_eq!(tool3.get_status().stable_log_compacted_size, size);
+        tool3.save_settings();
+        let mut tool4 = IssuanceTool::new();
+        let result = tool4.load_from_store(RecordLog::open(Box::new(memory.clone())).unwrap());
+        assert!(result.is_ok());
+        assert_eq!(sorted_payload(&tool4), sorted_payload(&tool3));
+    }
+
+    // test a token wasm uploaded since the last compaction is kept by the next one
+    #[test]
+    fn test_compaction_keeps_token_wasm() {
+        let memory = VecMemory::default();
+        let mut tool = IssuanceTool::new();
+        tool.attach_store(RecordLog::init(Box::new(memory.clone())));
+        let owner =
+            Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe")
+                .unwrap();
+        assert!(tool.set_owner(&owner, owner).is_ok());
+        for _ in 0..10 {
+            tool.save_settings();
+        }
+        assert!(tool.compact_store(&owner).is_ok());
+        let args = StoreWASMArgs {
+            template: DEFAULT_TOKEN_TEMPLATE.to_string(),
+            version: "0.1.0".to_string(),
+            release_notes: None,
+            wasm_module: test_token_wasm(1),
+        };
+        assert!(tool.set_token_wasm(&owner, args, 1).is_ok());
+        assert!(tool.compact_store(&owner).is_ok());
+
+        let mut tool2 = IssuanceTool::new();
+        let result = tool2.load_from_store(RecordLog::open(Box::new(memory)).unwrap());
+        assert!(result.is_ok());
+        assert_eq!(
+            tool2.get_token_wasm(DEFAULT_TOKEN_TEMPLATE).unwrap(),
+            tool.get_token_wasm(DEFAULT_TOKEN_TEMPLATE).unwrap()
+        );
+    }
+
+    fn memory_log_size(memory: &VecMemory) -> u64 {
+        RecordLog::open(Box::new(memory.clone())).unwrap().size()
+    }
+
+    // test the state saved as a ToolPayload is migrated to the record log
+    #[test]
+    fn test_payload_migration() {
+        let mut tool = IssuanceTool::new();
+        let owner =
+            Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe")
+                .unwrap();
+        let token_id = Principal::from_text("g7cye-cyaaa-aaaak-aaa5a-cai").unwrap();
+        let result = tool.set_owner(&owner, owner);
+        assert!(result.is_ok());
+        let token_info = TokenInfo {
+            issuer: owner,
+            token_id,
+            name: "test".to_string(),
+            symbol: "TST".to_string(),
+            decimals: 18,
+            total_supply: 100,
+            fee: Fee {
+                minimum: Nat::from(1),
+                rate: Nat::from(10000),
+            },
+            template: DEFAULT_TOKEN_TEMPLATE.to_string(),
+            controllers: vec![owner],
+            immutable: false,
+            blackhole_canister_id: None,
+            module_hash: None,
+            timestamp: 0,
+        };
+        assert!(tool.add_token(&owner, token_info).is_ok());
+        assert!(tool.set_token_logo(&token_id, b"GIF89a0000".to_vec()).is_ok());
+        tool.record_event(owner, ToolEvent::OwnerSet { owner }, 1);
+
+        // post_upgrade of the first version with the record log
+        let memory = VecMemory::default();
+        let mut tool2 = IssuanceTool::new();
+        tool2.load_from_payload(tool.to_payload());
+        tool2.attach_store(RecordLog::init(Box::new(memory.clone())));
+        tool2.save_all();
+
+        let mut tool3 = IssuanceTool::new();
+        let result = tool3.load_from_store(RecordLog::open(Box::new(memory)).unwrap());
+        assert!(result.is_ok());
+        assert_eq!(sorted_payload(&tool3), sorted_payload(&tool));
+        assert_eq!(tool3.certified_tokens_root(), tool.certified_tokens_root());
+    }
+
+    // TokenInfo and ToolPayload exactly as declared by the first release
+    #[derive(CandidType, Deserialize)]
+    struct BaselineTokenInfo {
+        issuer: Principal,
+        #[serde(rename = "tokenId")]
+        token_id: Principal,
+        name: String,
+        symbol: String,
+        decimals: u8,
+        #[serde(rename = "totalSupply")]
+        total_supply: u128,
+        fee: Fee,
+        timestamp: u64,
+    }
+
+    #[derive(CandidType, Deserialize)]
+    struct BaselineToolPayload {
+        owner: Principal,
+        cycles_per_token: u64,
+        token_wasm: Vec<u8>,
+        tokens: Vec<(Principal, BaselineTokenInfo)>,
+    }
+
+    fn baseline_payload(owner: Principal, token_wasm: Vec<u8>) -> BaselineToolPayload {
+        let token_id = Principal::from_text("g7cye-cyaaa-aaaak-aaa5a-cai").unwrap();
+        BaselineToolPayload {
+            owner,
+            cycles_per_token: 1_000,
+            token_wasm,
+            tokens: vec![(
+                token_id,
+                BaselineTokenInfo {
+                    issuer: owner,
+                    token_id,
+                    name: "test".to_string(),
+                    symbol: "TST".to_string(),
+                    decimals: 18,
+                    total_supply: 100,
+                    fee: Fee {
+                        minimum: Nat::from(1),
+                        rate: Nat::from(10000),
+                    },
+                    timestamp: 7,
+                },
+            )],
+        }
+    }
+
+    // test the ToolPayload saved by the first release is migrated to the record log
+    #[test]
+    fn test_legacy_payload_migration() {
+        let owner =
+            Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe")
+                .unwrap();
+        let token_id = Principal::from_text("g7cye-cyaaa-aaaak-aaa5a-cai").unwrap();
+        let bytes = Encode!(&baseline_payload(owner, test_token_wasm(1))).unwrap();
+
+        // a payload of a later version is not taken for the first release
+        let current_bytes = Encode!(&IssuanceTool::new().to_payload()).unwrap();
+        assert!(Decode!(&current_bytes, LegacyToolPayload).is_err());
+
+        let payload = Decode!(&bytes, LegacyToolPayload).unwrap();
+        let memory = VecMemory::default();
+        let mut tool = IssuanceTool::new();
+        assert!(tool.load_from_legacy_payload(payload, &owner, 9).is_ok());
+        tool.attach_store(RecordLog::init(Box::new(memory.clone())));
+        tool.save_all();
+        assert_eq!(tool.owner, owner);
+        assert_eq!(tool.cycles_per_token(), 1_000);
+        let token_info = tool.get_token_by_id(&token_id).unwrap();
+        assert_eq!(token_info.symbol, "TST");
+        assert_eq!(token_info.template, DEFAULT_TOKEN_TEMPLATE);
+        assert_eq!(token_info.timestamp, 7);
+        assert!(!token_info.immutable);
+        let token_wasm = tool.get_token_wasm(DEFAULT_TOKEN_TEMPLATE).unwrap();
+        assert_eq!(token_wasm.version, LEGACY_TOKEN_WASM_VERSION);
+        assert_eq!(token_wasm.uploader, owner);
+        assert_eq!(token_wasm.hash, Sha256::digest(&test_token_wasm(1)).to_vec());
+
+        let mut tool2 = IssuanceTool::new();
+        let result = tool2.load_from_store(RecordLog::open(Box::new(memory)).unwrap());
+        assert!(result.is_ok());
+        assert_eq!(sorted_payload(&tool2), sorted_payload(&tool));
+        assert_eq!(tool2.certified_tokens_root(), tool.certified_tokens_root());
+
+        // a token wasm that is no longer valid is dropped, the tokens are kept
+        let bytes = Encode!(&baseline_payload(owner, b"not a wasm module".to_vec())).unwrap();
+        let mut tool3 = IssuanceTool::new();
+        let result = tool3.load_from_legacy_payload(Decode!(&bytes, LegacyToolPayload).unwrap(), &owner, 9);
+        assert!(result.is_ok());
+        assert!(tool3.get_token_wasm(DEFAULT_TOKEN_TEMPLATE).is_err());
+        assert_eq!(tool3.get_token_by_id(&token_id).unwrap(), token_info);
+
+        // a tool saved before its owner was set is owned by the controller upgrading it, the wasm is kept
+        let upgrader =
+            Principal::from_text("czjfo-ddpvm-6sibl-6zbox-ee5zq-bx3hc-e336t-s6pka-dupmy-wcxqi-fae").unwrap();
+        let bytes = Encode!(&baseline_payload(Principal::anonymous(), test_token_wasm(1))).unwrap();
+        let mut tool4 = IssuanceTool::new();
+        let payload = Decode!(&bytes, LegacyToolPayload).unwrap();
+        let result = tool4.load_from_legacy_payload(payload.clone(), &Principal::anonymous(), 9);
+        assert_eq!(result.unwrap_err(), ToolError::NotAllowAnonymous);
+        let mut tool4 = IssuanceTool::new();
+        assert!(tool4.load_from_legacy_payload(payload, &upgrader, 9).is_ok());
+        assert_eq!(tool4.owner, upgrader);
+        assert_eq!(tool4.get_token_wasm(DEFAULT_TOKEN_TEMPLATE).unwrap().uploader, upgrader);
+        assert_eq!(tool4.events.last().unwrap().event, ToolEvent::OwnerSet { owner: upgrader });
+        // an owner already set is kept
+        assert!(tool4.set_owner_if_unset(&owner, 10).is_ok());
+        assert_eq!(tool4.owner, upgrader);
+    }
+
+    // ToolPayload of a version before some of the fields were added
+    #[derive(CandidType, Deserialize)]
+    struct EarlierToolPayload {
+        owner: Principal,
+        cycles_per_token: u64,
+        token_wasms: Vec<TokenWasm>,
+        tokens: Vec<(Principal, BaselineTokenInfo)>,
+        e8s_per_token: u64,
+        public_issuance: bool,
+        issuer_quota: u64,
+    }
+
+    // test the missing fields of a payload saved by a later version take the defaults
+    #[test]
+    fn test_earlier_payload_defaults() {
+        let owner =
+            Principal::from_text("qupnt-ohzy3-npshw-oba2m-sttkq-tyawc-vufye-u5fbz-zb6yu-conr3-tqe")
+                .unwrap();
+        let baseline = baseline_payload(owner, Vec::new());
+        let payload = EarlierToolPayload {
+            owner,
+            cycles_per_token: 1_000,
+            token_wasms: Vec::new(),
+            tokens: baseline.tokens,
+            e8s_per_token: 5,
+            public_issuance: true,
+            issuer_quota: 2,
+        };
+        let bytes = Encode!(&payload).unwrap();
+        assert!(Decode!(&bytes, LegacyToolPayload).is_err());
+        let payload = Decode!(&bytes, ToolPayload).unwrap();
+        let defaults = IssuanceTool::new().to_payload();
+        assert_eq!(payload.owner, owner);
+        assert_eq!(payload.e8s_per_token, 5);
+        assert!(payload.public_issuance);
+        assert_eq!(payload.issuer_quota, 2);
+        assert_eq!(payload.next_order_id, defaults.next_order_id);
+        assert_eq!(payload.ledger_canister_id, defaults.ledger_canister_id);
+        assert_eq!(payload.symbol_reservation_ttl, defaults.symbol_reservation_ttl);
+        assert_eq!(payload.tokens[0].1.template, DEFAULT_TOKEN_TEMPLATE);
+        assert!(payload.tokens[0].1.controllers.is_empty());
+
+        let mut tool = IssuanceTool::new();
+        tool.load_from_payload(payload);
+        assert_eq!(tool.get_token_count().unwrap(), 1);
+    }
 }
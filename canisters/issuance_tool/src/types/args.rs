@@ -1,4 +1,4 @@
-use super::{CanisterSettings, Fee, Subaccount};
+use super::{CanisterSettings, Fee, Subaccount, TokenSettings, DEFAULT_TOKEN_TEMPLATE};
 use candid::{CandidType, Deserialize, Principal};
 
 #[derive(CandidType, Clone, Deserialize)]
@@ -31,4 +31,92 @@ pub struct IssueTokenArgs {
     pub decimals: u8,
     pub total_supply: u128,
     pub fee: Fee,
+    pub template: Option<String>,
+    pub settings: Option<TokenSettings>,
+}
+
+#[derive(CandidType, Debug, PartialOrd, Eq, PartialEq, Clone, Deserialize)]
+pub struct CreateTokenArgs {
+    pub sub_account: Option<Subaccount>,
+    pub logo: Option<Vec<u8>>,
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+    pub total_supply: u128,
+    pub fee: Fee,
+    pub template: Option<String>,
+    pub settings: Option<TokenSettings>,
+}
+
+impl CreateTokenArgs {
+    // name of the token wasm template to install
+    pub fn template(&self) -> String {
+        template_or_default(&self.template)
+    }
+
+    // attach the canister created by the tool, so the token can follow the issueToken flow
+    pub fn into_issue_args(self, canister_id: Principal) -> IssueTokenArgs {
+        IssueTokenArgs {
+            canister_id,
+            sub_account: self.sub_account,
+            logo: self.logo,
+            name: self.name,
+            symbol: self.symbol,
+            decimals: self.decimals,
+            total_supply: self.total_supply,
+            fee: self.fee,
+            template: self.template,
+            settings: self.settings,
+        }
+    }
+}
+
+impl IssueTokenArgs {
+    // name of the token wasm template to install
+    pub fn template(&self) -> String {
+        template_or_default(&self.template)
+    }
+
+    // detach the canister id, so the token can follow the issuance flow of a created canister
+    pub fn into_create_args(self) -> CreateTokenArgs {
+        CreateTokenArgs {
+            sub_account: self.sub_account,
+            logo: self.logo,
+            name: self.name,
+            symbol: self.symbol,
+            decimals: self.decimals,
+            total_supply: self.total_supply,
+            fee: self.fee,
+            template: self.template,
+            settings: self.settings,
+        }
+    }
+}
+
+fn template_or_default(template: &Option<String>) -> String {
+    template
+        .clone()
+        .unwrap_or_else(|| DEFAULT_TOKEN_TEMPLATE.to_string())
+}
+
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenSortBy {
+    IssueTime,
+    Name,
+    Symbol,
+}
+
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortDirection {
+    Ascending,
+    Descending,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct ListTokensArgs {
+    // id of the last token of the previous page
+    pub cursor: Option<Principal>,
+    pub size: u64,
+    pub sort_by: Option<TokenSortBy>,
+    pub direction: Option<SortDirection>,
 }
@@ -14,6 +14,18 @@ pub struct CanisterSettings {
     pub freezing_threshold: Option<Nat>,
 }
 
+// settings of an issued token canister, applied when the token is handed over,
+// controllers replace the default controllers, an empty set leaves the token without controllers,
+// an immutable token is handed over to the blackhole canister of the tool, which must be set
+#[derive(CandidType, Debug, PartialOrd, Eq, PartialEq, Clone, Deserialize)]
+pub struct TokenSettings {
+    pub controllers: Option<Vec<Principal>>,
+    pub immutable: Option<bool>,
+    pub compute_allocation: Option<Nat>,
+    pub memory_allocation: Option<Nat>,
+    pub freezing_threshold: Option<Nat>,
+}
+
 #[derive(CandidType, Deserialize, Debug, Eq, PartialEq)]
 pub struct DefiniteCanisterSettingsArgs {
     pub controllers: Vec<Principal>,
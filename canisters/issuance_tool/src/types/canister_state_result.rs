@@ -13,4 +13,11 @@ pub struct CanisterStatusResultV2 {
     // this is for compat with Spec 0.12/0.13
     pub balance: Vec<(Vec<u8>, candid::Nat)>,
     pub freezing_threshold: candid::Nat,
-}
\ No newline at end of file
+}
+// the part of the canister status needed to verify a token, also returned by blackhole canisters
+#[derive(CandidType, Debug, Deserialize, Eq, PartialEq)]
+pub struct TokenCanisterStatus {
+    pub status: CanisterStatus,
+    pub module_hash: Option<Vec<u8>>,
+    pub settings: DefiniteCanisterSettingsArgs,
+}
@@ -1,4 +1,6 @@
 use candid::{CandidType, Deserialize};
+
+use super::Role;
 use thiserror::Error;
 
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, CandidType, Deserialize, Error)]
@@ -17,6 +19,120 @@ pub enum ToolError {
     TokenNotFound,
     #[error("Caller is not the controller of the token")]
     CallerIsNotControllerOfToken,
+    #[error("Create token canister failed, reason: {reason:?}")]
+    CreateCanisterFailed { reason: String },
+    #[error("Insufficient cycles, required: {required:?}, available: {available:?}")]
+    InsufficientCycles { required: u64, available: u64 },
+    #[error("Order not found")]
+    OrderNotFound,
+    #[error("Order is not waiting for payment")]
+    OrderNotPending,
+    #[error("Insufficient payment, required: {required:?} e8s, received: {received:?} e8s")]
+    InsufficientPayment { required: u64, received: u64 },
+    #[error("Caller is not the ledger canister")]
+    OnlyLedgerAllowCallIt,
+    #[error("Top up cycles failed, reason: {reason:?}")]
+    TopUpCyclesFailed { reason: String },
+    #[error("Issuer quota exceeded, quota: {quota:?}")]
+    IssuerQuotaExceeded { quota: u64 },
+    #[error("Token is already running the current token wasm")]
+    TokenAlreadyUpToDate,
+    #[error("Upgrade token code failed, reason: {reason:?}")]
+    UpgradeTokenCodeFailed { reason: String },
+    #[error("Token template not found, template: {template:?}")]
+    TokenTemplateNotFound { template: String },
+    #[error("Token wasm already exists")]
+    TokenWasmAlreadyExists,
+    #[error("Token wasm version already exists, template: {template:?}, version: {version:?}")]
+    TokenWasmVersionAlreadyExists { template: String, version: String },
+    #[error("Token wasm too large, size: {size:?}, limit: {limit:?}")]
+    TokenWasmTooLarge { size: u64, limit: u64 },
+    #[error("Token wasm is neither a wasm module nor gzip compressed")]
+    InvalidTokenWasmHeader,
+    #[error("Malformed token wasm, reason: {reason:?}")]
+    MalformedTokenWasm { reason: String },
+    #[error("Token wasm does not export {export:?}")]
+    TokenWasmMissingExport { export: String },
+    #[error("Token wasm upload not found")]
+    WasmUploadNotFound,
+    #[error("Token wasm hash mismatch, expected: {expected:?}, actual: {actual:?}")]
+    TokenWasmHashMismatch { expected: Vec<u8>, actual: Vec<u8> },
+    #[error("Symbol already exists, symbol: {symbol:?}")]
+    SymbolAlreadyExists { symbol: String },
+    #[error("Symbol is blocked, symbol: {symbol:?}")]
+    SymbolBlocked { symbol: String },
+    #[error("Symbol is reserved by another principal, symbol: {symbol:?}")]
+    SymbolReserved { symbol: String },
+    #[error("Too many symbol reservations, max: {max:?}")]
+    TooManySymbolReservations { max: u64 },
+    #[error("Symbol reservation not found")]
+    SymbolReservationNotFound,
+    #[error("Invalid token name, reason: {reason:?}")]
+    InvalidTokenName { reason: String },
+    #[error("Invalid token symbol, reason: {reason:?}")]
+    InvalidTokenSymbol { reason: String },
+    #[error("Invalid token decimals, decimals: {decimals:?}, max: {max:?}")]
+    InvalidTokenDecimals { decimals: u8, max: u8 },
+    #[error("Total supply must be greater than zero")]
+    InvalidTotalSupply,
+    #[error("Invalid fee rate, rate: {rate:?}, max: {max:?}")]
+    InvalidFeeRate { rate: String, max: u64 },
+    #[error("Logo is too large, size: {size:?}, limit: {limit:?}")]
+    LogoTooLarge { size: u64, limit: u64 },
+    #[error("Unsupported logo type")]
+    UnsupportedLogoType,
+    #[error("Issuance not found")]
+    IssuanceNotFound,
+    #[error("Issuance already completed")]
+    IssuanceAlreadyCompleted,
+    #[error("Another issuance of the canister or the caller is in progress")]
+    IssuanceInProgress,
+    #[error("Invalid token settings, reason: {reason:?}")]
+    InvalidTokenSettings { reason: String },
+    #[error("Token is immutable")]
+    TokenIsImmutable,
+    #[error("Caller does not have the role, role: {role:?}")]
+    RoleRequired { role: Role },
+    #[error("Owner is already set")]
+    OwnerAlreadySet,
+    #[error("Ownership transfer not found")]
+    OwnershipTransferNotFound,
+    #[error("Ownership transfer expired")]
+    OwnershipTransferExpired,
+    #[error("Multisig approval required, submit a proposal")]
+    MultisigApprovalRequired,
+    #[error("Invalid multisig config, reason: {reason:?}")]
+    InvalidMultisigConfig { reason: String },
+    #[error("Caller is not a multisig signer")]
+    OnlySignerAllowCallIt,
+    #[error("Proposal not found")]
+    ProposalNotFound,
+    #[error("Proposal is not open")]
+    ProposalNotOpen,
+    #[error("Proposal expired")]
+    ProposalExpired,
+    #[error("Proposal already approved by the caller")]
+    ProposalAlreadyApproved,
+    #[error("Order has no payment to convert or token to issue")]
+    OrderNotRetryable,
+    #[error("Order has no payment held by the tool")]
+    OrderNotRefundable,
+    #[error("Refund failed, reason: {reason:?}")]
+    RefundFailed { reason: String },
+    #[error("Hand over token failed, reason: {reason:?}")]
+    HandOverTokenFailed { reason: String },
+    #[error("Token is not verifiable, it is neither blackholed nor controlled by the tool")]
+    TokenNotVerifiable,
+    #[error("Caller is not the uploader of the wasm upload")]
+    NotUploader,
+    #[error("Too many wasm uploads staged, limit: {limit:?}")]
+    TooManyWasmUploads { limit: u64 },
+    #[error("Staged wasm uploads too large, size: {size:?}, limit: {limit:?}")]
+    StagedWasmTooLarge { size: u64, limit: u64 },
+    #[error("Only the proposer can cancel the proposal")]
+    OnlyProposerAllowCallIt,
+    #[error("Public issuance is disabled, only the owner and issuers can issue tokens")]
+    PublicIssuanceDisabled,
     #[error("error from remote, detail: {detail:?}")]
     Unknown { detail: String },
 }
@@ -24,13 +140,70 @@ pub enum ToolError {
 impl ToolError {
     pub(crate) fn code(&self) -> u32 {
         match self {
-            ToolError::NotAllowAnonymous { .. } => 1,
+            ToolError::NotAllowAnonymous => 1,
             ToolError::OnlyOwnerAllowCallIt => 2,
             ToolError::InvalidTokenWasmModule => 3,
             ToolError::CanisterAlreadyInstalled => 4,
-            ToolError::TokenNotFound { .. } => 5,
+            ToolError::TokenNotFound => 5,
             ToolError::InstallTokenCodeFailed { .. } => 6,
             ToolError::CallerIsNotControllerOfToken => 7,
+            ToolError::CreateCanisterFailed { .. } => 8,
+            ToolError::InsufficientCycles { .. } => 9,
+            ToolError::OrderNotFound => 10,
+            ToolError::OrderNotPending => 11,
+            ToolError::InsufficientPayment { .. } => 12,
+            ToolError::OnlyLedgerAllowCallIt => 13,
+            ToolError::TopUpCyclesFailed { .. } => 14,
+            ToolError::IssuerQuotaExceeded { .. } => 15,
+            ToolError::TokenAlreadyUpToDate => 16,
+            ToolError::UpgradeTokenCodeFailed { .. } => 17,
+            ToolError::TokenTemplateNotFound { .. } => 18,
+            ToolError::TokenWasmAlreadyExists => 19,
+            ToolError::TokenWasmVersionAlreadyExists { .. } => 20,
+            ToolError::TokenWasmTooLarge { .. } => 21,
+            ToolError::InvalidTokenWasmHeader => 22,
+            ToolError::MalformedTokenWasm { .. } => 23,
+            ToolError::TokenWasmMissingExport { .. } => 24,
+            ToolError::WasmUploadNotFound => 25,
+            ToolError::TokenWasmHashMismatch { .. } => 26,
+            ToolError::SymbolAlreadyExists { .. } => 27,
+            ToolError::SymbolBlocked { .. } => 28,
+            ToolError::SymbolReserved { .. } => 29,
+            ToolError::TooManySymbolReservations { .. } => 30,
+            ToolError::SymbolReservationNotFound => 31,
+            ToolError::InvalidTokenName { .. } => 32,
+            ToolError::InvalidTokenSymbol { .. } => 33,
+            ToolError::InvalidTokenDecimals { .. } => 34,
+            ToolError::InvalidTotalSupply => 35,
+            ToolError::InvalidFeeRate { .. } => 36,
+            ToolError::LogoTooLarge { .. } => 37,
+            ToolError::UnsupportedLogoType => 38,
+            ToolError::IssuanceNotFound => 39,
+            ToolError::IssuanceAlreadyCompleted => 40,
+            ToolError::IssuanceInProgress => 41,
+            ToolError::InvalidTokenSettings { .. } => 42,
+            ToolError::TokenIsImmutable => 43,
+            ToolError::RoleRequired { .. } => 44,
+            ToolError::OwnerAlreadySet => 45,
+            ToolError::OwnershipTransferNotFound => 46,
+            ToolError::OwnershipTransferExpired => 47,
+            ToolError::MultisigApprovalRequired => 48,
+            ToolError::InvalidMultisigConfig { .. } => 49,
+            ToolError::OnlySignerAllowCallIt => 50,
+            ToolError::ProposalNotFound => 51,
+            ToolError::ProposalNotOpen => 52,
+            ToolError::ProposalExpired => 53,
+            ToolError::ProposalAlreadyApproved => 54,
+            ToolError::OrderNotRetryable => 55,
+            ToolError::OrderNotRefundable => 56,
+            ToolError::RefundFailed { .. } => 57,
+            ToolError::HandOverTokenFailed { .. } => 58,
+            ToolError::TokenNotVerifiable => 59,
+            ToolError::NotUploader => 60,
+            ToolError::TooManyWasmUploads { .. } => 61,
+            ToolError::StagedWasmTooLarge { .. } => 62,
+            ToolError::OnlyProposerAllowCallIt => 63,
+            ToolError::PublicIssuanceDisabled => 64,
             ToolError::Unknown { .. } => 10000
         }
     }
@@ -0,0 +1,246 @@
+use candid::{CandidType, Deserialize, Principal};
+
+use super::{MultisigConfig, Role};
+
+// categories of the audit log events, used to filter the log
+#[derive(CandidType, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+pub enum EventType {
+    Ownership,
+    Role,
+    TokenWasm,
+    Price,
+    Setting,
+    Symbol,
+    Multisig,
+    Issuance,
+    Upgrade,
+    Order,
+}
+
+#[derive(CandidType, Debug, PartialOrd, Eq, PartialEq, Clone, Deserialize)]
+pub enum ToolEvent {
+    OwnerSet {
+        owner: Principal,
+    },
+    OwnershipProposed {
+        #[serde(rename = "newOwner")]
+        new_owner: Principal,
+        #[serde(rename = "expiresAt")]
+        expires_at: u64,
+    },
+    OwnershipAccepted {
+        #[serde(rename = "previousOwner")]
+        previous_owner: Principal,
+        owner: Principal,
+    },
+    OwnershipTransferCancelled,
+    RoleGranted {
+        role: Role,
+        principal: Principal,
+    },
+    RoleRevoked {
+        role: Role,
+        principal: Principal,
+    },
+    TokenWasmUploaded {
+        template: String,
+        version: String,
+        hash: Vec<u8>,
+    },
+    CyclesPerTokenSet {
+        #[serde(rename = "cyclesPerToken")]
+        cycles_per_token: u64,
+    },
+    E8sPerTokenSet {
+        #[serde(rename = "e8sPerToken")]
+        e8s_per_token: u64,
+    },
+    // a tool setting other than the prices, the value is formatted as text
+    SettingChanged {
+        name: String,
+        value: String,
+    },
+    SymbolsBlocked {
+        symbols: Vec<String>,
+    },
+    SymbolsUnblocked {
+        symbols: Vec<String>,
+    },
+    MultisigSet {
+        config: Option<MultisigConfig>,
+    },
+    ProposalCreated {
+        #[serde(rename = "proposalId")]
+        proposal_id: u64,
+        #[serde(rename = "actionHash")]
+        action_hash: Vec<u8>,
+    },
+    ProposalApproved {
+        #[serde(rename = "proposalId")]
+        proposal_id: u64,
+    },
+    ProposalExecuted {
+        #[serde(rename = "proposalId")]
+        proposal_id: u64,
+    },
+    ProposalFailed {
+        #[serde(rename = "proposalId")]
+        proposal_id: u64,
+        error: String,
+    },
+    ProposalCancelled {
+        #[serde(rename = "proposalId")]
+        proposal_id: u64,
+    },
+    IssuanceStarted {
+        #[serde(rename = "issuanceId")]
+        issuance_id: u64,
+        issuer: Principal,
+        #[serde(rename = "canisterId")]
+        canister_id: Option<Principal>,
+    },
+    TokenIssued {
+        #[serde(rename = "issuanceId")]
+        issuance_id: u64,
+        #[serde(rename = "tokenId")]
+        token_id: Principal,
+    },
+    ControllersHandedOver {
+        #[serde(rename = "issuanceId")]
+        issuance_id: u64,
+        #[serde(rename = "tokenId")]
+        token_id: Principal,
+        controllers: Vec<Principal>,
+    },
+    IssuanceFailed {
+        #[serde(rename = "issuanceId")]
+        issuance_id: u64,
+        error: String,
+    },
+    TokenUpgraded {
+        #[serde(rename = "tokenId")]
+        token_id: Principal,
+        #[serde(rename = "oldModuleHash")]
+        old_module_hash: Option<Vec<u8>>,
+        #[serde(rename = "newModuleHash")]
+        new_module_hash: Vec<u8>,
+    },
+    OrderPlaced {
+        #[serde(rename = "orderId")]
+        order_id: u64,
+    },
+    PaymentReceived {
+        #[serde(rename = "orderId")]
+        order_id: u64,
+        issuer: Principal,
+        #[serde(rename = "blockHeight")]
+        block_height: u64,
+    },
+    OrderFailed {
+        #[serde(rename = "orderId")]
+        order_id: u64,
+        reason: String,
+    },
+    OrderRefunded {
+        #[serde(rename = "orderId")]
+        order_id: u64,
+        payer: Principal,
+        #[serde(rename = "blockHeight")]
+        block_height: u64,
+    },
+}
+
+impl ToolEvent {
+    pub fn event_type(&self) -> EventType {
+        match self {
+            ToolEvent::OwnerSet { .. }
+            | ToolEvent::OwnershipProposed { .. }
+            | ToolEvent::OwnershipAccepted { .. }
+            | ToolEvent::OwnershipTransferCancelled => EventType::Ownership,
+            ToolEvent::RoleGranted { .. } | ToolEvent::RoleRevoked { .. } => EventType::Role,
+            ToolEvent::TokenWasmUploaded { .. } => EventType::TokenWasm,
+            ToolEvent::CyclesPerTokenSet { .. } | ToolEvent::E8sPerTokenSet { .. } => EventType::Price,
+            ToolEvent::SettingChanged { .. } => EventType::Setting,
+            ToolEvent::SymbolsBlocked { .. } | ToolEvent::SymbolsUnblocked { .. } => EventType::Symbol,
+            ToolEvent::MultisigSet { .. }
+            | ToolEvent::ProposalCreated { .. }
+            | ToolEvent::ProposalApproved { .. }
+            | ToolEvent::ProposalExecuted { .. }
+            | ToolEvent::ProposalFailed { .. }
+            | ToolEvent::ProposalCancelled { .. } => EventType::Multisig,
+            ToolEvent::IssuanceStarted { .. }
+            | ToolEvent::TokenIssued { .. }
+            | ToolEvent::ControllersHandedOver { .. }
+            | ToolEvent::IssuanceFailed { .. } => EventType::Issuance,
+            ToolEvent::TokenUpgraded { .. } => EventType::Upgrade,
+            ToolEvent::OrderPlaced { .. }
+            | ToolEvent::PaymentReceived { .. }
+            | ToolEvent::OrderFailed { .. }
+            | ToolEvent::OrderRefunded { .. } => EventType::Order,
+        }
+    }
+
+    // principals the event is about, besides its caller
+    pub fn principals(&self) -> Vec<Principal> {
+        match self {
+            ToolEvent::OwnerSet { owner } => vec![*owner],
+            ToolEvent::OwnershipProposed { new_owner, .. } => vec![*new_owner],
+            ToolEvent::OwnershipAccepted { previous_owner, owner } => vec![*previous_owner, *owner],
+            ToolEvent::RoleGranted { principal, .. } | ToolEvent::RoleRevoked { principal, .. } => vec![*principal],
+            ToolEvent::MultisigSet { config } => config.as_ref().map(|c| c.signers.clone()).unwrap_or_default(),
+            ToolEvent::IssuanceStarted { issuer, canister_id, .. } => {
+                let mut principals = vec![*issuer];
+                principals.extend(canister_id);
+                principals
+            }
+            ToolEvent::TokenIssued { token_id, .. } | ToolEvent::TokenUpgraded { token_id, .. } => vec![*token_id],
+            ToolEvent::ControllersHandedOver {
+                token_id, controllers, ..
+            } => {
+                let mut principals = vec![*token_id];
+                principals.extend(controllers);
+                principals
+            }
+            ToolEvent::PaymentReceived { issuer, .. } => vec![*issuer],
+            ToolEvent::OrderRefunded { payer, .. } => vec![*payer],
+            _ => Vec::new(),
+        }
+    }
+}
+
+// an entry of the append-only audit log
+#[derive(CandidType, Debug, PartialOrd, Eq, PartialEq, Clone, Deserialize)]
+pub struct Event {
+    #[serde(rename = "eventId")]
+    pub event_id: u64,
+    pub caller: Principal,
+    pub timestamp: u64,
+    pub event: ToolEvent,
+}
+
+#[derive(CandidType, Debug, Clone, Default, Deserialize)]
+pub struct EventFilter {
+    #[serde(rename = "eventType")]
+    pub event_type: Option<EventType>,
+    // events called by the principal or about it
+    pub principal: Option<Principal>,
+}
+
+impl EventFilter {
+    pub fn matches(&self, event: &Event) -> bool {
+        self.event_type.map_or(true, |t| t == event.event.event_type())
+            && self
+                .principal
+                .map_or(true, |p| event.caller == p || event.event.principals().contains(&p))
+    }
+}
+
+#[derive(CandidType, Debug, Clone, Deserialize)]
+pub struct EventPage {
+    pub events: Vec<Event>,
+    // number of events in the log
+    pub total: u64,
+    // id of the next matching event, to continue from
+    #[serde(rename = "nextStart")]
+    pub next_start: Option<u64>,
+}
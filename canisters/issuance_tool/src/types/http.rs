@@ -0,0 +1,40 @@
+use candid::{CandidType, Deserialize};
+use serde_bytes::ByteBuf;
+
+pub type HeaderField = (String, String);
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<HeaderField>,
+    pub body: ByteBuf,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct HttpResponse {
+    pub status_code: u16,
+    pub headers: Vec<HeaderField>,
+    pub body: ByteBuf,
+}
+
+impl HttpResponse {
+    pub fn new(status_code: u16, content_type: &str, body: Vec<u8>) -> Self {
+        HttpResponse {
+            status_code,
+            headers: vec![("Content-Type".to_string(), content_type.to_string())],
+            body: ByteBuf::from(body),
+        }
+    }
+
+    pub fn not_found() -> Self {
+        HttpResponse::new(404, "text/plain", b"Not found".to_vec())
+    }
+}
+
+#[derive(CandidType, Deserialize, PartialOrd, Eq, PartialEq, Clone, Debug)]
+pub struct TokenLogo {
+    #[serde(rename = "contentType")]
+    pub content_type: String,
+    pub data: ByteBuf,
+}
@@ -0,0 +1,40 @@
+use candid::{CandidType, Deserialize, Principal};
+
+use super::CreateTokenArgs;
+
+// steps of an issuance, each step is saved before the next one starts,
+// so a failed or interrupted issuance can be resumed from the last completed step
+#[derive(CandidType, Debug, PartialOrd, Eq, PartialEq, Clone, Copy, Deserialize)]
+pub enum IssuanceStep {
+    // the token canister is not installed yet (and not created yet if canister_id is none)
+    Pending,
+    // the token wasm is installed
+    Installed,
+    // the token is added to the registry
+    Registered,
+    // the controllers of the token are handed over, the issuance is done
+    Completed,
+}
+
+#[derive(CandidType, Debug, PartialOrd, Eq, PartialEq, Clone, Deserialize)]
+pub struct Issuance {
+    pub issuance_id: u64,
+    pub issuer: Principal,
+    // none until the tool creates the token canister
+    pub canister_id: Option<Principal>,
+    pub args: CreateTokenArgs,
+    // controllers of the token once the issuance completes
+    pub controllers: Vec<Principal>,
+    pub immutable: bool,
+    // the blackhole canister an immutable token is handed over to
+    pub blackhole_canister_id: Option<Principal>,
+    // sha256 of the installed module
+    pub module_hash: Option<Vec<u8>>,
+    // order paid for the issuance, if any
+    pub order_id: Option<u64>,
+    pub step: IssuanceStep,
+    // error of the last attempt
+    pub error: Option<String>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
@@ -0,0 +1,62 @@
+use candid::{CandidType, Deserialize, Principal};
+
+use super::{CreateTokenArgs, ICPTs, Subaccount};
+
+#[derive(CandidType, Debug, PartialOrd, Eq, PartialEq, Clone, Deserialize)]
+pub enum OrderStatus {
+    Pending,
+    // the payment is held in the order's account, it can be converted or refunded
+    Paid { block_height: u64 },
+    // the payment is sent to the cycles minting canister, the top up is not notified yet
+    TopUpSent { block_height: u64, cmc: Principal },
+    // the payment is converted into cycles of the tool, the token is being issued
+    Converted,
+    Issued { token_id: Principal },
+    // orders failed before their payments could be retried
+    Failed { reason: String },
+    // the cycles minting canister sent the top up back to the order's account, less its fee,
+    // the amount held can only be refunded
+    Refundable { amount: ICPTs },
+    Refunded { block_height: u64 },
+}
+
+// the payment notified by the ledger, kept to convert or refund it
+#[derive(CandidType, Debug, PartialOrd, Eq, PartialEq, Clone, Deserialize)]
+pub struct OrderPayment {
+    pub payer: Principal,
+    pub payer_subaccount: Option<Subaccount>,
+    // sub-account of the tool holding the payment
+    pub subaccount: Option<Subaccount>,
+    pub amount: ICPTs,
+    pub block_height: u64,
+    // block height of the refund of the amount paid over the price
+    #[serde(default)]
+    pub excess_refund: Option<u64>,
+}
+
+// an issuance waiting for its ICP payment, matched by memo (order id) or by the order's sub-account
+#[derive(CandidType, Debug, PartialOrd, Eq, PartialEq, Clone, Deserialize)]
+pub struct IssuanceOrder {
+    pub order_id: u64,
+    pub issuer: Principal,
+    pub args: CreateTokenArgs,
+    pub price: ICPTs,
+    pub subaccount: Subaccount,
+    pub payment_account: String,
+    pub status: OrderStatus,
+    pub payment: Option<OrderPayment>,
+    // error of the last attempt to convert the payment or issue the token
+    pub error: Option<String>,
+    pub timestamp: u64,
+}
+
+impl IssuanceOrder {
+    // the amount held in the order's account while it is paid, only the price once the excess is refunded
+    pub fn held_amount(&self) -> ICPTs {
+        match &self.payment {
+            Some(payment) if payment.excess_refund.is_some() => self.price,
+            Some(payment) => payment.amount,
+            None => ICPTs::from_e8s(0),
+        }
+    }
+}
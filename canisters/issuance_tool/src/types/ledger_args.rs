@@ -0,0 +1,71 @@
+use candid::{CandidType, Deserialize, Principal};
+use sha2::{Digest, Sha224};
+
+use super::{ICPTs, Memo, Subaccount};
+
+pub type BlockHeight = u64;
+
+// fee of a ledger transfer, 0.0001 ICP
+pub const TRANSACTION_FEE: ICPTs = ICPTs::from_e8s(10_000);
+// memo the cycles minting canister expects for a canister top up ("TPUP")
+pub const MEMO_TOP_UP_CANISTER: Memo = Memo(0x50555054);
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct TimeStamp {
+    pub timestamp_nanos: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct SendArgs {
+    pub memo: Memo,
+    pub amount: ICPTs,
+    pub fee: ICPTs,
+    pub from_subaccount: Option<Subaccount>,
+    pub to: String,
+    pub created_at_time: Option<TimeStamp>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct NotifyCanisterArgs {
+    pub block_height: BlockHeight,
+    pub max_fee: ICPTs,
+    pub from_subaccount: Option<Subaccount>,
+    pub to_canister: Principal,
+    pub to_subaccount: Option<Subaccount>,
+}
+
+// reply of the cycles minting canister to a notified top up, forwarded by the ledger
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum CyclesResponse {
+    CanisterCreated(Principal),
+    // the reason and the block height of the refund
+    Refunded(String, Option<BlockHeight>),
+    ToppedUp,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AccountIdentifier {
+    hash: [u8; 28],
+}
+
+impl AccountIdentifier {
+    pub fn new(owner: &Principal, subaccount: Option<Subaccount>) -> Self {
+        let mut hasher = Sha224::new();
+        hasher.update(b"\x0Aaccount-id");
+        hasher.update(owner.as_slice());
+        hasher.update(subaccount.unwrap_or(Subaccount([0u8; 32])).0);
+        let mut hash = [0u8; 28];
+        hash.copy_from_slice(&hasher.finalize());
+        AccountIdentifier { hash }
+    }
+
+    // hex text of crc32(hash) ++ hash, the format the ledger uses for account identifiers
+    pub fn to_hex(self) -> String {
+        let checksum = crc32fast::hash(&self.hash).to_be_bytes();
+        checksum
+            .iter()
+            .chain(self.hash.iter())
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
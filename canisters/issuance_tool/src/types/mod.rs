@@ -4,7 +4,16 @@ mod canister_state_result;
 mod canister_status;
 mod dft_types;
 mod error;
+mod event;
+mod http;
+mod issuance;
+mod issuance_order;
+mod ledger_args;
+mod ownership;
+mod proposal;
+mod role;
 mod sub_account;
+mod symbol;
 mod transaction_notification;
 mod wasm_bytes;
 
@@ -14,6 +23,15 @@ pub use canister_state_result::*;
 pub use canister_status::*;
 pub use dft_types::*;
 pub use error::*;
+pub use event::*;
+pub use http::*;
+pub use issuance::*;
+pub use issuance_order::*;
+pub use ledger_args::*;
+pub use ownership::*;
+pub use proposal::*;
+pub use role::*;
 pub use sub_account::*;
+pub use symbol::*;
 pub use transaction_notification::*;
 pub use wasm_bytes::*;
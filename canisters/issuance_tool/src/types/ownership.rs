@@ -0,0 +1,15 @@
+use candid::{CandidType, Deserialize, Principal};
+
+// time a proposed owner has to accept the ownership, 7 days in nanoseconds
+pub const OWNERSHIP_TRANSFER_TTL: u64 = 7 * 24 * 60 * 60 * 1_000_000_000;
+
+// an ownership transfer waiting for the new owner to accept it
+#[derive(CandidType, Debug, PartialOrd, Eq, PartialEq, Clone, Deserialize)]
+pub struct OwnershipTransfer {
+    #[serde(rename = "newOwner")]
+    pub new_owner: Principal,
+    #[serde(rename = "proposedAt")]
+    pub proposed_at: u64,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: u64,
+}
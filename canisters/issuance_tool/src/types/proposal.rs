@@ -0,0 +1,67 @@
+use candid::{CandidType, Deserialize, Principal};
+
+// max signers of the multisig
+pub const MAX_MULTISIG_SIGNERS: usize = 20;
+// default time signers have to approve a proposal, 3 days in nanoseconds
+pub const DEFAULT_PROPOSAL_TTL: u64 = 3 * 24 * 60 * 60 * 1_000_000_000;
+
+// M-of-N signers of the privileged operations, when set the operations can only be executed by proposals
+#[derive(CandidType, Debug, PartialOrd, Eq, PartialEq, Clone, Deserialize)]
+pub struct MultisigConfig {
+    pub signers: Vec<Principal>,
+    pub threshold: u64,
+    // time a proposal stays open, in nanoseconds
+    #[serde(rename = "proposalTtl")]
+    pub proposal_ttl: Option<u64>,
+}
+
+// privileged operation executed once a proposal is approved
+#[derive(CandidType, Debug, PartialOrd, Eq, PartialEq, Clone, Deserialize)]
+pub enum ProposalAction {
+    // commit the staged upload with the sha256, recorded as uploaded by the proposer
+    SetTokenWasm {
+        #[serde(rename = "wasmHash")]
+        wasm_hash: Vec<u8>,
+    },
+    SetCyclesPerToken(u64),
+    SetE8sPerToken(u64),
+    SetPaymentCanisters { ledger: Principal, cmc: Principal },
+    SetInstallCompressedWasm(bool),
+    SetBlackholeCanister(Option<Principal>),
+    SetMultisig(Option<MultisigConfig>),
+}
+
+#[derive(CandidType, Debug, PartialOrd, Eq, PartialEq, Clone, Deserialize)]
+pub enum ProposalStatus {
+    Open,
+    Executed,
+    Failed { error: String },
+    Expired,
+    Cancelled,
+}
+
+#[derive(CandidType, Debug, PartialOrd, Eq, PartialEq, Clone, Deserialize)]
+pub struct ProposalApproval {
+    pub signer: Principal,
+    #[serde(rename = "approvedAt")]
+    pub approved_at: u64,
+}
+
+#[derive(CandidType, Debug, PartialOrd, Eq, PartialEq, Clone, Deserialize)]
+pub struct Proposal {
+    #[serde(rename = "proposalId")]
+    pub proposal_id: u64,
+    pub proposer: Principal,
+    pub action: ProposalAction,
+    // sha256 of the candid encoded action
+    #[serde(rename = "actionHash")]
+    pub action_hash: Vec<u8>,
+    pub approvals: Vec<ProposalApproval>,
+    pub status: ProposalStatus,
+    #[serde(rename = "createdAt")]
+    pub created_at: u64,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: u64,
+    #[serde(rename = "closedAt")]
+    pub closed_at: Option<u64>,
+}
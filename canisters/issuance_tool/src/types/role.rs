@@ -0,0 +1,22 @@
+use candid::{CandidType, Deserialize, Principal};
+
+// roles granted by the owner (or an admin), the owner implicitly holds every role,
+// an admin holds every role except granting admin
+#[derive(CandidType, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+pub enum Role {
+    Admin,
+    // publish token wasm templates
+    WasmPublisher,
+    // set the price of an issuance
+    FeeManager,
+    // block and unblock symbols
+    Moderator,
+    // issue tokens when public issuance is disabled
+    Issuer,
+}
+
+#[derive(CandidType, Debug, Clone, PartialEq, Eq, PartialOrd, Deserialize)]
+pub struct RoleHolders {
+    pub role: Role,
+    pub holders: Vec<Principal>,
+}
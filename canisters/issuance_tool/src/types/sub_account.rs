@@ -1,6 +1,26 @@
-use candid::{CandidType, Deserialize};
+use candid::{CandidType, Deserialize, Principal};
 use serde::Serialize;
 
-#[derive(Serialize, Deserialize, CandidType, Clone, Hash, Debug, PartialEq, Eq, Copy)]
+#[derive(Serialize, Deserialize, CandidType, Clone, Hash, Debug, PartialEq, Eq, PartialOrd, Ord, Copy)]
 #[serde(transparent)]
-pub struct Subaccount(pub [u8; 32]);
\ No newline at end of file
+pub struct Subaccount(pub [u8; 32]);
+
+// sub-account of an issuance order, the order id is stored big-endian in the last 8 bytes
+impl From<u64> for Subaccount {
+    fn from(order_id: u64) -> Self {
+        let mut subaccount = [0u8; 32];
+        subaccount[24..].copy_from_slice(&order_id.to_be_bytes());
+        Subaccount(subaccount)
+    }
+}
+
+// sub-account used by the cycles minting canister to find the canister to top up
+impl From<&Principal> for Subaccount {
+    fn from(principal: &Principal) -> Self {
+        let bytes = principal.as_slice();
+        let mut subaccount = [0u8; 32];
+        subaccount[0] = bytes.len() as u8;
+        subaccount[1..1 + bytes.len()].copy_from_slice(bytes);
+        Subaccount(subaccount)
+    }
+}
@@ -0,0 +1,19 @@
+use candid::{CandidType, Deserialize, Principal};
+
+// default time a symbol reservation is held, 24 hours in nanoseconds
+pub const DEFAULT_SYMBOL_RESERVATION_TTL: u64 = 24 * 60 * 60 * 1_000_000_000;
+// max active symbol reservations of a principal
+pub const MAX_SYMBOL_RESERVATIONS: usize = 5;
+
+// symbols are compared case-insensitively
+pub fn fold_symbol(symbol: &str) -> String {
+    symbol.trim().to_lowercase()
+}
+
+#[derive(CandidType, Debug, PartialOrd, Eq, PartialEq, Clone, Deserialize)]
+pub struct SymbolReservation {
+    pub symbol: String,
+    pub holder: Principal,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: u64,
+}
@@ -19,20 +19,20 @@ use super::Subaccount;
 pub struct ICPTs {
     /// Number of 10^-8 ICPs.
     /// Named because the equivalent part of a Bitcoin is called a Satoshi
-    e8s: u64,
+    pub e8s: u64,
+}
+
+impl ICPTs {
+    pub const fn from_e8s(e8s: u64) -> Self {
+        ICPTs { e8s }
+    }
 }
 
 #[derive(
-    Serialize, Deserialize, CandidType, Clone, Copy, Hash, Debug, PartialEq, Eq, PartialOrd, Ord,
+    Serialize, Deserialize, CandidType, Clone, Copy, Hash, Debug, PartialEq, Eq, PartialOrd, Ord, Default,
 )]
 pub struct Memo(pub u64);
 
-impl Default for Memo {
-    fn default() -> Memo {
-        Memo(0)
-    }
-}
-
 /// Struct sent by the ledger canister when it notifies a recipient of a payment
 #[derive(Serialize, Deserialize, CandidType, Clone, Hash, Debug, PartialEq, Eq)]
 pub struct TransactionNotification {
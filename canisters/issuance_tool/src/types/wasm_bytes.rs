@@ -1,11 +1,90 @@
-use candid::{CandidType, Deserialize};
+use candid::{CandidType, Deserialize, Principal};
 
-#[derive(CandidType, Default, Clone, Deserialize)]
-pub struct WASMBytes {
-    pub token_wasm: Option<Vec<u8>>,
+// template installed when the issue args do not pick one
+pub const DEFAULT_TOKEN_TEMPLATE: &str = "dft_basic";
+// uploads staged at the same time, and their total size
+pub const MAX_WASM_UPLOADS: usize = 4;
+pub const MAX_STAGED_WASM_SIZE: usize = 16 * 1024 * 1024;
+
+// a token wasm module in the registry, keyed by the sha256 of the decompressed module,
+// the module is stored gzip compressed
+#[derive(CandidType, Debug, PartialOrd, Eq, PartialEq, Clone, Deserialize)]
+pub struct TokenWasm {
+    pub template: String,
+    pub version: String,
+    pub hash: Vec<u8>,
+    pub uploader: Principal,
+    pub release_notes: Option<String>,
+    pub timestamp: u64,
+    // size of the decompressed module
+    pub size: u64,
+    #[serde(with = "serde_bytes")]
+    pub wasm_module: Vec<u8>,
 }
-#[derive(CandidType, Deserialize)]
+
+// TokenWasm without the module bytes, returned by the listing queries
+#[derive(CandidType, Debug, PartialOrd, Eq, PartialEq, Clone, Deserialize)]
+pub struct TokenWasmInfo {
+    pub template: String,
+    pub version: String,
+    pub hash: Vec<u8>,
+    pub uploader: Principal,
+    #[serde(rename = "releaseNotes")]
+    pub release_notes: Option<String>,
+    pub timestamp: u64,
+    pub size: u64,
+    #[serde(rename = "compressedSize")]
+    pub compressed_size: u64,
+}
+
+impl From<&TokenWasm> for TokenWasmInfo {
+    fn from(wasm: &TokenWasm) -> Self {
+        TokenWasmInfo {
+            template: wasm.template.clone(),
+            version: wasm.version.clone(),
+            hash: wasm.hash.clone(),
+            uploader: wasm.uploader,
+            release_notes: wasm.release_notes.clone(),
+            timestamp: wasm.timestamp,
+            size: wasm.size,
+            compressed_size: wasm.wasm_module.len() as u64,
+        }
+    }
+}
+
+#[derive(CandidType, Debug, PartialOrd, Eq, PartialEq, Clone, Deserialize)]
 pub struct StoreWASMArgs {
+    pub template: String,
+    pub version: String,
+    pub release_notes: Option<String>,
     #[serde(with = "serde_bytes")]
     pub wasm_module: Vec<u8>,
 }
+
+#[derive(CandidType, Deserialize)]
+pub struct BeginWasmUploadArgs {
+    pub template: String,
+    pub version: String,
+    pub release_notes: Option<String>,
+}
+
+// a token wasm being uploaded in chunks, it is not part of the registry until committed
+#[derive(Debug, Clone)]
+pub struct WasmUpload {
+    pub uploader: Principal,
+    pub template: String,
+    pub version: String,
+    pub release_notes: Option<String>,
+    pub wasm_module: Vec<u8>,
+    pub timestamp: u64,
+}
+
+#[derive(CandidType, Debug, Clone, Deserialize)]
+pub struct WasmUploadInfo {
+    pub upload_id: u64,
+    pub uploader: Principal,
+    pub template: String,
+    pub version: String,
+    pub size: u64,
+    pub timestamp: u64,
+}
@@ -1,8 +1,12 @@
 #!/usr/bin/ic-repl
 import tool = "rrkah-fqaaa-aaaaa-aaaaq-cai" as ".dfx/local/canisters/issuanceTool/issuanceTool.did";
+// the owner of the tool, tasks.py installs the tool with the same identity (IDENTITY)
 identity default "~/.config/dfx/identity/dft_tool/identity.pem";
 call tool.uploadTokenWasm(
   record {
+    template = "dft_basic";
+    version = "0.1.0";
+    release_notes = null;
     wasm_module = file "wasm/dft_basic_opt.wasm";
   },
 );
\ No newline at end of file